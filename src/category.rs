@@ -0,0 +1,393 @@
+//! Holds logic for translating with Custom Translator models.
+//!
+//! Custom models are picked with a category ID. When the model for a category
+//! is not available, the service quietly uses its general model unless told
+//! otherwise. The Flows here ask it not to, fall back to the general model
+//! themselves, and report which model was actually used.
+//!
+//! The v2 cogs and the translators built on them can only pass a category along,
+//! so they can't tell when the general model was used.
+use futures::Future;
+use futures::future;
+use std::fmt;
+use std::str::FromStr;
+#[cfg(feature = "serialization")]
+use serde::{de, Serialize, Serializer, Deserialize, Deserializer};
+use engine::Dispatcher;
+use flow::{self, Flow, CogFlow, Zip};
+use cogs::translator_v3::{self, TranslateRequest, TranslationResult, FutureTranslateResponse, ApiError};
+
+/// Category of the general model
+pub const GENERAL_CATEGORY: &'static str = "general";
+
+/// Categories of the models provided by the service
+const STANDARD_CATEGORIES: &'static [&'static str] = &["general", "generalnn", "tech"];
+
+/// Codes of the service errors meaning that there is no model for the requested category
+const CATEGORY_UNAVAILABLE_CODES: &'static [u32] = &[400075];
+
+/// A validated category ID.
+///
+/// Either a standard category such as "general", or the ID of a Custom Translator
+/// model, made of the ID of its workspace and the code of its domain, such as
+/// "a2eb72f9-43a4-4246-9fa1-08ee10c4a9ed-TECH".
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CategoryId(String);
+
+/// Category ID error mapping
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Empty,
+    InvalidWorkspaceId(String),
+    InvalidDomain(String),
+}
+
+impl CategoryId {
+    /// Parses and validates a category ID
+    pub fn parse(s: &str) -> Result<CategoryId, Error> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(Error::Empty);
+        }
+        let lower = s.to_lowercase();
+        if STANDARD_CATEGORIES.contains(&lower.as_str()) {
+            return Ok(CategoryId(lower));
+        }
+        // A workspace ID is a GUID, so it holds exactly four hyphens
+        let mut parts = s.splitn(6, '-');
+        let workspace: Vec<&str> = parts.by_ref().take(5).collect();
+        let domain = parts.next().unwrap_or("");
+        let lengths = [8, 4, 4, 4, 12];
+        let valid_workspace = workspace.len() == 5 &&
+                              workspace
+                                  .iter()
+                                  .zip(lengths.iter())
+                                  .all(|(part, len)| part.len() == *len && part.chars().all(|c| c.is_ascii_hexdigit()));
+        if !valid_workspace {
+            let end = s.len() - domain.len();
+            return Err(Error::InvalidWorkspaceId(s[..end].trim_right_matches('-').to_string()));
+        }
+        if domain.is_empty() || !domain.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(Error::InvalidDomain(domain.to_string()));
+        }
+        Ok(CategoryId(s.to_string()))
+    }
+
+    /// Returns the category of the general model
+    pub fn general() -> CategoryId {
+        CategoryId(GENERAL_CATEGORY.to_string())
+    }
+
+    /// Whether or not this is a category of a model provided by the service
+    pub fn is_standard(&self) -> bool {
+        STANDARD_CATEGORIES.contains(&self.0.as_str())
+    }
+
+    /// Returns the workspace ID of a custom category
+    pub fn workspace_id(&self) -> Option<&str> {
+        if self.is_standard() {
+            None
+        } else {
+            Some(&self.0[..36])
+        }
+    }
+
+    /// Returns the domain code of a custom category
+    pub fn domain(&self) -> Option<&str> {
+        if self.is_standard() {
+            None
+        } else {
+            Some(&self.0[37..])
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for CategoryId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CategoryId::parse(s)
+    }
+}
+
+impl fmt::Display for CategoryId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl Serialize for CategoryId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl<'de> Deserialize<'de> for CategoryId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        CategoryId::parse(&s).map_err(|e| de::Error::custom(format!("invalid category '{}': {:?}", s, e)))
+    }
+}
+
+/// The model that produced a translation
+#[derive(Debug, Clone, PartialEq)]
+pub enum Model {
+    General,
+    Custom(CategoryId),
+}
+
+/// Translations along with the model that produced them
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelTranslation {
+    pub results: Vec<TranslationResult>,
+    pub model: Model,
+    /// Why the custom model could not be used, if the general model was used instead
+    pub fallback_reason: Option<ApiError>,
+}
+
+impl ModelTranslation {
+    /// Whether or not the general model was used instead of the custom one
+    pub fn fell_back(&self) -> bool {
+        self.fallback_reason.is_some()
+    }
+}
+
+/// Translations of the same texts by the general model and a custom one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub general: Vec<TranslationResult>,
+    pub custom: Vec<TranslationResult>,
+}
+
+impl Comparison {
+    /// Returns the (text index, translation index) of every translation the two models disagree on
+    pub fn differences(&self) -> Vec<(usize, usize)> {
+        let mut differences = vec![];
+        for (i, (general, custom)) in self.general.iter().zip(&self.custom).enumerate() {
+            for (j, (g, c)) in general.translations.iter().zip(&custom.translations).enumerate() {
+                if g.text != c.text {
+                    differences.push((i, j));
+                }
+            }
+        }
+        differences
+    }
+}
+
+/// Returns a Flow that translates with the model of the given category, falling back
+/// to the general model if it is not available.
+///
+/// The category and allow_fallback of the request are overridden.
+pub fn translate<'a>(req: TranslateRequest<'a>, category: &'a CategoryId) -> FallbackTranslation {
+    let custom = flow::cog(TranslateRequest {
+                               category: Some(category),
+                               allow_fallback: Some(false),
+                               ..general_request(&req)
+                           });
+    FallbackTranslation {
+        category: category.clone(),
+        custom: custom,
+        general: flow::cog(general_request(&req)),
+    }
+}
+
+/// Returns a Flow that translates with both the general model and the model of the given
+/// category, so that their translations can be compared.
+///
+/// Unlike `translate`, this fails if the custom model is not available.
+pub fn compare<'a>(req: TranslateRequest<'a>, category: &'a CategoryId) -> CategoryComparison {
+    let custom = TranslateRequest {
+        category: Some(category),
+        allow_fallback: Some(false),
+        ..general_request(&req)
+    };
+    CategoryComparison { flow: flow::cog(general_request(&req)).zip(flow::cog(custom)) }
+}
+
+/// Copies a request, without its category so that it uses the general model
+fn general_request<'a>(req: &TranslateRequest<'a>) -> TranslateRequest<'a> {
+    TranslateRequest {
+        texts: req.texts,
+        to: req.to,
+        from: req.from,
+        text_type: req.text_type,
+        category: None,
+        profanity_action: req.profanity_action,
+        profanity_marker: req.profanity_marker,
+        include_alignment: req.include_alignment,
+        include_sentence_length: req.include_sentence_length,
+        suggested_from: req.suggested_from,
+        from_script: req.from_script,
+        to_script: req.to_script,
+        allow_fallback: None,
+        glossary: req.glossary,
+    }
+}
+
+/// Flow for `translate`
+pub struct FallbackTranslation {
+    category: CategoryId,
+    custom: CogFlow<FutureTranslateResponse>,
+    general: CogFlow<FutureTranslateResponse>,
+}
+
+impl Flow for FallbackTranslation {
+    type Item = ModelTranslation;
+
+    fn start(self, dispatcher: Dispatcher) -> Box<Future<Item = Self::Item, Error = flow::Error>> {
+        let FallbackTranslation { category, custom, general } = self;
+        let general_dispatcher = dispatcher.clone();
+        Box::new(custom.start(dispatcher).then(move |result| -> Box<Future<Item = ModelTranslation, Error = flow::Error>> {
+            match result {
+                Ok(results) => {
+                    Box::new(future::ok(ModelTranslation {
+                                            results: results,
+                                            model: Model::Custom(category),
+                                            fallback_reason: None,
+                                        }))
+                }
+                Err(flow::Error::TranslatorV3Error(translator_v3::Error::ServiceError(_, ref e)))
+                    if CATEGORY_UNAVAILABLE_CODES.contains(&e.code) => {
+                    let reason = e.clone();
+                    Box::new(general.start(general_dispatcher).map(move |results| {
+                        ModelTranslation {
+                            results: results,
+                            model: Model::General,
+                            fallback_reason: Some(reason),
+                        }
+                    }))
+                }
+                Err(e) => Box::new(future::err(e)),
+            }
+        }))
+    }
+}
+
+/// Flow for `compare`
+pub struct CategoryComparison {
+    flow: Zip<CogFlow<FutureTranslateResponse>, CogFlow<FutureTranslateResponse>>,
+}
+
+impl Flow for CategoryComparison {
+    type Item = Comparison;
+
+    fn start(self, dispatcher: Dispatcher) -> Box<Future<Item = Self::Item, Error = flow::Error>> {
+        Box::new(self.flow.start(dispatcher).map(|(general, custom)| {
+                                                     Comparison {
+                                                         general: general,
+                                                         custom: custom,
+                                                     }
+                                                 }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::client::Response;
+    use hyper::StatusCode;
+    use engine::{scripted_dispatcher, SentRequest};
+    use language::LanguageTag;
+
+    const CUSTOM: &'static str = "a2eb72f9-43a4-4246-9fa1-08ee10c4a9ed-TECH";
+
+    #[test]
+    fn parse_test() {
+        let custom = CategoryId::parse(CUSTOM).unwrap();
+        assert_eq!(custom.workspace_id(), Some("a2eb72f9-43a4-4246-9fa1-08ee10c4a9ed"));
+        assert_eq!(custom.domain(), Some("TECH"));
+        assert!(!custom.is_standard());
+        assert_eq!(CategoryId::parse(" GeneralNN").unwrap().as_str(), "generalnn");
+        assert_eq!(CategoryId::parse(""), Err(Error::Empty));
+        assert_eq!(CategoryId::parse("a2eb72f9-43a4-4246-9fa1-TECH"),
+                   Err(Error::InvalidWorkspaceId("a2eb72f9-43a4-4246-9fa1-TECH".to_string())));
+        assert_eq!(CategoryId::parse("a2eb72f9-43a4-4246-9fa1-08ee10c4a9ez-TECH"),
+                   Err(Error::InvalidWorkspaceId("a2eb72f9-43a4-4246-9fa1-08ee10c4a9ez".to_string())));
+        assert_eq!(CategoryId::parse("a2eb72f9-43a4-4246-9fa1-08ee10c4a9ed-"),
+                   Err(Error::InvalidDomain("".to_string())));
+    }
+
+    fn results(text: &str) -> String {
+        format!(r#"[{{"translations": [{{"text": "{}", "to": "de"}}]}}]"#, text)
+    }
+
+    /// The categories the requests asked for, checking that the custom model was not allowed to fall back
+    fn categories(sent: &[SentRequest]) -> Vec<Option<String>> {
+        sent.iter()
+            .map(|r| {
+                let category = r.uri
+                    .split(|c| c == '?' || c == '&')
+                    .find(|p| p.starts_with("category="))
+                    .map(|p| p["category=".len()..].to_string());
+                if category.is_some() {
+                    assert!(r.uri.contains("allowFallback=false"));
+                }
+                category
+            })
+            .collect()
+    }
+
+    fn translate_with(custom: Response) -> (ModelTranslation, Vec<Option<String>>) {
+        let general = Response::new().with_body(results("Allgemein"));
+        let (dispatcher, sent) = scripted_dispatcher(vec![custom, general]);
+        let texts = ["General"];
+        let to = [LanguageTag::parse("de").unwrap()];
+        let req = TranslateRequest {
+            texts: &texts,
+            to: &to,
+            ..Default::default()
+        };
+        let category = CategoryId::parse(CUSTOM).unwrap();
+        let result = translate(req, &category).start(dispatcher).wait().unwrap();
+        let categories = categories(&sent.borrow());
+        (result, categories)
+    }
+
+    #[test]
+    fn translate_test() {
+        let (result, categories) = translate_with(Response::new().with_body(results("Spezifisch")));
+        assert_eq!(result.model, Model::Custom(CategoryId::parse(CUSTOM).unwrap()));
+        assert!(!result.fell_back());
+        assert_eq!(result.results[0].translations[0].text, "Spezifisch");
+        assert_eq!(categories, vec![Some(CUSTOM.to_string())]);
+    }
+
+    #[test]
+    fn fallback_test() {
+        let unavailable = Response::new()
+            .with_status(StatusCode::BadRequest)
+            .with_body(r#"{"error":{"code":400075,"message":"The language pair and category combination is not valid."}}"#);
+        let (result, categories) = translate_with(unavailable);
+        assert_eq!(result.model, Model::General);
+        assert_eq!(result.fallback_reason.map(|e| e.code), Some(400075));
+        assert_eq!(result.results[0].translations[0].text, "Allgemein");
+        assert_eq!(categories, vec![Some(CUSTOM.to_string()), None]);
+    }
+
+    #[test]
+    fn compare_test() {
+        let responses = vec![Response::new().with_body(results("Allgemein")),
+                             Response::new().with_body(results("Spezifisch"))];
+        let (dispatcher, sent) = scripted_dispatcher(responses);
+        let texts = ["General"];
+        let to = [LanguageTag::parse("de").unwrap()];
+        let req = TranslateRequest {
+            texts: &texts,
+            to: &to,
+            category: Some(&CategoryId::parse("tech").unwrap()),
+            ..Default::default()
+        };
+        let category = CategoryId::parse(CUSTOM).unwrap();
+        let comparison = compare(req, &category).start(dispatcher).wait().unwrap();
+        assert_eq!(categories(&sent.borrow()), vec![None, Some(CUSTOM.to_string())]);
+        assert_eq!(comparison.general[0].translations[0].text, "Allgemein");
+        assert_eq!(comparison.custom[0].translations[0].text, "Spezifisch");
+        assert_eq!(comparison.differences(), vec![(0, 0)]);
+    }
+}
@@ -0,0 +1,771 @@
+//! This module holds Cogs for the Document Translation API, which translates whole
+//! documents held in storage containers.
+//!
+//! Batches are submitted with SubmitBatchRequest and translated as long-running
+//! operations, see Engine::run_operation. Requests go to the endpoint of a
+//! Translator resource, such as "https://my-resource.cognitiveservices.azure.com".
+use hyper::client::Request;
+use hyper::client::Response;
+use hyper::{Method, StatusCode, Uri};
+use engine;
+use futures::{Future, Poll};
+use serde::Serializer;
+use serde_json;
+use url::Url;
+use core::str::FromStr;
+use std::fmt;
+use super::*;
+use language::LanguageTag;
+use category::CategoryId;
+use operation::{Operation, OperationLocation, Status};
+use paging::{PagedCog, Page, NextPage};
+
+const API_PATH: &'static str = "translator/text/batch/v1.1/";
+
+/// The endpoint of a Translator resource
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoint {
+    base: String,
+    api: Url,
+}
+
+impl Endpoint {
+    /// Returns an Endpoint for the given base URI, such as "https://my-resource.cognitiveservices.azure.com".
+    ///
+    /// The base must be an absolute http or https URI without a query or fragment.
+    pub fn new<S: Into<String>>(base: S) -> Result<Endpoint, Error> {
+        let base = base.into().trim_right_matches('/').to_string();
+        let api = Url::parse(&format!("{}/{}", base, API_PATH))
+            .map_err(|e| Error::InvalidEndpoint(format!("{}: {}", base, e)))?;
+        if api.scheme() != "http" && api.scheme() != "https" {
+            return Err(Error::InvalidEndpoint(format!("{}: not an http or https URI", base)));
+        }
+        if api.query().is_some() || api.fragment().is_some() {
+            return Err(Error::InvalidEndpoint(format!("{}: has a query or fragment", base)));
+        }
+        if Uri::from_str(api.as_str()).is_err() {
+            return Err(Error::InvalidEndpoint(format!("{}: not a valid URI", base)));
+        }
+        Ok(Endpoint {
+               base: base,
+               api: api,
+           })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.base
+    }
+
+    /// Returns the URI of the given path segments of the API, percent-encoding each one
+    fn uri(&self, segments: &[&str], params: &[(&str, String)]) -> Uri {
+        let mut url = self.api.clone();
+        {
+            // Validated by Endpoint::new to be an http(s) URI, which can always have segments
+            let mut path = url.path_segments_mut().unwrap();
+            path.pop_if_empty().extend(segments);
+        }
+        if !params.is_empty() {
+            let mut mut_pairs = url.query_pairs_mut();
+            for &(k, ref v) in params {
+                mut_pairs.append_pair(k, v);
+            }
+            mut_pairs.finish();
+        }
+        // Appending encoded segments and query pairs to a valid URI keeps it valid
+        Uri::from_str(url.as_str()).unwrap()
+    }
+}
+
+/// A request for translating the documents of one or more storage containers.
+///
+/// As a Cog, this returns the ID of the submitted batch. As an Operation, use it with
+/// Engine::run_operation to wait for the batch to finish.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubmitBatchRequest {
+    pub endpoint: Endpoint,
+    pub inputs: Vec<BatchInput>,
+}
+
+/// Documents to translate, and where to put their translations
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchInput {
+    pub source: DocumentSource,
+    pub targets: Vec<DocumentTarget>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage_type: Option<StorageType>,
+}
+
+/// Where documents to translate are read from
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentSource {
+    /// URL of a container or, with StorageType::File, of a single document, including a SAS token
+    pub source_url: String,
+    /// Language of the documents, detected by the service if None
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_optional_language")]
+    pub language: Option<LanguageTag>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<DocumentFilter>,
+}
+
+/// Narrows down which documents of a container are translated
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DocumentFilter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suffix: Option<String>,
+}
+
+/// Where translated documents are written to
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentTarget {
+    /// URL of a container, including a SAS token
+    pub target_url: String,
+    #[serde(serialize_with = "serialize_language")]
+    pub language: LanguageTag,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_optional_category")]
+    pub category: Option<CategoryId>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub glossaries: Vec<GlossaryFile>,
+}
+
+/// A glossary document to apply when translating
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GlossaryFile {
+    /// URL of the glossary document, including a SAS token
+    pub glossary_url: String,
+    /// One of the formats returned by SupportedFormatsRequest for FormatKind::Glossary, such as "TSV"
+    pub format: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+/// Whether a source or target URL points at a container or at a single document
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum StorageType {
+    Folder,
+    File,
+}
+
+impl DocumentSource {
+    /// Returns a source for all the documents of a container, whose language is detected
+    pub fn new<S: Into<String>>(source_url: S) -> DocumentSource {
+        DocumentSource {
+            source_url: source_url.into(),
+            language: None,
+            filter: None,
+        }
+    }
+}
+
+impl DocumentTarget {
+    /// Returns a target using the general model and no glossaries
+    pub fn new<S: Into<String>>(target_url: S, language: LanguageTag) -> DocumentTarget {
+        DocumentTarget {
+            target_url: target_url.into(),
+            language: language,
+            category: None,
+            glossaries: vec![],
+        }
+    }
+}
+
+/// Status of a batch or of a single document
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum JobStatus {
+    NotStarted,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+    Cancelling,
+    ValidationFailed,
+}
+
+impl JobStatus {
+    /// Whether or not the job is done, one way or another
+    pub fn is_finished(&self) -> bool {
+        match *self {
+            JobStatus::NotStarted |
+            JobStatus::Running |
+            JobStatus::Cancelling => false,
+            _ => true,
+        }
+    }
+}
+
+/// Status of a batch
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchStatus {
+    pub id: String,
+    pub created_date_time_utc: String,
+    pub last_action_date_time_utc: String,
+    pub status: JobStatus,
+    /// Set if the batch failed as a whole
+    pub error: Option<ApiError>,
+    pub summary: BatchSummary,
+}
+
+/// Number of documents of a batch in each state
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchSummary {
+    pub total: u32,
+    pub failed: u32,
+    pub success: u32,
+    pub in_progress: u32,
+    pub not_yet_started: u32,
+    pub cancelled: u32,
+    pub total_character_charged: u64,
+}
+
+/// Status of a single document of a batch
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentStatus {
+    pub id: String,
+    /// Location of the translated document, once there is one
+    pub path: Option<String>,
+    pub source_path: String,
+    pub created_date_time_utc: String,
+    pub last_action_date_time_utc: String,
+    pub status: JobStatus,
+    /// Language the document is translated into
+    pub to: String,
+    /// From 0 to 1
+    pub progress: f64,
+    #[serde(default)]
+    pub character_charged: u64,
+    pub error: Option<ApiError>,
+}
+
+/// A file format supported by the service
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileFormat {
+    pub format: String,
+    pub file_extensions: Vec<String>,
+    pub content_types: Vec<String>,
+    pub default_version: Option<String>,
+    #[serde(default)]
+    pub versions: Vec<String>,
+}
+
+/// Which kind of file formats to list
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FormatKind {
+    Document,
+    Glossary,
+}
+
+#[derive(Serialize)]
+struct BatchBody<'a> {
+    inputs: &'a [BatchInput],
+}
+
+#[derive(Deserialize)]
+struct DocumentsPage {
+    value: Vec<DocumentStatus>,
+    #[serde(rename = "@nextLink")]
+    next_link: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Formats {
+    value: Vec<FileFormat>,
+}
+
+fn serialize_language<S: Serializer>(language: &LanguageTag, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(language.as_str())
+}
+
+fn serialize_optional_language<S>(language: &Option<LanguageTag>, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer
+{
+    match *language {
+        Some(ref tag) => serializer.serialize_some(tag.as_str()),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn serialize_optional_category<S>(category: &Option<CategoryId>, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer
+{
+    match *category {
+        Some(ref category) => serializer.serialize_some(category.as_str()),
+        None => serializer.serialize_none(),
+    }
+}
+
+future_response!(
+    /// Wrapper type for our boxed future
+    FutureSubmitBatchResponse, String);
+
+impl From<SubmitBatchRequest> for Request {
+    fn from(s: SubmitBatchRequest) -> Self {
+        json_post(s.endpoint.uri(&["batches"], &[]), &BatchBody { inputs: &s.inputs })
+    }
+}
+
+impl Cog for SubmitBatchRequest {
+    type Output = FutureSubmitBatchResponse;
+    type Item = String;
+    type Error = Error;
+}
+
+impl From<Result<Response, engine::Error>> for FutureSubmitBatchResponse {
+    fn from(result: Result<Response, engine::Error>) -> Self {
+        let f = checked_response(result).and_then(|resp| {
+            match resp.headers().get::<OperationLocation>() {
+                Some(&OperationLocation(ref location)) => {
+                    let path = location.split('?').next().unwrap_or("");
+                    Ok(path.trim_right_matches('/').rsplit('/').next().unwrap_or("").to_string())
+                }
+                None => Err(Error::MissingOperationLocation),
+            }
+        });
+        FutureSubmitBatchResponse(Box::new(f))
+    }
+}
+
+impl Operation for SubmitBatchRequest {
+    type Item = BatchStatus;
+    type Error = Error;
+
+    fn status(resp: Response) -> Box<Future<Item = Status<Self::Item>, Error = Self::Error>> {
+        Box::new(read_json(Ok(resp)).map(|status: BatchStatus| if status.status.is_finished() {
+                                             Status::Done(status)
+                                         } else {
+                                             Status::InProgress
+                                         }))
+    }
+}
+
+/// A request for the status of a batch
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchStatusRequest {
+    pub endpoint: Endpoint,
+    pub batch_id: String,
+}
+
+future_response!(
+    /// Wrapper type for our boxed future
+    FutureBatchStatusResponse, BatchStatus);
+
+impl From<BatchStatusRequest> for Request {
+    fn from(b: BatchStatusRequest) -> Self {
+        Request::new(Method::Get, b.endpoint.uri(&["batches", &b.batch_id], &[]))
+    }
+}
+
+impl Cog for BatchStatusRequest {
+    type Output = FutureBatchStatusResponse;
+    type Item = BatchStatus;
+    type Error = Error;
+}
+
+impl From<Result<Response, engine::Error>> for FutureBatchStatusResponse {
+    fn from(result: Result<Response, engine::Error>) -> Self {
+        FutureBatchStatusResponse(read_json(result))
+    }
+}
+
+/// A request for cancelling a batch.
+///
+/// Documents that are already being translated are still translated and charged for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CancelBatchRequest {
+    pub endpoint: Endpoint,
+    pub batch_id: String,
+}
+
+impl From<CancelBatchRequest> for Request {
+    fn from(c: CancelBatchRequest) -> Self {
+        Request::new(Method::Delete, c.endpoint.uri(&["batches", &c.batch_id], &[]))
+    }
+}
+
+impl Cog for CancelBatchRequest {
+    type Output = FutureBatchStatusResponse;
+    type Item = BatchStatus;
+    type Error = Error;
+}
+
+/// A request for the status of every document of a batch.
+///
+/// This is a PagedCog, use it with Engine::stream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentsRequest {
+    pub endpoint: Endpoint,
+    pub batch_id: String,
+    /// Number of documents per page, chosen by the service if None
+    pub max_page_size: Option<usize>,
+}
+
+impl PagedCog for DocumentsRequest {
+    type Item = DocumentStatus;
+    type Error = Error;
+
+    fn page_request(&self, next: Option<&NextPage>) -> Request {
+        let uri = match next {
+            Some(&NextPage::Link(ref uri)) => uri.clone(),
+            _ => {
+                let params: Vec<(&str, String)> = self.max_page_size
+                    .map(|size| ("$maxpagesize", size.to_string()))
+                    .into_iter()
+                    .collect();
+                self.endpoint
+                    .uri(&["batches", &self.batch_id, "documents"], &params)
+            }
+        };
+        Request::new(Method::Get, uri)
+    }
+
+    fn read_page(resp: Response) -> Box<Future<Item = Page<Self::Item>, Error = Self::Error>> {
+        Box::new(read_json(Ok(resp)).map(|page: DocumentsPage| {
+            Page {
+                items: page.value,
+                next: page.next_link
+                    .and_then(|link| Uri::from_str(&link).ok())
+                    .map(NextPage::Link),
+            }
+        }))
+    }
+}
+
+/// A request for the status of a single document of a batch
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentStatusRequest {
+    pub endpoint: Endpoint,
+    pub batch_id: String,
+    pub document_id: String,
+}
+
+future_response!(
+    /// Wrapper type for our boxed future
+    FutureDocumentStatusResponse, DocumentStatus);
+
+impl From<DocumentStatusRequest> for Request {
+    fn from(d: DocumentStatusRequest) -> Self {
+        let path = ["batches", &d.batch_id, "documents", &d.document_id];
+        Request::new(Method::Get, d.endpoint.uri(&path, &[]))
+    }
+}
+
+impl Cog for DocumentStatusRequest {
+    type Output = FutureDocumentStatusResponse;
+    type Item = DocumentStatus;
+    type Error = Error;
+}
+
+impl From<Result<Response, engine::Error>> for FutureDocumentStatusResponse {
+    fn from(result: Result<Response, engine::Error>) -> Self {
+        FutureDocumentStatusResponse(read_json(result))
+    }
+}
+
+/// A request for the document or glossary formats the service supports
+#[derive(Debug, Clone, PartialEq)]
+pub struct SupportedFormatsRequest {
+    pub endpoint: Endpoint,
+    pub kind: FormatKind,
+}
+
+future_response!(
+    /// Wrapper type for our boxed future
+    FutureSupportedFormatsResponse, Vec<FileFormat>);
+
+impl From<SupportedFormatsRequest> for Request {
+    fn from(s: SupportedFormatsRequest) -> Self {
+        let kind = match s.kind {
+            FormatKind::Document => "documents",
+            FormatKind::Glossary => "glossaries",
+        };
+        Request::new(Method::Get, s.endpoint.uri(&[kind, "formats"], &[]))
+    }
+}
+
+impl Cog for SupportedFormatsRequest {
+    type Output = FutureSupportedFormatsResponse;
+    type Item = Vec<FileFormat>;
+    type Error = Error;
+}
+
+impl From<Result<Response, engine::Error>> for FutureSupportedFormatsResponse {
+    fn from(result: Result<Response, engine::Error>) -> Self {
+        let f = read_json(result).map(|formats: Formats| formats.value);
+        FutureSupportedFormatsResponse(Box::new(f))
+    }
+}
+
+/// Error details returned by the service
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ApiError {
+    /// Such as "InvalidRequest", or empty if the service did not return one
+    pub code: String,
+    pub message: String,
+    /// What the error is about, such as "Document"
+    pub target: Option<String>,
+}
+
+/// Document Translation error mapping
+#[derive(Debug)]
+pub enum Error {
+    JsonError(serde_json::Error),
+    ServiceError(StatusCode, ApiError),
+    MissingOperationLocation,
+    InvalidEndpoint(String),
+    EngineError(engine::Error),
+}
+
+impl JsonError for Error {
+    type Details = ApiError;
+
+    fn message_details(message: String) -> ApiError {
+        ApiError {
+            code: String::new(),
+            message: message,
+            target: None,
+        }
+    }
+
+    fn json_error(e: serde_json::Error) -> Self {
+        Error::JsonError(e)
+    }
+
+    fn service_error(status: StatusCode, details: ApiError) -> Self {
+        Error::ServiceError(status, details)
+    }
+
+    fn engine_error(e: engine::Error) -> Self {
+        Error::EngineError(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::{self, Client};
+    use hyper::header::{Authorization, Bearer};
+    use hyper::server::{self, Http, Service};
+    use futures::Stream;
+    use futures::future;
+    use tokio_core::reactor::{Core, Handle};
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+    use std::time::Duration;
+    use engine::{Engine, Credentials, SubscriptionKey};
+    use operation::PollSettings;
+    use paging::PageSettings;
+
+    const TOKEN: &'static str = "stand-in-token";
+
+    fn batch_status(status: &str) -> String {
+        format!(r#"{{"id": "b1", "createdDateTimeUtc": "2021-03-01T10:00:00Z",
+                    "lastActionDateTimeUtc": "2021-03-01T10:01:00Z", "status": "{}",
+                    "summary": {{"total": 2, "failed": 0, "success": 2, "inProgress": 0,
+                                 "notYetStarted": 0, "cancelled": 0, "totalCharacterCharged": 120}}}}"#,
+                status)
+    }
+
+    fn document_status(id: &str) -> String {
+        format!(r#"{{"id": "{id}", "path": "https://target/{id}.docx", "sourcePath": "https://source/{id}.docx",
+                    "createdDateTimeUtc": "2021-03-01T10:00:00Z", "lastActionDateTimeUtc": "2021-03-01T10:01:00Z",
+                    "status": "Succeeded", "to": "fr", "progress": 1}}"#,
+                id = id)
+    }
+
+    /// Stands in for the token service and the Document Translation API of a resource
+    struct StandIn {
+        base: String,
+        polls: Rc<Cell<usize>>,
+        submitted: Rc<RefCell<Option<String>>>,
+    }
+
+    impl Service for StandIn {
+        type Request = server::Request;
+        type Response = server::Response;
+        type Error = hyper::Error;
+        type Future = Box<Future<Item = server::Response, Error = hyper::Error>>;
+
+        fn call(&self, req: server::Request) -> Self::Future {
+            let path = req.path().to_string();
+            let query = req.query().unwrap_or("").to_string();
+            let method = req.method().clone();
+            if path != "/sts/v1.0/issueToken" {
+                assert_eq!(req.headers().get::<Authorization<Bearer>>().map(|a| a.token.as_str()),
+                           Some(TOKEN));
+            }
+            let api = "/translator/text/batch/v1.1";
+            let ok = |body: String| server::Response::new().with_body(body);
+            let resp = match (method, path.as_str()) {
+                (Method::Post, "/sts/v1.0/issueToken") => ok(TOKEN.to_string()),
+                (Method::Post, p) if p == format!("{}/batches", api) => {
+                    let submitted = self.submitted.clone();
+                    let location = format!("{}{}/batches/b1", self.base, api);
+                    return Box::new(req.body().concat2().map(move |body| {
+                        *submitted.borrow_mut() = Some(String::from_utf8(body.to_vec()).unwrap());
+                        server::Response::new()
+                            .with_status(StatusCode::Accepted)
+                            .with_header(OperationLocation(location))
+                    }));
+                }
+                (Method::Get, p) if p == format!("{}/batches/b1", api) => {
+                    self.polls.set(self.polls.get() + 1);
+                    ok(batch_status(if self.polls.get() < 2 { "Running" } else { "Succeeded" }))
+                }
+                (Method::Delete, p) if p == format!("{}/batches/b1", api) => ok(batch_status("Cancelling")),
+                (Method::Get, p) if p == format!("{}/batches/b1/documents", api) => {
+                    if query.contains("skip") {
+                        ok(format!(r#"{{"value": [{}]}}"#, document_status("d2")))
+                    } else {
+                        assert_eq!(query, "%24maxpagesize=1");
+                        ok(format!(r#"{{"value": [{}], "@nextLink": "{}{}/batches/b1/documents?$skip=1"}}"#,
+                                   document_status("d1"),
+                                   self.base,
+                                   api))
+                    }
+                }
+                (Method::Get, p) if p == format!("{}/documents/formats", api) => {
+                    ok(r#"{"value": [{"format": "PlainText", "fileExtensions": [".txt"],
+                                      "contentTypes": ["text/plain"], "versions": []}]}"#
+                               .to_string())
+                }
+                _ => {
+                    server::Response::new()
+                        .with_status(StatusCode::NotFound)
+                        .with_body(r#"{"error": {"code": "NotFound", "message": "No such batch", "target": "Batch"}}"#)
+                }
+            };
+            Box::new(future::ok(resp))
+        }
+    }
+
+    /// Starts a StandIn on the event loop of the given handle, returning its base URI
+    fn stand_in(handle: &Handle, polls: Rc<Cell<usize>>, submitted: Rc<RefCell<Option<String>>>) -> String {
+        let addr = "127.0.0.1:0".parse().unwrap();
+        let base = Rc::new(RefCell::new(String::new()));
+        let base_ref = base.clone();
+        let serve = Http::new()
+            .serve_addr_handle(&addr,
+                               handle,
+                               move || {
+                Ok(StandIn {
+                       base: base_ref.borrow().clone(),
+                       polls: polls.clone(),
+                       submitted: submitted.clone(),
+                   })
+            })
+            .unwrap();
+        *base.borrow_mut() = format!("http://{}", serve.incoming_ref().local_addr());
+        let handle_ref = handle.clone();
+        handle.spawn(serve
+                         .for_each(move |conn| {
+                                       handle_ref.spawn(conn.map(|_| ()).map_err(|_| ()));
+                                       Ok(())
+                                   })
+                         .map_err(|_| ()));
+        let base = base.borrow().clone();
+        base
+    }
+
+    #[test]
+    fn stand_in_test() {
+        let mut core = Core::new().unwrap();
+        let polls = Rc::new(Cell::new(0));
+        let submitted = Rc::new(RefCell::new(None));
+        let base = stand_in(&core.handle(), polls.clone(), submitted.clone());
+        let token_uri = Uri::from_str(&format!("{}/sts/v1.0/issueToken", base)).unwrap();
+        let credentials = Credentials::new(SubscriptionKey::new("key")).with_token_uri(token_uri);
+        let engine = Engine::new(credentials, Client::new(&core.handle()));
+        let endpoint = Endpoint::new(format!("{}/", base)).unwrap();
+
+        let mut target = DocumentTarget::new("https://target", LanguageTag::parse("fr").unwrap());
+        target.category = Some(CategoryId::parse("tech").unwrap());
+        target.glossaries.push(GlossaryFile {
+                                   glossary_url: "https://glossaries/terms.tsv".to_string(),
+                                   format: "TSV".to_string(),
+                                   version: None,
+                               });
+        let submit = SubmitBatchRequest {
+            endpoint: endpoint.clone(),
+            inputs: vec![BatchInput {
+                             source: DocumentSource {
+                                 language: Some(LanguageTag::parse("en").unwrap()),
+                                 ..DocumentSource::new("https://source")
+                             },
+                             targets: vec![target],
+                             storage_type: Some(StorageType::Folder),
+                         }],
+        };
+        let settings = PollSettings {
+            initial_delay: Duration::from_millis(1),
+            ..PollSettings::default()
+        };
+        let status = core.run(engine.run_operation(submit.clone(), settings)).unwrap();
+        assert_eq!(status.status, JobStatus::Succeeded);
+        assert_eq!(status.summary.total_character_charged, 120);
+        assert_eq!(polls.get(), 2);
+        assert_eq!(submitted.borrow().clone().unwrap(),
+                   r#"{"inputs":[{"source":{"sourceUrl":"https://source","language":"en"},"targets":[{"targetUrl":"https://target","language":"fr","category":"tech","glossaries":[{"glossaryUrl":"https://glossaries/terms.tsv","format":"TSV"}]}],"storageType":"Folder"}]}"#);
+        assert_eq!(core.run(engine.run(submit)).unwrap(), "b1");
+
+        let documents = DocumentsRequest {
+            endpoint: endpoint.clone(),
+            batch_id: "b1".to_string(),
+            max_page_size: Some(1),
+        };
+        let documents = core.run(engine.stream(documents, PageSettings::default()).collect())
+            .unwrap();
+        let ids: Vec<&str> = documents.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, vec!["d1", "d2"]);
+        assert_eq!(documents[0].path, Some("https://target/d1.docx".to_string()));
+
+        let cancel = CancelBatchRequest {
+            endpoint: endpoint.clone(),
+            batch_id: "b1".to_string(),
+        };
+        assert_eq!(core.run(engine.run(cancel)).unwrap().status, JobStatus::Cancelling);
+
+        let formats = SupportedFormatsRequest {
+            endpoint: endpoint.clone(),
+            kind: FormatKind::Document,
+        };
+        assert_eq!(core.run(engine.run(formats)).unwrap()[0].file_extensions, vec![".txt"]);
+
+        let missing = BatchStatusRequest {
+            endpoint: endpoint,
+            batch_id: "b2".to_string(),
+        };
+        match core.run(engine.run(missing)) {
+            Err(Error::ServiceError(StatusCode::NotFound, ref e)) if e.code == "NotFound" => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn endpoint_test() {
+        let endpoint = Endpoint::new("https://my-resource.cognitiveservices.azure.com/").unwrap();
+        assert_eq!(endpoint.as_str(), "https://my-resource.cognitiveservices.azure.com");
+        let req: Request = DocumentStatusRequest {
+                endpoint: endpoint,
+                batch_id: "b/1?x".to_string(),
+                document_id: "../d 1#".to_string(),
+            }
+            .into();
+        assert_eq!(req.uri().path(),
+                   "/translator/text/batch/v1.1/batches/b%2F1%3Fx/documents/..%2Fd%201%23");
+        assert_eq!(req.uri().query(), None);
+        for invalid in &["my-resource.cognitiveservices.azure.com", "ftp://host", "https://host?key=1", ""] {
+            match Endpoint::new(*invalid) {
+                Err(Error::InvalidEndpoint(_)) => (),
+                other => panic!("Unexpected result for {}: {:?}", invalid, other),
+            }
+        }
+    }
+}
@@ -1,10 +1,52 @@
 //! This module holds logic and data structures related to cognition services,
 //! called Cogs.
 
+/// Defines a wrapper type for a boxed future, to be used as the Output of a Cog
+macro_rules! future_response {
+    ($(#[$attr:meta])* $name:ident, $item:ty) => {
+        $(#[$attr])*
+        pub struct $name(Box<Future<Item = $item, Error = Error>>);
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.pad(stringify!($name))
+            }
+        }
+
+        impl Future for $name {
+            type Item = $item;
+            type Error = Error;
+
+            fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+                self.0.poll()
+            }
+        }
+    }
+}
+
+/// Implements Cog for the owned variant of a request, with the same Output as the borrowing one
+macro_rules! owned_cog {
+    ($owned:ident, $borrowed:ident) => {
+        impl Cog for $owned {
+            type Output = <$borrowed<'static> as Cog>::Output;
+            type Item = <$borrowed<'static> as Cog>::Item;
+            type Error = <$borrowed<'static> as Cog>::Error;
+        }
+    }
+}
+
 pub mod translation;
+pub mod translator_v3;
+pub mod document_translation;
 
 use hyper::client::{Request, Response};
+use hyper::{Method, StatusCode, Uri};
+use hyper::header::{ContentLength, ContentType};
 use futures::Future;
+use futures::future;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json;
 use super::engine;
 
 /// Trait representing something that can be turned into a Cognitive Service endpoint.
@@ -26,6 +68,7 @@ use super::engine;
 /// # use hyper_tls;
 /// # use std::env;
 /// # use cogs::cogs::translation::TranslateRequest;
+/// # use cogs::language::LanguageTag;
 /// let mut core = tokio_core::reactor::Core::new().unwrap();
 /// let handle = core.handle();
 /// let client = hyper::Client::configure()
@@ -35,12 +78,14 @@ use super::engine;
 /// # let sub_key = SubscriptionKey::new(env::var("AZURE_SUBSCRIPTION_KEY").unwrap().as_str());
 /// let credentials = Credentials::new(sub_key);
 /// let engine = Engine::new(credentials, client);
+/// let (en, de) = (LanguageTag::parse("en").unwrap(), LanguageTag::parse("de").unwrap());
 /// let translate_req = TranslateRequest {
 ///     text: "Hello",
-///     from: Some("en"),
-///     to: "de",
+///     from: Some(&en),
+///     to: &de,
 ///     content_type: None,
 ///     category: None,
+///     profanity_action: None,
 /// };
 /// let work = engine.run(translate_req);
 /// // TODO: get a sandbox key so this actually works as expected, returning "Hallo"
@@ -64,3 +109,87 @@ pub trait Cog: Into<Request> {
     /// Error type
     type Error: 'static;
 }
+
+/// Copies borrowed strings, for building the owned variant of a request
+fn owned_strings(strings: &[&str]) -> Vec<String> {
+    strings.iter().map(|s| s.to_string()).collect()
+}
+
+/// Borrows owned strings, for turning the owned variant of a request back into the borrowing one
+fn str_refs(strings: &[String]) -> Vec<&str> {
+    strings.iter().map(|s| s.as_str()).collect()
+}
+
+/// Error type of a JSON based API, letting it use `checked_response` and `read_json`
+trait JsonError: 'static {
+    /// Error details the service returns in the body of unsuccessful Responses
+    type Details: DeserializeOwned;
+
+    /// Returns details holding just a message, for bodies that aren't the usual JSON
+    fn message_details(message: String) -> Self::Details;
+
+    fn json_error(e: serde_json::Error) -> Self;
+
+    fn service_error(status: StatusCode, details: Self::Details) -> Self;
+
+    fn engine_error(e: engine::Error) -> Self;
+}
+
+#[derive(Deserialize)]
+struct ErrorBody<D> {
+    error: D,
+}
+
+/// Builds a POST Request for the given URI, with the given body as JSON
+fn json_post<B>(uri: Uri, body: &B) -> Request
+    where B: Serialize
+{
+    // Serializing plain structs and vecs of them can't fail
+    let body = serde_json::to_vec(body).unwrap();
+    let mut req = Request::new(Method::Post, uri);
+    {
+        let headers = req.headers_mut();
+        headers.set(ContentType::json());
+        headers.set(ContentLength(body.len() as u64));
+    }
+    req.set_body(body);
+    req
+}
+
+/// Checks that a Response was successful, turning the error of the service into a service error otherwise
+fn checked_response<E>(result: Result<Response, engine::Error>) -> Box<Future<Item = Response, Error = E>>
+    where E: JsonError
+{
+    match result {
+        Ok(resp) => {
+            let status = resp.status();
+            if status.is_success() {
+                Box::new(future::ok(resp))
+            } else {
+                Box::new(engine::read_to_bytes(resp)
+                             .map_err(E::engine_error)
+                             .and_then(move |b| Err(E::service_error(status, error_details::<E>(&b)))))
+            }
+        }
+        Err(e) => Box::new(future::err(E::engine_error(e))),
+    }
+}
+
+/// Checks that a Response was successful and parses its body as JSON
+fn read_json<T, E>(result: Result<Response, engine::Error>) -> Box<Future<Item = T, Error = E>>
+    where T: DeserializeOwned + 'static,
+          E: JsonError
+{
+    let f = checked_response::<E>(result)
+        .and_then(|resp| engine::read_to_bytes(resp).map_err(E::engine_error))
+        .and_then(|b| serde_json::from_slice(&b).map_err(E::json_error));
+    Box::new(f)
+}
+
+/// Parses the error details in the body of an unsuccessful Response, falling back to the raw body
+fn error_details<E: JsonError>(body: &[u8]) -> E::Details {
+    match serde_json::from_slice::<ErrorBody<E::Details>>(body) {
+        Ok(parsed) => parsed.error,
+        Err(_) => E::message_details(String::from_utf8_lossy(body).into_owned()),
+    }
+}
@@ -4,106 +4,1881 @@ use hyper::client::Response;
 use engine;
 use futures::Future;
 use url::Url;
-use hyper::Uri;
+use hyper::{Uri, StatusCode};
 use hyper::Method;
+use hyper::header::{ContentLength, ContentType};
 use core::str::FromStr;
-use futures::Poll;
+use futures::{Poll, Stream};
+use hyper::Chunk;
 use std::fmt;
+use std::ops::Range;
 use elementtree::*;
 use futures::future;
 use super::*;
+use flow::{self, CogFlow, Concat, Flow};
+use engine::{Engine, Dispatcher};
+use hyper::client::{Connect, Service};
+use time::{self, Tm, Duration};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use language::LanguageTag;
+use category::CategoryId;
 
 /// A Translation request.
 pub struct TranslateRequest<'a> {
     pub text: &'a str,
-    pub from: Option<&'a str>,
-    pub to: &'a str,
+    pub from: Option<&'a LanguageTag>,
+    pub to: &'a LanguageTag,
     pub content_type: Option<TranslateContentType>,
-    pub category: Option<&'a str>,
+    /// The v2 API quietly uses the general model when there is no model for the
+    /// category, and gives no way to tell. Use category::translate to know which
+    /// model was used.
+    pub category: Option<&'a CategoryId>,
+    /// The v2 API always marks profanity with tags, see parse_profanity
+    pub profanity_action: Option<ProfanityAction>,
 }
 
 /// Possible Content types for translation
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
 pub enum TranslateContentType {
     Plain,
     Html,
 }
 
-/// Wrapper type for our boxed future
-pub struct FutureTranslateResponse(Box<Future<Item = String, Error = Error>>);
+/// What the service should do with profanity found in translations.
+///
+/// Sent as the `profanityAction` option of the v3 translate cog, and shared with the v2 cogs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub enum ProfanityAction {
+    NoAction,
+    Marked,
+    Deleted,
+}
+
+/// How profanity is marked when using ProfanityAction::Marked.
+///
+/// Sent as the `profanityMarker` option of the v3 translate cog, the v2 API always uses tags.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub enum ProfanityMarker {
+    Asterisk,
+    Tag,
+}
+
+impl ProfanityAction {
+    /// Returns the value the service expects for this action
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            ProfanityAction::NoAction => "NoAction",
+            ProfanityAction::Marked => "Marked",
+            ProfanityAction::Deleted => "Deleted",
+        }
+    }
+}
+
+impl ProfanityMarker {
+    /// Returns the value the service expects for this marker
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            ProfanityMarker::Asterisk => "Asterisk",
+            ProfanityMarker::Tag => "Tag",
+        }
+    }
+}
+
+/// A translation with its profanity marks parsed out
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarkedProfanity {
+    /// The translation without any tags
+    pub text: String,
+    /// Character ranges of the profanity in `text`
+    pub spans: Vec<Range<usize>>,
+}
+
+const PROFANITY_OPEN: &'static str = "<profanity>";
+const PROFANITY_CLOSE: &'static str = "</profanity>";
+
+/// Parses the profanity marked in a translation using the given marker.
+///
+/// Tags are removed from the text, while asterisks are left in place with
+/// every run of them making up a span. The service does not tell its asterisks
+/// apart from those already in the text, so with ProfanityMarker::Asterisk any
+/// literal "*" is reported as masked profanity too. Use ProfanityMarker::Tag
+/// when the texts may contain asterisks.
+pub fn parse_profanity(translated: &str, marker: ProfanityMarker) -> MarkedProfanity {
+    let mut text = String::with_capacity(translated.len());
+    let mut spans = vec![];
+    match marker {
+        ProfanityMarker::Tag => {
+            let mut rest = translated;
+            let mut chars = 0;
+            while let Some(open) = rest.find(PROFANITY_OPEN) {
+                let (before, after) = rest.split_at(open);
+                let after = &after[PROFANITY_OPEN.len()..];
+                match after.find(PROFANITY_CLOSE) {
+                    Some(close) => {
+                        let word = &after[..close];
+                        text.push_str(before);
+                        chars += before.chars().count();
+                        let word_chars = word.chars().count();
+                        text.push_str(word);
+                        spans.push(chars..chars + word_chars);
+                        chars += word_chars;
+                        rest = &after[close + PROFANITY_CLOSE.len()..];
+                    }
+                    // An unclosed tag is kept as is
+                    None => break,
+                }
+            }
+            text.push_str(rest);
+        }
+        ProfanityMarker::Asterisk => {
+            let mut start = None;
+            for (i, c) in translated.chars().enumerate() {
+                match (c == '*', start) {
+                    (true, None) => start = Some(i),
+                    (false, Some(s)) => {
+                        spans.push(s..i);
+                        start = None;
+                    }
+                    _ => (),
+                }
+            }
+            if let Some(s) = start {
+                spans.push(s..translated.chars().count());
+            }
+            text.push_str(translated);
+        }
+    }
+    MarkedProfanity {
+        text: text,
+        spans: spans,
+    }
+}
+
+future_response!(
+    /// Wrapper type for our boxed future
+    FutureTranslateResponse, String);
+
+const TRANSLATE_BASE_URI: &'static str = "https://api.microsofttranslator.com/v2/http.svc/Translate";
+const TRANSLATE_ARRAY_URI: &'static str = "https://api.microsofttranslator.com/v2/http.svc/TranslateArray";
+const DETECT_URI: &'static str = "https://api.microsofttranslator.com/v2/http.svc/Detect";
+const BREAK_SENTENCES_URI: &'static str = "https://api.microsofttranslator.com/v2/http.svc/BreakSentences";
+const GET_TRANSLATIONS_URI: &'static str = "https://api.microsofttranslator.com/v2/http.svc/GetTranslations";
+const GET_TRANSLATIONS_ARRAY_URI: &'static str = "https://api.microsofttranslator.com/v2/http.svc/GetTranslationsArray";
+const DETECT_ARRAY_URI: &'static str = "https://api.microsofttranslator.com/v2/http.svc/DetectArray";
+const GET_LANGUAGES_FOR_TRANSLATE_URI: &'static str = "https://api.microsofttranslator.com/v2/http.svc/GetLanguagesForTranslate";
+const GET_LANGUAGES_FOR_SPEAK_URI: &'static str = "https://api.microsofttranslator.com/v2/http.svc/GetLanguagesForSpeak";
+const SPEAK_URI: &'static str = "https://api.microsofttranslator.com/v2/http.svc/Speak";
+const ADD_TRANSLATION_URI: &'static str = "https://api.microsofttranslator.com/v2/http.svc/AddTranslation";
+const ADD_TRANSLATION_ARRAY_URI: &'static str = "https://api.microsofttranslator.com/v2/http.svc/AddTranslationArray";
+const GET_LANGUAGE_NAMES_URI: &'static str = "https://api.microsofttranslator.com/v2/http.svc/GetLanguageNames";
+
+const V2_NS: &'static str = "http://schemas.datacontract.org/2004/07/Microsoft.MT.Web.Service.V2";
+const ARRAYS_NS: &'static str = "http://schemas.microsoft.com/2003/10/Serialization/Arrays";
+
+/// Maximum number of texts the service accepts in a single array request
+pub const MAX_ARRAY_TEXTS: usize = 2000;
+/// Maximum number of translations the service accepts in a single AddTranslationArrayRequest
+pub const MAX_ADD_TRANSLATIONS: usize = 100;
+/// Maximum number of characters of an original text submitted with a translation
+pub const MAX_ADD_ORIGINAL_CHARS: usize = 1000;
+/// Maximum number of characters of a submitted translation
+pub const MAX_ADD_TRANSLATED_CHARS: usize = 2000;
+/// Maximum number of characters, across all texts, the service accepts in a single array request
+pub const MAX_ARRAY_CHARS: usize = 10000;
+
+impl<'a> From<TranslateRequest<'a>> for Request {
+    fn from(t: TranslateRequest<'a>) -> Self {
+        let mut url = Url::parse_with_params(TRANSLATE_BASE_URI, &[("to", t.to.v2_code()), ("text", t.text)])
+            .unwrap();
+        {
+            let mut mut_pairs = url.query_pairs_mut();
+            match t.from {
+                Some(from) => {
+                    mut_pairs.append_pair("from", from.v2_code());
+                }
+                _ => (),
+            }
+            match t.content_type {
+                Some(TranslateContentType::Html) => {
+                    mut_pairs.append_pair("contentType", "text/html");
+                }
+                Some(TranslateContentType::Plain) => {
+                    mut_pairs.append_pair("contentType", "text/plain");
+                }
+                _ => (),
+            }
+            match t.category {
+                Some(cat) => {
+                    mut_pairs.append_pair("category", cat.as_str());
+                }
+                _ => (),
+            }
+            match t.profanity_action {
+                Some(action) => {
+                    mut_pairs.append_pair("profanityAction", action.as_str());
+                }
+                _ => (),
+            }
+            mut_pairs.finish();
+        }
+        let as_uri = Uri::from_str(url.as_str()).unwrap();
+        Request::new(Method::Get, as_uri)
+    }
+}
+
+impl<'a> Cog for TranslateRequest<'a> {
+    type Output = FutureTranslateResponse;
+    type Item = String;
+    type Error = Error;
+}
+
+impl From<Result<Response, engine::Error>> for FutureTranslateResponse {
+    fn from(result: Result<Response, engine::Error>) -> Self {
+        FutureTranslateResponse(Box::new(read_xml(result).map(|root| root.text().to_string())))
+    }
+}
+
+/// A request for translating many texts at once.
+///
+/// The service limits how many texts and characters a single request can hold,
+/// see `batched` for translating any number of texts.
+pub struct TranslateArrayRequest<'a> {
+    pub texts: &'a [&'a str],
+    pub from: Option<&'a LanguageTag>,
+    pub to: &'a LanguageTag,
+    pub content_type: Option<TranslateContentType>,
+    pub category: Option<&'a CategoryId>,
+    pub state: Option<&'a str>,
+    pub profanity_action: Option<ProfanityAction>,
+}
+
+impl<'a> TranslateArrayRequest<'a> {
+    /// Splits this request into as many requests as needed to stay within
+    /// MAX_ARRAY_TEXTS and MAX_ARRAY_CHARS, keeping the texts in order.
+    ///
+    /// A single text that is longer than MAX_ARRAY_CHARS gets a request of its own.
+    pub fn split(&self) -> Vec<TranslateArrayRequest<'a>> {
+        let texts = self.texts;
+        let mut requests = vec![];
+        let mut start = 0;
+        let mut chars = 0;
+        for (i, text) in texts.iter().enumerate() {
+            let text_chars = text.chars().count();
+            if i > start && (i - start == MAX_ARRAY_TEXTS || chars + text_chars > MAX_ARRAY_CHARS) {
+                requests.push(self.with_texts(&texts[start..i]));
+                start = i;
+                chars = 0;
+            }
+            chars += text_chars;
+        }
+        if start < texts.len() {
+            requests.push(self.with_texts(&texts[start..]));
+        }
+        requests
+    }
+
+    /// Returns a Flow that translates all the texts, splitting them across
+    /// as many requests as needed. Translations are returned in order.
+    ///
+    /// The requests are sent one after the other, use `Concat::with_concurrency`
+    /// on the returned Flow to send several at once.
+    pub fn batched(&self) -> Concat<CogFlow<FutureTranslateArrayResponse>> {
+        flow::concat(self.split().into_iter().map(flow::cog).collect())
+    }
+
+    fn with_texts(&self, texts: &'a [&'a str]) -> TranslateArrayRequest<'a> {
+        TranslateArrayRequest {
+            texts: texts,
+            from: self.from,
+            to: self.to,
+            content_type: self.content_type,
+            category: self.category,
+            state: self.state,
+            profanity_action: self.profanity_action,
+        }
+    }
+}
+
+/// A single translation returned by a TranslateArrayRequest
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArrayTranslation {
+    /// Language of the original text, as detected by the service if it was not given
+    pub from: String,
+    pub translated_text: String,
+    pub original_text_sentence_lengths: Vec<usize>,
+    pub translated_text_sentence_lengths: Vec<usize>,
+    pub state: Option<String>,
+}
+
+future_response!(
+    /// Wrapper type for our boxed future
+    FutureTranslateArrayResponse, Vec<ArrayTranslation>);
+
+impl<'a> From<TranslateArrayRequest<'a>> for Request {
+    fn from(t: TranslateArrayRequest<'a>) -> Self {
+        let mut root = Element::new("TranslateArrayRequest");
+        root.append_new_child("AppId");
+        if let Some(from) = t.from {
+            root.append_new_child("From").set_text(from.v2_code());
+        }
+        {
+            let options = root.append_new_child("Options");
+            append_translate_options(options, t.category, t.content_type, t.profanity_action, t.state);
+        }
+        {
+            let texts = root.append_new_child("Texts");
+            for text in t.texts {
+                texts
+                    .append_new_child((ARRAYS_NS, "string"))
+                    .set_text(*text);
+            }
+        }
+        root.append_new_child("To").set_text(t.to.v2_code());
+        xml_post(TRANSLATE_ARRAY_URI, &root)
+    }
+}
+
+impl<'a> Cog for TranslateArrayRequest<'a> {
+    type Output = FutureTranslateArrayResponse;
+    type Item = Vec<ArrayTranslation>;
+    type Error = Error;
+}
+
+impl From<Result<Response, engine::Error>> for FutureTranslateArrayResponse {
+    fn from(result: Result<Response, engine::Error>) -> Self {
+        let f = read_xml(result).and_then(|root| {
+            root.find_all((V2_NS, "TranslateArrayResponse"))
+                .map(|el| {
+                         Ok(ArrayTranslation {
+                                from: v2_text(el, "From").to_string(),
+                                translated_text: v2_text(el, "TranslatedText").to_string(),
+                                original_text_sentence_lengths: sentence_lengths(el, "OriginalTextSentenceLengths")?,
+                                translated_text_sentence_lengths: sentence_lengths(el, "TranslatedTextSentenceLengths")?,
+                                state: v2_opt_text(el, "State"),
+                            })
+                     })
+                .collect::<Result<Vec<_>, _>>()
+        });
+        FutureTranslateArrayResponse(Box::new(f))
+    }
+}
+
+/// A request for detecting the language of a text
+pub struct DetectRequest<'a> {
+    pub text: &'a str,
+}
+
+future_response!(
+    /// Wrapper type for our boxed future
+    FutureDetectResponse, String);
+
+impl<'a> From<DetectRequest<'a>> for Request {
+    fn from(d: DetectRequest<'a>) -> Self {
+        let url = Url::parse_with_params(DETECT_URI, &[("text", d.text)]).unwrap();
+        let as_uri = Uri::from_str(url.as_str()).unwrap();
+        Request::new(Method::Get, as_uri)
+    }
+}
+
+impl<'a> Cog for DetectRequest<'a> {
+    type Output = FutureDetectResponse;
+    type Item = String;
+    type Error = Error;
+}
+
+impl From<Result<Response, engine::Error>> for FutureDetectResponse {
+    fn from(result: Result<Response, engine::Error>) -> Self {
+        let f = read_xml(result).map(|root| root.text().to_string());
+        FutureDetectResponse(Box::new(f))
+    }
+}
+
+/// A request for detecting the languages of many texts at once.
+///
+/// Language codes are returned in the same order as the texts.
+pub struct DetectArrayRequest<'a> {
+    pub texts: &'a [&'a str],
+}
+
+future_response!(
+    /// Wrapper type for our boxed future
+    FutureDetectArrayResponse, Vec<String>);
+
+impl<'a> From<DetectArrayRequest<'a>> for Request {
+    fn from(d: DetectArrayRequest<'a>) -> Self {
+        xml_post(DETECT_ARRAY_URI, &string_array(d.texts))
+    }
+}
+
+impl<'a> Cog for DetectArrayRequest<'a> {
+    type Output = FutureDetectArrayResponse;
+    type Item = Vec<String>;
+    type Error = Error;
+}
+
+impl From<Result<Response, engine::Error>> for FutureDetectArrayResponse {
+    fn from(result: Result<Response, engine::Error>) -> Self {
+        let f = read_xml(result).map(|root| read_string_array(&root));
+        FutureDetectArrayResponse(Box::new(f))
+    }
+}
+
+/// A request for breaking a text into sentences
+pub struct BreakSentencesRequest<'a> {
+    pub text: &'a str,
+    pub language: &'a LanguageTag,
+}
+
+/// Sentence boundaries returned by a BreakSentencesRequest
+#[derive(Debug, Clone, PartialEq)]
+pub struct SentenceBreaks {
+    /// Length of each sentence, in characters
+    pub lengths: Vec<usize>,
+}
+
+impl SentenceBreaks {
+    /// Returns the character range of each sentence
+    pub fn offsets(&self) -> Vec<Range<usize>> {
+        let mut start = 0;
+        self.lengths
+            .iter()
+            .map(|len| {
+                     let range = start..start + len;
+                     start += *len;
+                     range
+                 })
+            .collect()
+    }
+
+    /// Returns the sentences of the text that was broken up.
+    ///
+    /// Sentences reaching past the end of the given text are cut short.
+    pub fn sentences<'t>(&self, text: &'t str) -> Vec<&'t str> {
+        // Byte index of every char boundary, including the end of the text
+        let boundaries: Vec<usize> = text.char_indices()
+            .map(|(i, _)| i)
+            .chain(Some(text.len()))
+            .collect();
+        let byte_at = |char_idx: usize| boundaries[char_idx.min(boundaries.len() - 1)];
+        self.offsets()
+            .into_iter()
+            .map(|r| &text[byte_at(r.start)..byte_at(r.end)])
+            .collect()
+    }
+}
+
+future_response!(
+    /// Wrapper type for our boxed future
+    FutureBreakSentencesResponse, SentenceBreaks);
+
+impl<'a> From<BreakSentencesRequest<'a>> for Request {
+    fn from(b: BreakSentencesRequest<'a>) -> Self {
+        let url = Url::parse_with_params(BREAK_SENTENCES_URI,
+                                         &[("text", b.text), ("language", b.language.v2_code())])
+                .unwrap();
+        let as_uri = Uri::from_str(url.as_str()).unwrap();
+        Request::new(Method::Get, as_uri)
+    }
+}
+
+impl<'a> Cog for BreakSentencesRequest<'a> {
+    type Output = FutureBreakSentencesResponse;
+    type Item = SentenceBreaks;
+    type Error = Error;
+}
+
+impl From<Result<Response, engine::Error>> for FutureBreakSentencesResponse {
+    fn from(result: Result<Response, engine::Error>) -> Self {
+        let f = read_xml(result).and_then(|root| {
+            root.find_all((ARRAYS_NS, "int"))
+                .map(|i| i.text().parse().map_err(|_| Error::XMLParsingError))
+                .collect::<Result<Vec<_>, _>>()
+                .map(|lengths| SentenceBreaks { lengths: lengths })
+        });
+        FutureBreakSentencesResponse(Box::new(f))
+    }
+}
+
+/// A request for the ranked candidate translations of a text
+pub struct GetTranslationsRequest<'a> {
+    pub text: &'a str,
+    pub from: &'a LanguageTag,
+    pub to: &'a LanguageTag,
+    pub max_translations: usize,
+    pub content_type: Option<TranslateContentType>,
+    pub category: Option<&'a CategoryId>,
+    pub state: Option<&'a str>,
+    pub profanity_action: Option<ProfanityAction>,
+}
+
+/// A request for the ranked candidate translations of many texts at once
+pub struct GetTranslationsArrayRequest<'a> {
+    pub texts: &'a [&'a str],
+    pub from: &'a LanguageTag,
+    pub to: &'a LanguageTag,
+    pub max_translations: usize,
+    pub content_type: Option<TranslateContentType>,
+    pub category: Option<&'a CategoryId>,
+    pub state: Option<&'a str>,
+    pub profanity_action: Option<ProfanityAction>,
+}
+
+/// Candidate translations of a single text
+#[derive(Debug, Clone, PartialEq)]
+pub struct Translations {
+    pub from: String,
+    pub state: Option<String>,
+    /// Candidates, best first
+    pub matches: Vec<TranslationMatch>,
+}
+
+/// A single candidate translation
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranslationMatch {
+    pub translated_text: String,
+    /// Set if the candidate comes from user submitted translations
+    pub matched_original_text: Option<String>,
+    /// How good the candidate is, from -10 to 10. Machine translations are rated 5
+    pub rating: i32,
+    /// How many times this candidate was submitted by users
+    pub count: u32,
+    /// How closely the original text matched the text the candidate was submitted for, from 0 to 100
+    pub match_degree: u32,
+    pub error: Option<String>,
+}
+
+future_response!(
+    /// Wrapper type for our boxed future
+    FutureGetTranslationsResponse, Translations);
+
+future_response!(
+    /// Wrapper type for our boxed future
+    FutureGetTranslationsArrayResponse, Vec<Translations>);
+
+impl<'a> From<GetTranslationsRequest<'a>> for Request {
+    fn from(g: GetTranslationsRequest<'a>) -> Self {
+        let max_translations = g.max_translations.to_string();
+        let url = Url::parse_with_params(GET_TRANSLATIONS_URI,
+                                         &[("text", g.text),
+                                           ("from", g.from.v2_code()),
+                                           ("to", g.to.v2_code()),
+                                           ("maxTranslations", max_translations.as_str())])
+                .unwrap();
+        let mut options = Element::new((V2_NS, "TranslateOptions"));
+        append_translate_options(&mut options, g.category, g.content_type, g.profanity_action, g.state);
+        xml_post(url.as_str(), &options)
+    }
+}
+
+impl<'a> Cog for GetTranslationsRequest<'a> {
+    type Output = FutureGetTranslationsResponse;
+    type Item = Translations;
+    type Error = Error;
+}
+
+impl From<Result<Response, engine::Error>> for FutureGetTranslationsResponse {
+    fn from(result: Result<Response, engine::Error>) -> Self {
+        let f = read_xml(result).and_then(|root| read_translations(&root));
+        FutureGetTranslationsResponse(Box::new(f))
+    }
+}
+
+impl<'a> From<GetTranslationsArrayRequest<'a>> for Request {
+    fn from(g: GetTranslationsArrayRequest<'a>) -> Self {
+        let mut root = Element::new("GetTranslationsArrayRequest");
+        root.append_new_child("AppId");
+        root.append_new_child("From").set_text(g.from.v2_code());
+        {
+            let options = root.append_new_child("Options");
+            append_translate_options(options, g.category, g.content_type, g.profanity_action, g.state);
+        }
+        {
+            let texts = root.append_new_child("Texts");
+            for text in g.texts {
+                texts
+                    .append_new_child((ARRAYS_NS, "string"))
+                    .set_text(*text);
+            }
+        }
+        root.append_new_child("To").set_text(g.to.v2_code());
+        root.append_new_child("MaxTranslations")
+            .set_text(g.max_translations.to_string());
+        xml_post(GET_TRANSLATIONS_ARRAY_URI, &root)
+    }
+}
+
+impl<'a> Cog for GetTranslationsArrayRequest<'a> {
+    type Output = FutureGetTranslationsArrayResponse;
+    type Item = Vec<Translations>;
+    type Error = Error;
+}
+
+impl From<Result<Response, engine::Error>> for FutureGetTranslationsArrayResponse {
+    fn from(result: Result<Response, engine::Error>) -> Self {
+        let f = read_xml(result).and_then(|root| {
+                                              root.find_all((V2_NS, "GetTranslationsResponse"))
+                                                  .map(read_translations)
+                                                  .collect::<Result<Vec<_>, _>>()
+                                          });
+        FutureGetTranslationsArrayResponse(Box::new(f))
+    }
+}
+
+/// Reads a GetTranslationsResponse element
+fn read_translations(el: &Element) -> Result<Translations, Error> {
+    let matches = match el.find((V2_NS, "Translations")) {
+        Some(translations) => {
+            translations
+                .find_all((V2_NS, "TranslationMatch"))
+                .map(|m| {
+                         Ok(TranslationMatch {
+                                translated_text: v2_text(m, "TranslatedText").to_string(),
+                                matched_original_text: v2_opt_text(m, "MatchedOriginalText"),
+                                rating: v2_number(m, "Rating")?,
+                                count: v2_number(m, "Count")?,
+                                match_degree: v2_number(m, "MatchDegree")?,
+                                error: v2_opt_text(m, "Error"),
+                            })
+                     })
+                .collect::<Result<Vec<_>, _>>()?
+        }
+        None => vec![],
+    };
+    Ok(Translations {
+           from: v2_text(el, "From").to_string(),
+           state: v2_opt_text(el, "State"),
+           matches: matches,
+       })
+}
+
+/// A request for the codes of the languages that can be translated
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct GetLanguagesForTranslateRequest;
+
+/// A request for the codes of the languages that can be spoken
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct GetLanguagesForSpeakRequest;
+
+/// A request for the names of languages, localized into the given locale.
+///
+/// Names are returned in the same order as the language codes.
+pub struct GetLanguageNamesRequest<'a> {
+    pub locale: &'a LanguageTag,
+    pub language_codes: &'a [LanguageTag],
+}
+
+future_response!(
+    /// Wrapper type for our boxed future
+    FutureLanguagesResponse, Vec<String>);
+
+future_response!(
+    /// Wrapper type for our boxed future
+    FutureLanguageNamesResponse, Vec<String>);
+
+impl From<GetLanguagesForTranslateRequest> for Request {
+    fn from(_: GetLanguagesForTranslateRequest) -> Self {
+        Request::new(Method::Get,
+                     Uri::from_str(GET_LANGUAGES_FOR_TRANSLATE_URI).unwrap())
+    }
+}
+
+impl Cog for GetLanguagesForTranslateRequest {
+    type Output = FutureLanguagesResponse;
+    type Item = Vec<String>;
+    type Error = Error;
+}
+
+impl From<GetLanguagesForSpeakRequest> for Request {
+    fn from(_: GetLanguagesForSpeakRequest) -> Self {
+        Request::new(Method::Get,
+                     Uri::from_str(GET_LANGUAGES_FOR_SPEAK_URI).unwrap())
+    }
+}
+
+impl Cog for GetLanguagesForSpeakRequest {
+    type Output = FutureLanguagesResponse;
+    type Item = Vec<String>;
+    type Error = Error;
+}
+
+impl From<Result<Response, engine::Error>> for FutureLanguagesResponse {
+    fn from(result: Result<Response, engine::Error>) -> Self {
+        let f = read_xml(result).map(|root| read_string_array(&root));
+        FutureLanguagesResponse(Box::new(f))
+    }
+}
+
+impl<'a> From<GetLanguageNamesRequest<'a>> for Request {
+    fn from(g: GetLanguageNamesRequest<'a>) -> Self {
+        let url = Url::parse_with_params(GET_LANGUAGE_NAMES_URI, &[("locale", g.locale.v2_code())])
+            .unwrap();
+        let codes: Vec<&str> = g.language_codes.iter().map(|c| c.v2_code()).collect();
+        xml_post(url.as_str(), &string_array(&codes))
+    }
+}
+
+impl<'a> Cog for GetLanguageNamesRequest<'a> {
+    type Output = FutureLanguageNamesResponse;
+    type Item = Vec<String>;
+    type Error = Error;
+}
+
+impl From<Result<Response, engine::Error>> for FutureLanguageNamesResponse {
+    fn from(result: Result<Response, engine::Error>) -> Self {
+        let f = read_xml(result).map(|root| read_string_array(&root));
+        FutureLanguageNamesResponse(Box::new(f))
+    }
+}
+
+/// The languages supported by the service, with their names localized into a locale
+#[derive(Debug, Clone)]
+pub struct LanguageCatalog {
+    locale: String,
+    translate: Vec<String>,
+    speak: Vec<String>,
+    names: HashMap<String, String>,
+    fetched_at: Tm,
+}
+
+impl LanguageCatalog {
+    /// Returns a Flow that fetches a catalog with language names localized into the given locale
+    pub fn fetch(locale: &LanguageTag) -> FetchLanguageCatalog {
+        FetchLanguageCatalog { locale: locale.clone() }
+    }
+
+    /// Returns a new catalog from its parts. Names are given in the same order as
+    /// the translation language codes.
+    pub fn new(locale: String,
+               translate: Vec<String>,
+               speak: Vec<String>,
+               names: Vec<String>)
+               -> LanguageCatalog {
+        let names = translate.iter().cloned().zip(names).collect();
+        LanguageCatalog::with_names(locale, translate, speak, names)
+    }
+
+    fn with_names(locale: String,
+                  translate: Vec<String>,
+                  speak: Vec<String>,
+                  names: HashMap<String, String>)
+                  -> LanguageCatalog {
+        LanguageCatalog {
+            locale: locale,
+            translate: translate,
+            speak: speak,
+            names: names,
+            fetched_at: time::now(),
+        }
+    }
+
+    /// Locale the language names are in
+    pub fn locale(&self) -> &str {
+        &self.locale
+    }
+
+    /// Codes of the languages that can be translated
+    pub fn translation_languages(&self) -> &[String] {
+        &self.translate
+    }
+
+    /// Codes of the languages that can be spoken
+    pub fn speech_languages(&self) -> &[String] {
+        &self.speak
+    }
+
+    /// Whether or not the given language code can be translated from or to
+    pub fn supports_translation(&self, code: &str) -> bool {
+        self.translate.iter().any(|c| c == code)
+    }
+
+    /// Whether or not the given language code can be spoken
+    pub fn supports_speech(&self, code: &str) -> bool {
+        self.speak.iter().any(|c| c == code)
+    }
+
+    /// Returns the localized name of a language
+    pub fn name(&self, code: &str) -> Option<&str> {
+        self.names.get(code).map(|n| n.as_str())
+    }
+
+    /// Returns (code, name) pairs for all the translation languages, sorted by name
+    pub fn named_languages(&self) -> Vec<(&str, &str)> {
+        let mut languages: Vec<(&str, &str)> = self.translate
+            .iter()
+            .map(|c| (c.as_str(), self.name(c).unwrap_or(c.as_str())))
+            .collect();
+        languages.sort_by(|a, b| a.1.cmp(b.1));
+        languages
+    }
+
+    /// Whether or not the catalog was fetched longer than max_age ago
+    pub fn is_older_than(&self, max_age: Duration) -> bool {
+        time::now() - self.fetched_at > max_age
+    }
+}
+
+/// Flow for `LanguageCatalog::fetch`
+pub struct FetchLanguageCatalog {
+    locale: LanguageTag,
+}
+
+impl Flow for FetchLanguageCatalog {
+    type Item = LanguageCatalog;
+
+    fn start(self, dispatcher: Dispatcher) -> Box<Future<Item = Self::Item, Error = flow::Error>> {
+        let locale = self.locale;
+        let work = flow::cog(GetLanguagesForTranslateRequest)
+            .zip(flow::cog(GetLanguagesForSpeakRequest))
+            .then_cog(move |(translate, speak): (Vec<String>, Vec<String>)| {
+                // Codes that are not valid language tags are left without a name
+                let (named, tags): (Vec<String>, Vec<LanguageTag>) = translate
+                    .iter()
+                    .filter_map(|c| LanguageTag::parse(c).ok().map(|t| (c.clone(), t)))
+                    .unzip();
+                let names = flow::cog(GetLanguageNamesRequest {
+                                          locale: &locale,
+                                          language_codes: &tags,
+                                      });
+                names.map_item(move |names| {
+                    let names = named.into_iter().zip(names).collect();
+                    LanguageCatalog::with_names(locale.to_string(), translate, speak, names)
+                })
+            });
+        work.start(dispatcher)
+    }
+}
+
+/// A LanguageCatalog that is fetched when first needed, and fetched again once it gets too old
+#[derive(Clone)]
+pub struct CachedLanguageCatalog {
+    locale: LanguageTag,
+    max_age: Duration,
+    catalog: Rc<RefCell<Option<LanguageCatalog>>>,
+}
+
+impl CachedLanguageCatalog {
+    /// Returns a new, empty cache for catalogs in the given locale
+    pub fn new(locale: &LanguageTag, max_age: Duration) -> CachedLanguageCatalog {
+        CachedLanguageCatalog {
+            locale: locale.clone(),
+            max_age: max_age,
+            catalog: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    /// Returns the cached catalog, fetching it first if needed
+    pub fn get<Connector>(&self,
+                          engine: &Engine<Connector>)
+                          -> Box<Future<Item = LanguageCatalog, Error = flow::Error>>
+        where Connector: Connect + Service
+    {
+        if let Some(ref catalog) = *self.catalog.borrow() {
+            if !catalog.is_older_than(self.max_age) {
+                return Box::new(future::ok(catalog.clone()));
+            }
+        }
+        let cache = self.catalog.clone();
+        Box::new(engine
+                     .run_flow(LanguageCatalog::fetch(&self.locale))
+                     .map(move |catalog| {
+                              *cache.borrow_mut() = Some(catalog.clone());
+                              catalog
+                          }))
+    }
+}
+
+/// A request for speaking a text out loud, returning the audio
+pub struct SpeakRequest<'a> {
+    pub text: &'a str,
+    pub language: &'a LanguageTag,
+    pub format: Option<AudioFormat>,
+    pub quality: Option<AudioQuality>,
+    pub gender: Option<VoiceGender>,
+}
+
+impl<'a> SpeakRequest<'a> {
+    /// Turns this into a request whose audio is streamed instead of buffered
+    pub fn streaming(self) -> SpeakStreamRequest<'a> {
+        SpeakStreamRequest(self)
+    }
+}
+
+/// A SpeakRequest whose audio is streamed chunk by chunk as it arrives
+pub struct SpeakStreamRequest<'a>(pub SpeakRequest<'a>);
+
+/// Possible audio formats for speech
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub enum AudioFormat {
+    Wav,
+    Mp3,
+}
+
+/// Possible audio qualities for speech
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub enum AudioQuality {
+    MinSize,
+    MaxQuality,
+}
+
+/// Possible voice genders for speech
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub enum VoiceGender {
+    Female,
+    Male,
+}
+
+future_response!(
+    /// Wrapper type for our boxed future
+    FutureSpeakResponse, Vec<u8>);
+
+future_response!(
+    /// Wrapper type for our boxed future
+    FutureSpeakStreamResponse, AudioStream);
+
+/// Stream of audio chunks
+pub struct AudioStream(Box<Stream<Item = Chunk, Error = Error>>);
+
+impl fmt::Debug for AudioStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("AudioStream")
+    }
+}
+
+impl Stream for AudioStream {
+    type Item = Chunk;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.0.poll()
+    }
+}
+
+impl<'a> From<SpeakRequest<'a>> for Request {
+    fn from(s: SpeakRequest<'a>) -> Self {
+        let mut url = Url::parse_with_params(SPEAK_URI, &[("text", s.text), ("language", s.language.v2_code())])
+            .unwrap();
+        {
+            let mut mut_pairs = url.query_pairs_mut();
+            match s.format {
+                Some(AudioFormat::Wav) => {
+                    mut_pairs.append_pair("format", "audio/wav");
+                }
+                Some(AudioFormat::Mp3) => {
+                    mut_pairs.append_pair("format", "audio/mp3");
+                }
+                _ => (),
+            }
+            let quality = s.quality.map(|q| match q {
+                                             AudioQuality::MinSize => "MinSize",
+                                             AudioQuality::MaxQuality => "MaxQuality",
+                                         });
+            let gender = s.gender.map(|g| match g {
+                                           VoiceGender::Female => "female",
+                                           VoiceGender::Male => "male",
+                                       });
+            let options: Vec<&str> = quality.into_iter().chain(gender).collect();
+            if !options.is_empty() {
+                mut_pairs.append_pair("options", &options.join("|"));
+            }
+            mut_pairs.finish();
+        }
+        let as_uri = Uri::from_str(url.as_str()).unwrap();
+        Request::new(Method::Get, as_uri)
+    }
+}
+
+impl<'a> Cog for SpeakRequest<'a> {
+    type Output = FutureSpeakResponse;
+    type Item = Vec<u8>;
+    type Error = Error;
+}
+
+impl From<Result<Response, engine::Error>> for FutureSpeakResponse {
+    fn from(result: Result<Response, engine::Error>) -> Self {
+        let f = checked_response(result).and_then(|resp| {
+                                                      engine::read_to_bytes(resp)
+                                                          .map_err(|e| Error::EngineError(e))
+                                                  });
+        FutureSpeakResponse(Box::new(f))
+    }
+}
+
+impl<'a> From<SpeakStreamRequest<'a>> for Request {
+    fn from(s: SpeakStreamRequest<'a>) -> Self {
+        s.0.into()
+    }
+}
+
+impl<'a> Cog for SpeakStreamRequest<'a> {
+    type Output = FutureSpeakStreamResponse;
+    type Item = AudioStream;
+    type Error = Error;
+}
+
+impl From<Result<Response, engine::Error>> for FutureSpeakStreamResponse {
+    fn from(result: Result<Response, engine::Error>) -> Self {
+        let f = checked_response(result).map(|resp| {
+                                                 AudioStream(Box::new(engine::stream_body(resp)
+                                                                          .map_err(|e| Error::EngineError(e))))
+                                             });
+        FutureSpeakStreamResponse(Box::new(f))
+    }
+}
+
+/// A request for submitting a corrected translation of a text.
+///
+/// Submissions are tied to a user and optionally a category. Use `validate`
+/// before running it to catch submissions the service would reject.
+pub struct AddTranslationRequest<'a> {
+    pub original_text: &'a str,
+    pub translated_text: &'a str,
+    pub from: &'a LanguageTag,
+    pub to: &'a LanguageTag,
+    /// From -10 to 10. Ratings of 5 and above make the translation available right away
+    pub rating: Option<i32>,
+    pub content_type: Option<TranslateContentType>,
+    pub category: Option<&'a CategoryId>,
+    pub user: &'a str,
+    pub uri: Option<&'a str>,
+}
+
+impl<'a> AddTranslationRequest<'a> {
+    /// Checks the submission against the limits of the service
+    pub fn validate(&self) -> Result<(), SubmissionError> {
+        validate_submission(self.user,
+                            &AddedTranslation {
+                                 original_text: self.original_text,
+                                 translated_text: self.translated_text,
+                                 rating: self.rating,
+                                 sequence: None,
+                             })
+    }
+}
+
+/// A request for submitting many corrected translations at once
+pub struct AddTranslationArrayRequest<'a> {
+    pub translations: &'a [AddedTranslation<'a>],
+    pub from: &'a LanguageTag,
+    pub to: &'a LanguageTag,
+    pub content_type: Option<TranslateContentType>,
+    pub category: Option<&'a CategoryId>,
+    pub user: &'a str,
+    pub uri: Option<&'a str>,
+}
+
+impl<'a> AddTranslationArrayRequest<'a> {
+    /// Checks the submissions against the limits of the service
+    pub fn validate(&self) -> Result<(), SubmissionError> {
+        if self.translations.len() > MAX_ADD_TRANSLATIONS {
+            return Err(SubmissionError::TooManyTranslations(self.translations.len()));
+        }
+        for t in self.translations {
+            validate_submission(self.user, t)?;
+        }
+        Ok(())
+    }
+}
+
+/// A single corrected translation, for use with AddTranslationArrayRequest
+pub struct AddedTranslation<'a> {
+    pub original_text: &'a str,
+    pub translated_text: &'a str,
+    pub rating: Option<i32>,
+    pub sequence: Option<i32>,
+}
+
+/// Reasons for a submitted translation to be rejected
+#[derive(Debug, PartialEq)]
+pub enum SubmissionError {
+    MissingUser,
+    RatingOutOfRange(i32),
+    OriginalTextTooLong(usize),
+    TranslatedTextTooLong(usize),
+    TooManyTranslations(usize),
+    /// Rejected by the service
+    Rejected(StatusCode, String),
+}
+
+future_response!(
+    /// Wrapper type for our boxed future
+    FutureAddTranslationResponse, ());
+
+impl<'a> From<AddTranslationRequest<'a>> for Request {
+    fn from(a: AddTranslationRequest<'a>) -> Self {
+        let mut url = Url::parse_with_params(ADD_TRANSLATION_URI,
+                                             &[("originalText", a.original_text),
+                                               ("translatedText", a.translated_text),
+                                               ("from", a.from.v2_code()),
+                                               ("to", a.to.v2_code()),
+                                               ("user", a.user)])
+                .unwrap();
+        {
+            let mut mut_pairs = url.query_pairs_mut();
+            if let Some(rating) = a.rating {
+                mut_pairs.append_pair("rating", &rating.to_string());
+            }
+            match a.content_type {
+                Some(TranslateContentType::Html) => {
+                    mut_pairs.append_pair("contentType", "text/html");
+                }
+                Some(TranslateContentType::Plain) => {
+                    mut_pairs.append_pair("contentType", "text/plain");
+                }
+                _ => (),
+            }
+            if let Some(cat) = a.category {
+                mut_pairs.append_pair("category", cat.as_str());
+            }
+            if let Some(uri) = a.uri {
+                mut_pairs.append_pair("uri", uri);
+            }
+            mut_pairs.finish();
+        }
+        let as_uri = Uri::from_str(url.as_str()).unwrap();
+        Request::new(Method::Get, as_uri)
+    }
+}
+
+impl<'a> Cog for AddTranslationRequest<'a> {
+    type Output = FutureAddTranslationResponse;
+    type Item = ();
+    type Error = Error;
+}
+
+impl<'a> From<AddTranslationArrayRequest<'a>> for Request {
+    fn from(a: AddTranslationArrayRequest<'a>) -> Self {
+        let mut root = Element::new("AddtranslationsRequest");
+        root.append_new_child("AppId");
+        root.append_new_child("From").set_text(a.from.v2_code());
+        {
+            let options = root.append_new_child("Options");
+            if let Some(cat) = a.category {
+                options.append_new_child((V2_NS, "Category")).set_text(cat.as_str());
+            }
+            match a.content_type {
+                Some(TranslateContentType::Html) => {
+                    options
+                        .append_new_child((V2_NS, "ContentType"))
+                        .set_text("text/html");
+                }
+                Some(TranslateContentType::Plain) => {
+                    options
+                        .append_new_child((V2_NS, "ContentType"))
+                        .set_text("text/plain");
+                }
+                _ => (),
+            }
+            if let Some(uri) = a.uri {
+                options.append_new_child((V2_NS, "Uri")).set_text(uri);
+            }
+            options.append_new_child((V2_NS, "User")).set_text(a.user);
+        }
+        root.append_new_child("To").set_text(a.to.v2_code());
+        {
+            let translations = root.append_new_child("Translations");
+            for t in a.translations {
+                let translation = translations.append_new_child((V2_NS, "Translation"));
+                translation
+                    .append_new_child((V2_NS, "OriginalText"))
+                    .set_text(t.original_text);
+                if let Some(rating) = t.rating {
+                    translation
+                        .append_new_child((V2_NS, "Rating"))
+                        .set_text(rating.to_string());
+                }
+                if let Some(sequence) = t.sequence {
+                    translation
+                        .append_new_child((V2_NS, "Sequence"))
+                        .set_text(sequence.to_string());
+                }
+                translation
+                    .append_new_child((V2_NS, "TranslatedText"))
+                    .set_text(t.translated_text);
+            }
+        }
+        xml_post(ADD_TRANSLATION_ARRAY_URI, &root)
+    }
+}
+
+impl<'a> Cog for AddTranslationArrayRequest<'a> {
+    type Output = FutureAddTranslationResponse;
+    type Item = ();
+    type Error = Error;
+}
+
+impl From<Result<Response, engine::Error>> for FutureAddTranslationResponse {
+    fn from(result: Result<Response, engine::Error>) -> Self {
+        let f = checked_response(result)
+            .map(|_| ())
+            .map_err(|e| match e {
+                         Error::ServiceError(status, message) => {
+                             Error::SubmissionError(SubmissionError::Rejected(status, message))
+                         }
+                         other => other,
+                     });
+        FutureAddTranslationResponse(Box::new(f))
+    }
+}
+
+/// Owned variant of TranslateRequest, which can be stored, sent across threads and serialized
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct OwnedTranslateRequest {
+    pub text: String,
+    pub from: Option<LanguageTag>,
+    pub to: LanguageTag,
+    pub content_type: Option<TranslateContentType>,
+    pub category: Option<CategoryId>,
+    pub profanity_action: Option<ProfanityAction>,
+}
+
+impl OwnedTranslateRequest {
+    /// Borrows this as a TranslateRequest
+    pub fn as_request<'a>(&'a self) -> TranslateRequest<'a> {
+        TranslateRequest {
+            text: &self.text,
+            from: self.from.as_ref(),
+            to: &self.to,
+            content_type: self.content_type,
+            category: self.category.as_ref(),
+            profanity_action: self.profanity_action,
+        }
+    }
+}
+
+impl<'a> From<TranslateRequest<'a>> for OwnedTranslateRequest {
+    fn from(t: TranslateRequest<'a>) -> Self {
+        OwnedTranslateRequest {
+            text: t.text.to_string(),
+            from: t.from.cloned(),
+            to: t.to.clone(),
+            content_type: t.content_type,
+            category: t.category.cloned(),
+            profanity_action: t.profanity_action,
+        }
+    }
+}
+
+impl From<OwnedTranslateRequest> for Request {
+    fn from(t: OwnedTranslateRequest) -> Self {
+        t.as_request().into()
+    }
+}
+
+owned_cog!(OwnedTranslateRequest, TranslateRequest);
+
+/// Owned variant of TranslateArrayRequest
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct OwnedTranslateArrayRequest {
+    pub texts: Vec<String>,
+    pub from: Option<LanguageTag>,
+    pub to: LanguageTag,
+    pub content_type: Option<TranslateContentType>,
+    pub category: Option<CategoryId>,
+    pub state: Option<String>,
+    pub profanity_action: Option<ProfanityAction>,
+}
+
+impl<'a> From<TranslateArrayRequest<'a>> for OwnedTranslateArrayRequest {
+    fn from(t: TranslateArrayRequest<'a>) -> Self {
+        OwnedTranslateArrayRequest {
+            texts: owned_strings(t.texts),
+            from: t.from.cloned(),
+            to: t.to.clone(),
+            content_type: t.content_type,
+            category: t.category.cloned(),
+            state: t.state.map(|s| s.to_string()),
+            profanity_action: t.profanity_action,
+        }
+    }
+}
+
+impl From<OwnedTranslateArrayRequest> for Request {
+    fn from(t: OwnedTranslateArrayRequest) -> Self {
+        let texts = str_refs(&t.texts);
+        TranslateArrayRequest {
+                texts: &texts,
+                from: t.from.as_ref(),
+                to: &t.to,
+                content_type: t.content_type,
+                category: t.category.as_ref(),
+                state: t.state.as_ref().map(|s| s.as_str()),
+                profanity_action: t.profanity_action,
+            }
+            .into()
+    }
+}
+
+owned_cog!(OwnedTranslateArrayRequest, TranslateArrayRequest);
+
+/// Owned variant of DetectRequest
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct OwnedDetectRequest {
+    pub text: String,
+}
+
+impl<'a> From<DetectRequest<'a>> for OwnedDetectRequest {
+    fn from(d: DetectRequest<'a>) -> Self {
+        OwnedDetectRequest { text: d.text.to_string() }
+    }
+}
+
+impl From<OwnedDetectRequest> for Request {
+    fn from(d: OwnedDetectRequest) -> Self {
+        DetectRequest { text: &d.text }.into()
+    }
+}
+
+owned_cog!(OwnedDetectRequest, DetectRequest);
+
+/// Owned variant of DetectArrayRequest
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct OwnedDetectArrayRequest {
+    pub texts: Vec<String>,
+}
+
+impl<'a> From<DetectArrayRequest<'a>> for OwnedDetectArrayRequest {
+    fn from(d: DetectArrayRequest<'a>) -> Self {
+        OwnedDetectArrayRequest { texts: owned_strings(d.texts) }
+    }
+}
+
+impl From<OwnedDetectArrayRequest> for Request {
+    fn from(d: OwnedDetectArrayRequest) -> Self {
+        DetectArrayRequest { texts: &str_refs(&d.texts) }.into()
+    }
+}
+
+owned_cog!(OwnedDetectArrayRequest, DetectArrayRequest);
+
+/// Owned variant of BreakSentencesRequest
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct OwnedBreakSentencesRequest {
+    pub text: String,
+    pub language: LanguageTag,
+}
+
+impl<'a> From<BreakSentencesRequest<'a>> for OwnedBreakSentencesRequest {
+    fn from(b: BreakSentencesRequest<'a>) -> Self {
+        OwnedBreakSentencesRequest {
+            text: b.text.to_string(),
+            language: b.language.clone(),
+        }
+    }
+}
 
-impl fmt::Debug for FutureTranslateResponse {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.pad("FutureTranslateResponse")
+impl From<OwnedBreakSentencesRequest> for Request {
+    fn from(b: OwnedBreakSentencesRequest) -> Self {
+        BreakSentencesRequest {
+                text: &b.text,
+                language: &b.language,
+            }
+            .into()
     }
 }
 
-impl Future for FutureTranslateResponse {
-    type Item = String;
-    type Error = Error;
+owned_cog!(OwnedBreakSentencesRequest, BreakSentencesRequest);
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        self.0.poll()
-    }
+/// Owned variant of GetTranslationsRequest
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct OwnedGetTranslationsRequest {
+    pub text: String,
+    pub from: LanguageTag,
+    pub to: LanguageTag,
+    pub max_translations: usize,
+    pub content_type: Option<TranslateContentType>,
+    pub category: Option<CategoryId>,
+    pub state: Option<String>,
+    pub profanity_action: Option<ProfanityAction>,
 }
 
-const TRANSLATE_BASE_URI: &'static str = "https://api.microsofttranslator.com/v2/http.svc/Translate";
+impl<'a> From<GetTranslationsRequest<'a>> for OwnedGetTranslationsRequest {
+    fn from(g: GetTranslationsRequest<'a>) -> Self {
+        OwnedGetTranslationsRequest {
+            text: g.text.to_string(),
+            from: g.from.clone(),
+            to: g.to.clone(),
+            max_translations: g.max_translations,
+            content_type: g.content_type,
+            category: g.category.cloned(),
+            state: g.state.map(|s| s.to_string()),
+            profanity_action: g.profanity_action,
+        }
+    }
+}
 
-impl<'a> From<TranslateRequest<'a>> for Request {
-    fn from(t: TranslateRequest<'a>) -> Self {
-        let mut url = Url::parse_with_params(TRANSLATE_BASE_URI, &[("to", t.to), ("text", t.text)])
-            .unwrap();
-        {
-            let mut mut_pairs = url.query_pairs_mut();
-            match t.from {
-                Some(from) => {
-                    mut_pairs.append_pair("from", from);
-                }
-                _ => (),
+impl From<OwnedGetTranslationsRequest> for Request {
+    fn from(g: OwnedGetTranslationsRequest) -> Self {
+        GetTranslationsRequest {
+                text: &g.text,
+                from: &g.from,
+                to: &g.to,
+                max_translations: g.max_translations,
+                content_type: g.content_type,
+                category: g.category.as_ref(),
+                state: g.state.as_ref().map(|s| s.as_str()),
+                profanity_action: g.profanity_action,
             }
-            match t.content_type {
-                Some(TranslateContentType::Html) => {
-                    mut_pairs.append_pair("contentType", "text/html");
-                }
-                Some(TranslateContentType::Plain) => {
-                    mut_pairs.append_pair("contentType", "text/plain");
-                }
-                _ => (),
+            .into()
+    }
+}
+
+owned_cog!(OwnedGetTranslationsRequest, GetTranslationsRequest);
+
+/// Owned variant of GetTranslationsArrayRequest
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct OwnedGetTranslationsArrayRequest {
+    pub texts: Vec<String>,
+    pub from: LanguageTag,
+    pub to: LanguageTag,
+    pub max_translations: usize,
+    pub content_type: Option<TranslateContentType>,
+    pub category: Option<CategoryId>,
+    pub state: Option<String>,
+    pub profanity_action: Option<ProfanityAction>,
+}
+
+impl<'a> From<GetTranslationsArrayRequest<'a>> for OwnedGetTranslationsArrayRequest {
+    fn from(g: GetTranslationsArrayRequest<'a>) -> Self {
+        OwnedGetTranslationsArrayRequest {
+            texts: owned_strings(g.texts),
+            from: g.from.clone(),
+            to: g.to.clone(),
+            max_translations: g.max_translations,
+            content_type: g.content_type,
+            category: g.category.cloned(),
+            state: g.state.map(|s| s.to_string()),
+            profanity_action: g.profanity_action,
+        }
+    }
+}
+
+impl From<OwnedGetTranslationsArrayRequest> for Request {
+    fn from(g: OwnedGetTranslationsArrayRequest) -> Self {
+        let texts = str_refs(&g.texts);
+        GetTranslationsArrayRequest {
+                texts: &texts,
+                from: &g.from,
+                to: &g.to,
+                max_translations: g.max_translations,
+                content_type: g.content_type,
+                category: g.category.as_ref(),
+                state: g.state.as_ref().map(|s| s.as_str()),
+                profanity_action: g.profanity_action,
             }
-            match t.category {
-                Some(cat) => {
-                    mut_pairs.append_pair("category", cat);
-                }
-                _ => (),
+            .into()
+    }
+}
+
+owned_cog!(OwnedGetTranslationsArrayRequest, GetTranslationsArrayRequest);
+
+/// Owned variant of GetLanguageNamesRequest
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct OwnedGetLanguageNamesRequest {
+    pub locale: LanguageTag,
+    pub language_codes: Vec<LanguageTag>,
+}
+
+impl<'a> From<GetLanguageNamesRequest<'a>> for OwnedGetLanguageNamesRequest {
+    fn from(g: GetLanguageNamesRequest<'a>) -> Self {
+        OwnedGetLanguageNamesRequest {
+            locale: g.locale.clone(),
+            language_codes: g.language_codes.to_vec(),
+        }
+    }
+}
+
+impl From<OwnedGetLanguageNamesRequest> for Request {
+    fn from(g: OwnedGetLanguageNamesRequest) -> Self {
+        GetLanguageNamesRequest {
+                locale: &g.locale,
+                language_codes: &g.language_codes,
             }
-            mut_pairs.finish();
+            .into()
+    }
+}
+
+owned_cog!(OwnedGetLanguageNamesRequest, GetLanguageNamesRequest);
+
+/// Owned variant of SpeakRequest
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct OwnedSpeakRequest {
+    pub text: String,
+    pub language: LanguageTag,
+    pub format: Option<AudioFormat>,
+    pub quality: Option<AudioQuality>,
+    pub gender: Option<VoiceGender>,
+}
+
+impl OwnedSpeakRequest {
+    /// Borrows this as a SpeakRequest
+    pub fn as_request<'a>(&'a self) -> SpeakRequest<'a> {
+        SpeakRequest {
+            text: &self.text,
+            language: &self.language,
+            format: self.format,
+            quality: self.quality,
+            gender: self.gender,
         }
-        let as_uri = Uri::from_str(url.as_str()).unwrap();
-        Request::new(Method::Get, as_uri)
+    }
+
+    /// Turns this into a request whose audio is streamed instead of buffered
+    pub fn streaming(self) -> OwnedSpeakStreamRequest {
+        OwnedSpeakStreamRequest(self)
     }
 }
 
-impl<'a> Cog for TranslateRequest<'a> {
-    type Output = FutureTranslateResponse;
-    type Item = String;
-    type Error = Error;
+/// Owned variant of SpeakStreamRequest
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct OwnedSpeakStreamRequest(pub OwnedSpeakRequest);
+
+impl<'a> From<SpeakRequest<'a>> for OwnedSpeakRequest {
+    fn from(s: SpeakRequest<'a>) -> Self {
+        OwnedSpeakRequest {
+            text: s.text.to_string(),
+            language: s.language.clone(),
+            format: s.format,
+            quality: s.quality,
+            gender: s.gender,
+        }
+    }
 }
 
-impl From<Result<Response, engine::Error>> for FutureTranslateResponse {
-    fn from(result: Result<Response, engine::Error>) -> Self {
-        match result {
-            Ok(resp) => {
-                let body_bytes = engine::read_to_bytes(resp);
-                let f = body_bytes
-                    .map_err(|e| Error::EngineError(e))
-                    .and_then(|b| match Element::from_reader(b.as_slice()) {
-                                  Ok(root) => Ok(root.text().to_string()),
-                                  _ => Err(Error::XMLParsingError),
-                              });
+impl From<OwnedSpeakRequest> for Request {
+    fn from(s: OwnedSpeakRequest) -> Self {
+        s.as_request().into()
+    }
+}
+
+owned_cog!(OwnedSpeakRequest, SpeakRequest);
+
+impl<'a> From<SpeakStreamRequest<'a>> for OwnedSpeakStreamRequest {
+    fn from(s: SpeakStreamRequest<'a>) -> Self {
+        OwnedSpeakStreamRequest(s.0.into())
+    }
+}
+
+impl From<OwnedSpeakStreamRequest> for Request {
+    fn from(s: OwnedSpeakStreamRequest) -> Self {
+        s.0.into()
+    }
+}
+
+owned_cog!(OwnedSpeakStreamRequest, SpeakStreamRequest);
+
+/// Owned variant of AddTranslationRequest
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct OwnedAddTranslationRequest {
+    pub original_text: String,
+    pub translated_text: String,
+    pub from: LanguageTag,
+    pub to: LanguageTag,
+    pub rating: Option<i32>,
+    pub content_type: Option<TranslateContentType>,
+    pub category: Option<CategoryId>,
+    pub user: String,
+    pub uri: Option<String>,
+}
+
+impl OwnedAddTranslationRequest {
+    /// Borrows this as an AddTranslationRequest
+    pub fn as_request<'a>(&'a self) -> AddTranslationRequest<'a> {
+        AddTranslationRequest {
+            original_text: &self.original_text,
+            translated_text: &self.translated_text,
+            from: &self.from,
+            to: &self.to,
+            rating: self.rating,
+            content_type: self.content_type,
+            category: self.category.as_ref(),
+            user: &self.user,
+            uri: self.uri.as_ref().map(|s| s.as_str()),
+        }
+    }
+
+    /// Checks the submission against the limits of the service
+    pub fn validate(&self) -> Result<(), SubmissionError> {
+        self.as_request().validate()
+    }
+}
+
+impl<'a> From<AddTranslationRequest<'a>> for OwnedAddTranslationRequest {
+    fn from(a: AddTranslationRequest<'a>) -> Self {
+        OwnedAddTranslationRequest {
+            original_text: a.original_text.to_string(),
+            translated_text: a.translated_text.to_string(),
+            from: a.from.clone(),
+            to: a.to.clone(),
+            rating: a.rating,
+            content_type: a.content_type,
+            category: a.category.cloned(),
+            user: a.user.to_string(),
+            uri: a.uri.map(|s| s.to_string()),
+        }
+    }
+}
+
+impl From<OwnedAddTranslationRequest> for Request {
+    fn from(a: OwnedAddTranslationRequest) -> Self {
+        a.as_request().into()
+    }
+}
+
+owned_cog!(OwnedAddTranslationRequest, AddTranslationRequest);
+
+/// Owned variant of AddTranslationArrayRequest
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct OwnedAddTranslationArrayRequest {
+    pub translations: Vec<OwnedAddedTranslation>,
+    pub from: LanguageTag,
+    pub to: LanguageTag,
+    pub content_type: Option<TranslateContentType>,
+    pub category: Option<CategoryId>,
+    pub user: String,
+    pub uri: Option<String>,
+}
+
+/// Owned variant of AddedTranslation
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct OwnedAddedTranslation {
+    pub original_text: String,
+    pub translated_text: String,
+    pub rating: Option<i32>,
+    pub sequence: Option<i32>,
+}
+
+impl OwnedAddedTranslation {
+    /// Borrows this as an AddedTranslation
+    pub fn as_added<'a>(&'a self) -> AddedTranslation<'a> {
+        AddedTranslation {
+            original_text: &self.original_text,
+            translated_text: &self.translated_text,
+            rating: self.rating,
+            sequence: self.sequence,
+        }
+    }
+}
+
+impl OwnedAddTranslationArrayRequest {
+    /// Checks the submissions against the limits of the service
+    pub fn validate(&self) -> Result<(), SubmissionError> {
+        let translations: Vec<AddedTranslation> = self.translations.iter().map(|t| t.as_added()).collect();
+        self.with_request(&translations).validate()
+    }
+
+    fn with_request<'a>(&'a self, translations: &'a [AddedTranslation<'a>]) -> AddTranslationArrayRequest<'a> {
+        AddTranslationArrayRequest {
+            translations: translations,
+            from: &self.from,
+            to: &self.to,
+            content_type: self.content_type,
+            category: self.category.as_ref(),
+            user: &self.user,
+            uri: self.uri.as_ref().map(|s| s.as_str()),
+        }
+    }
+}
+
+impl<'a> From<AddTranslationArrayRequest<'a>> for OwnedAddTranslationArrayRequest {
+    fn from(a: AddTranslationArrayRequest<'a>) -> Self {
+        OwnedAddTranslationArrayRequest {
+            translations: a.translations
+                .iter()
+                .map(|t| {
+                         OwnedAddedTranslation {
+                             original_text: t.original_text.to_string(),
+                             translated_text: t.translated_text.to_string(),
+                             rating: t.rating,
+                             sequence: t.sequence,
+                         }
+                     })
+                .collect(),
+            from: a.from.clone(),
+            to: a.to.clone(),
+            content_type: a.content_type,
+            category: a.category.cloned(),
+            user: a.user.to_string(),
+            uri: a.uri.map(|s| s.to_string()),
+        }
+    }
+}
+
+impl From<OwnedAddTranslationArrayRequest> for Request {
+    fn from(a: OwnedAddTranslationArrayRequest) -> Self {
+        let translations: Vec<AddedTranslation> = a.translations.iter().map(|t| t.as_added()).collect();
+        a.with_request(&translations).into()
+    }
+}
+
+owned_cog!(OwnedAddTranslationArrayRequest, AddTranslationArrayRequest);
+
+fn validate_submission(user: &str, t: &AddedTranslation) -> Result<(), SubmissionError> {
+    if user.is_empty() {
+        return Err(SubmissionError::MissingUser);
+    }
+    match t.rating {
+        Some(r) if r < -10 || r > 10 => return Err(SubmissionError::RatingOutOfRange(r)),
+        _ => (),
+    }
+    let original_chars = t.original_text.chars().count();
+    if original_chars > MAX_ADD_ORIGINAL_CHARS {
+        return Err(SubmissionError::OriginalTextTooLong(original_chars));
+    }
+    let translated_chars = t.translated_text.chars().count();
+    if translated_chars > MAX_ADD_TRANSLATED_CHARS {
+        return Err(SubmissionError::TranslatedTextTooLong(translated_chars));
+    }
+    Ok(())
+}
+
+/// Builds an ArrayOfstring element holding the given strings
+fn string_array(strings: &[&str]) -> Element {
+    let mut root = Element::new((ARRAYS_NS, "ArrayOfstring"));
+    for s in strings {
+        root.append_new_child((ARRAYS_NS, "string")).set_text(*s);
+    }
+    root
+}
+
+/// Reads the strings held by an ArrayOfstring element
+fn read_string_array(root: &Element) -> Vec<String> {
+    root.find_all((ARRAYS_NS, "string"))
+        .map(|s| s.text().to_string())
+        .collect()
+}
+
+/// Appends the translation options common to the v2 POST methods, in the order the service expects
+fn append_translate_options(options: &mut Element,
+                            category: Option<&CategoryId>,
+                            content_type: Option<TranslateContentType>,
+                            profanity_action: Option<ProfanityAction>,
+                            state: Option<&str>) {
+    if let Some(cat) = category {
+        options.append_new_child((V2_NS, "Category")).set_text(cat.as_str());
+    }
+    match content_type {
+        Some(TranslateContentType::Html) => {
+            options
+                .append_new_child((V2_NS, "ContentType"))
+                .set_text("text/html");
+        }
+        Some(TranslateContentType::Plain) => {
+            options
+                .append_new_child((V2_NS, "ContentType"))
+                .set_text("text/plain");
+        }
+        _ => (),
+    }
+    if let Some(action) = profanity_action {
+        options
+            .append_new_child((V2_NS, "ProfanityAction"))
+            .set_text(action.as_str());
+    }
+    options.append_new_child((V2_NS, "ReservedFlags"));
+    if let Some(st) = state {
+        options.append_new_child((V2_NS, "State")).set_text(st);
+    }
+}
+
+/// Builds a POST Request for the given uri with the given XML as its body
+fn xml_post(uri: &str, root: &Element) -> Request {
+    let mut body = vec![];
+    // Writing to a Vec can't fail
+    root.to_writer(&mut body).unwrap();
+    let mut req = Request::new(Method::Post, Uri::from_str(uri).unwrap());
+    {
+        let headers = req.headers_mut();
+        headers.set(ContentType::xml());
+        headers.set(ContentLength(body.len() as u64));
+    }
+    req.set_body(body);
+    req
+}
 
-                FutureTranslateResponse(Box::new(f))
+/// Checks that a Response was successful, turning its body into a ServiceError if not
+fn checked_response(result: Result<Response, engine::Error>) -> Box<Future<Item = Response, Error = Error>> {
+    match result {
+        Ok(resp) => {
+            let status = resp.status();
+            if status.is_success() {
+                Box::new(future::ok(resp))
+            } else {
+                Box::new(engine::read_to_bytes(resp)
+                             .map_err(|e| Error::EngineError(e))
+                             .and_then(move |b| {
+                    Err(Error::ServiceError(status, String::from_utf8_lossy(&b).into_owned()))
+                }))
             }
-            Err(e) => FutureTranslateResponse(future::err(Error::EngineError(e)).boxed()),
         }
+        Err(e) => Box::new(future::err(Error::EngineError(e))),
+    }
+}
+
+/// Checks that a Response was successful and parses its body as XML
+fn read_xml(result: Result<Response, engine::Error>) -> Box<Future<Item = Element, Error = Error>> {
+    Box::new(checked_response(result).and_then(|resp| {
+        engine::read_to_bytes(resp)
+            .map_err(|e| Error::EngineError(e))
+            .and_then(|b| Element::from_reader(b.as_slice()).map_err(|_| Error::XMLParsingError))
+    }))
+}
+
+/// Returns the text of the child with the given name in the V2 namespace, or ""
+fn v2_text<'a>(el: &'a Element, name: &'a str) -> &'a str {
+    el.find((V2_NS, name)).map(|c| c.text()).unwrap_or("")
+}
+
+/// Returns the text of the child with the given name in the V2 namespace, if it isn't empty
+fn v2_opt_text(el: &Element, name: &str) -> Option<String> {
+    el.find((V2_NS, name))
+        .map(|c| c.text())
+        .and_then(|t| if t.is_empty() {
+                      None
+                  } else {
+                      Some(t.to_string())
+                  })
+}
+
+/// Parses the text of the child with the given name in the V2 namespace, defaulting to 0 if missing
+fn v2_number<N>(el: &Element, name: &str) -> Result<N, Error>
+    where N: FromStr + Default
+{
+    match el.find((V2_NS, name)).map(|c| c.text().trim()) {
+        Some(t) if !t.is_empty() => t.parse().map_err(|_| Error::XMLParsingError),
+        _ => Ok(N::default()),
+    }
+}
+
+/// Reads the ints held by the child with the given name
+fn sentence_lengths(el: &Element, name: &str) -> Result<Vec<usize>, Error> {
+    match el.find((V2_NS, name)) {
+        Some(lengths) => {
+            lengths
+                .find_all((ARRAYS_NS, "int"))
+                .map(|i| i.text().parse().map_err(|_| Error::XMLParsingError))
+                .collect()
+        }
+        None => Ok(vec![]),
     }
 }
 
@@ -111,6 +1886,8 @@ impl From<Result<Response, engine::Error>> for FutureTranslateResponse {
 #[derive(Debug)]
 pub enum Error {
     XMLParsingError,
+    ServiceError(StatusCode, String),
+    SubmissionError(SubmissionError),
     EngineError(engine::Error),
 }
 
@@ -123,6 +1900,7 @@ mod tests {
     use tokio_core;
     use std::env;
     use hyper_tls;
+    use futures::Stream;
 
     fn subscription_key() -> String {
         env::var("AZURE_SUBSCRIPTION_KEY").unwrap()
@@ -130,6 +1908,8 @@ mod tests {
 
     #[test]
     fn translation_test() {
+        let en = LanguageTag::parse("en").unwrap();
+        let de = LanguageTag::parse("de").unwrap();
         let mut core = tokio_core::reactor::Core::new().unwrap();
         let handle = core.handle();
         let client = Client::configure()
@@ -140,12 +1920,459 @@ mod tests {
         let engine = Engine::new(credentials, client);
         let translate_req = TranslateRequest {
             text: "Hello",
-            from: Some("en"),
-            to: "de",
+            from: Some(&en),
+            to: &de,
             content_type: None,
             category: None,
+            profanity_action: None,
         };
         let work = engine.run(translate_req);
         assert_eq!(core.run(work).unwrap(), "") // TODO: get a sandbox key so this starts working again.
     }
+
+    fn body_string(req: Request) -> String {
+        let body = req.body().concat2().wait().unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    fn response(body: &str) -> Result<Response, engine::Error> {
+        Ok(Response::new().with_body(body.to_string()))
+    }
+
+    #[test]
+    fn translate_array_split_test() {
+        let de = LanguageTag::parse("de").unwrap();
+        let long_text: String = (0..MAX_ARRAY_CHARS - 10).map(|_| 'a').collect();
+        let many_texts: Vec<&str> = (0..MAX_ARRAY_TEXTS + 1).map(|_| "a").collect();
+        let mut texts = vec!["hello", long_text.as_str(), "world"];
+        texts.extend(many_texts);
+        let req = TranslateArrayRequest {
+            texts: &texts,
+            from: None,
+            to: &de,
+            content_type: None,
+            category: None,
+            state: None,
+            profanity_action: None,
+        };
+        let split: Vec<usize> = req.split().iter().map(|r| r.texts.len()).collect();
+        assert_eq!(split, vec![3, MAX_ARRAY_TEXTS, 1]);
+    }
+
+    #[test]
+    fn translate_array_request_test() {
+        let en = LanguageTag::parse("en").unwrap();
+        let de = LanguageTag::parse("de").unwrap();
+        let texts = ["Hello", "World"];
+        let req: Request = TranslateArrayRequest {
+                texts: &texts,
+                from: Some(&en),
+                to: &de,
+                content_type: Some(TranslateContentType::Plain),
+                category: None,
+                state: None,
+                profanity_action: Some(ProfanityAction::Marked),
+            }
+            .into();
+        assert_eq!(req.method(), &Method::Post);
+        let body = body_string(req);
+        assert!(body.contains(&format!("<ProfanityAction xmlns=\"{}\">Marked</ProfanityAction>", V2_NS)));
+        assert!(body.contains("<From>en</From>"));
+        assert!(body.contains(&format!("<ContentType xmlns=\"{}\">text/plain</ContentType>", V2_NS)));
+        assert!(body.contains(&format!("<Texts><string xmlns=\"{ns}\">Hello</string><string xmlns=\"{ns}\">World</string></Texts>",
+                                       ns = ARRAYS_NS)));
+        assert!(body.contains("<To>de</To>"));
+    }
+
+    #[test]
+    fn translate_array_response_test() {
+        let body = format!(r#"<ArrayOfTranslateArrayResponse xmlns="{}">
+              <TranslateArrayResponse>
+                <From>en</From>
+                <OriginalTextSentenceLengths xmlns:a="{ns}"><a:int>6</a:int><a:int>5</a:int></OriginalTextSentenceLengths>
+                <State/>
+                <TranslatedText>Hallo. Welt</TranslatedText>
+                <TranslatedTextSentenceLengths xmlns:a="{ns}"><a:int>6</a:int><a:int>4</a:int></TranslatedTextSentenceLengths>
+              </TranslateArrayResponse>
+            </ArrayOfTranslateArrayResponse>"#,
+                           V2_NS,
+                           ns = ARRAYS_NS);
+        let result = FutureTranslateArrayResponse::from(response(&body)).wait().unwrap();
+        assert_eq!(result,
+                   vec![ArrayTranslation {
+                            from: "en".to_string(),
+                            translated_text: "Hallo. Welt".to_string(),
+                            original_text_sentence_lengths: vec![6, 5],
+                            translated_text_sentence_lengths: vec![6, 4],
+                            state: None,
+                        }]);
+    }
+
+    #[test]
+    fn detect_array_request_test() {
+        let texts = ["Hello", "Hallo"];
+        let req: Request = DetectArrayRequest { texts: &texts }.into();
+        assert_eq!(body_string(req),
+                   format!("<?xml version=\"1.0\" encoding=\"utf-8\"?><ArrayOfstring xmlns=\"{}\"><string>Hello</string><string>Hallo</string></ArrayOfstring>",
+                           ARRAYS_NS));
+    }
+
+    #[test]
+    fn detect_response_test() {
+        let body = r#"<string xmlns="http://schemas.microsoft.com/2003/10/Serialization/">en</string>"#;
+        let result = FutureDetectResponse::from(response(body)).wait().unwrap();
+        assert_eq!(result, "en");
+        let body = format!(r#"<ArrayOfstring xmlns="{}"><string>en</string><string>de</string></ArrayOfstring>"#,
+                           ARRAYS_NS);
+        let result = FutureDetectArrayResponse::from(response(&body)).wait().unwrap();
+        assert_eq!(result, vec!["en", "de"]);
+    }
+
+    #[test]
+    fn break_sentences_test() {
+        let body = format!(r#"<ArrayOfint xmlns="{}"><int>7</int><int>8</int></ArrayOfint>"#,
+                           ARRAYS_NS);
+        let breaks = FutureBreakSentencesResponse::from(response(&body)).wait().unwrap();
+        assert_eq!(breaks.offsets(), vec![0..7, 7..15]);
+        assert_eq!(breaks.sentences("Grüße. Tschüss!"), vec!["Grüße. ", "Tschüss!"]);
+    }
+
+    #[test]
+    fn sentences_past_end_test() {
+        let breaks = SentenceBreaks { lengths: vec![7, 9, 4] };
+        assert_eq!(breaks.sentences("Grüße. Tschüss!"), vec!["Grüße. ", "Tschüss!", ""]);
+    }
+
+    #[test]
+    fn get_translations_request_test() {
+        let en = LanguageTag::parse("en").unwrap();
+        let de = LanguageTag::parse("de").unwrap();
+        let req: Request = GetTranslationsRequest {
+                text: "Hello",
+                from: &en,
+                to: &de,
+                max_translations: 3,
+                content_type: None,
+                category: Some(&CategoryId::general()),
+                state: None,
+                profanity_action: Some(ProfanityAction::Marked),
+            }
+            .into();
+        assert_eq!(req.uri().query(),
+                   Some("text=Hello&from=en&to=de&maxTranslations=3"));
+        assert_eq!(body_string(req),
+                   format!("<?xml version=\"1.0\" encoding=\"utf-8\"?><TranslateOptions xmlns=\"{}\"><Category>general</Category><ProfanityAction>Marked</ProfanityAction><ReservedFlags /></TranslateOptions>",
+                           V2_NS));
+    }
+
+    #[test]
+    fn get_translations_array_request_test() {
+        let en = LanguageTag::parse("en").unwrap();
+        let de = LanguageTag::parse("de").unwrap();
+        let req: Request = GetTranslationsArrayRequest {
+                texts: &["Hello"],
+                from: &en,
+                to: &de,
+                max_translations: 3,
+                content_type: None,
+                category: None,
+                state: None,
+                profanity_action: Some(ProfanityAction::Deleted),
+            }
+            .into();
+        let body = body_string(req);
+        assert!(body.contains(&format!("<ProfanityAction xmlns=\"{}\">Deleted</ProfanityAction>", V2_NS)),
+                "{}",
+                body);
+    }
+
+    #[test]
+    fn get_translations_array_response_test() {
+        let body = format!(r#"<ArrayOfGetTranslationsResponse xmlns="{}">
+              <GetTranslationsResponse>
+                <From>en</From>
+                <State/>
+                <Translations>
+                  <TranslationMatch>
+                    <Count>2</Count>
+                    <MatchDegree>100</MatchDegree>
+                    <MatchedOriginalText>Hello</MatchedOriginalText>
+                    <Rating>6</Rating>
+                    <TranslatedText>Servus</TranslatedText>
+                  </TranslationMatch>
+                  <TranslationMatch>
+                    <Count>0</Count>
+                    <MatchDegree>100</MatchDegree>
+                    <MatchedOriginalText/>
+                    <Rating>5</Rating>
+                    <TranslatedText>Hallo</TranslatedText>
+                  </TranslationMatch>
+                </Translations>
+              </GetTranslationsResponse>
+            </ArrayOfGetTranslationsResponse>"#,
+                           V2_NS);
+        let result = FutureGetTranslationsArrayResponse::from(response(&body)).wait().unwrap();
+        assert_eq!(result,
+                   vec![Translations {
+                            from: "en".to_string(),
+                            state: None,
+                            matches: vec![TranslationMatch {
+                                              translated_text: "Servus".to_string(),
+                                              matched_original_text: Some("Hello".to_string()),
+                                              rating: 6,
+                                              count: 2,
+                                              match_degree: 100,
+                                              error: None,
+                                          },
+                                          TranslationMatch {
+                                              translated_text: "Hallo".to_string(),
+                                              matched_original_text: None,
+                                              rating: 5,
+                                              count: 0,
+                                              match_degree: 100,
+                                              error: None,
+                                          }],
+                        }]);
+    }
+
+    #[test]
+    fn language_catalog_test() {
+        let catalog = LanguageCatalog::new("de".to_string(),
+                                           vec!["en".to_string(), "de".to_string()],
+                                           vec!["en".to_string()],
+                                           vec!["Englisch".to_string(), "Deutsch".to_string()]);
+        assert!(catalog.supports_translation("de"));
+        assert!(!catalog.supports_translation("ger"));
+        assert!(catalog.supports_speech("en"));
+        assert!(!catalog.supports_speech("de"));
+        assert_eq!(catalog.name("en"), Some("Englisch"));
+        assert_eq!(catalog.named_languages(),
+                   vec![("de", "Deutsch"), ("en", "Englisch")]);
+        assert!(!catalog.is_older_than(Duration::minutes(1)));
+    }
+
+    #[test]
+    fn fetch_language_catalog_test() {
+        let dispatcher = Dispatcher::new(|req: Request| {
+            if req.uri().path() == "/v2/http.svc/GetLanguageNames" {
+                assert_eq!(req.uri().query(), Some("locale=zh-CHS"));
+            }
+            let body = match req.uri().path() {
+                "/v2/http.svc/GetLanguagesForTranslate" => vec!["en", "zh-CHT", "de"],
+                "/v2/http.svc/GetLanguagesForSpeak" => vec!["en"],
+                _ => vec!["英语", "繁体中文", "德语"],
+            };
+            let xml = string_array(&body).to_string().unwrap();
+            Box::new(future::ok(Response::new().with_body(xml)))
+        });
+        let locale = LanguageTag::parse("zh-Hans").unwrap();
+        let catalog = LanguageCatalog::fetch(&locale).start(dispatcher).wait().unwrap();
+        assert_eq!(catalog.locale(), "zh-Hans");
+        assert_eq!(catalog.speech_languages(), &["en".to_string()]);
+        assert_eq!(catalog.name("zh-CHT"), Some("繁体中文"));
+        assert_eq!(catalog.name("de"), Some("德语"));
+    }
+
+    #[test]
+    fn speak_request_test() {
+        let en = LanguageTag::parse("en").unwrap();
+        let req: Request = SpeakRequest {
+                text: "Hello",
+                language: &en,
+                format: Some(AudioFormat::Mp3),
+                quality: Some(AudioQuality::MaxQuality),
+                gender: Some(VoiceGender::Female),
+            }
+            .into();
+        assert_eq!(req.uri().query(),
+                   Some("text=Hello&language=en&format=audio%2Fmp3&options=MaxQuality%7Cfemale"));
+    }
+
+    #[test]
+    fn speak_stream_response_test() {
+        let resp = Response::new().with_body(vec![1u8, 2, 3]);
+        let stream = FutureSpeakStreamResponse::from(Ok(resp)).wait().unwrap();
+        let bytes: Vec<u8> = stream
+            .collect()
+            .wait()
+            .unwrap()
+            .iter()
+            .flat_map(|c| c.to_vec())
+            .collect();
+        assert_eq!(bytes, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn add_translation_validation_test() {
+        let en = LanguageTag::parse("en").unwrap();
+        let de = LanguageTag::parse("de").unwrap();
+        let long_text: String = (0..MAX_ADD_ORIGINAL_CHARS + 1).map(|_| 'a').collect();
+        let translations = [AddedTranslation {
+                                original_text: "Hello",
+                                translated_text: "Servus",
+                                rating: Some(6),
+                                sequence: None,
+                            },
+                            AddedTranslation {
+                                original_text: &long_text,
+                                translated_text: "Servus",
+                                rating: None,
+                                sequence: None,
+                            }];
+        let mut req = AddTranslationArrayRequest {
+            translations: &translations[..1],
+            from: &en,
+            to: &de,
+            content_type: None,
+            category: None,
+            user: "linguist",
+            uri: None,
+        };
+        assert_eq!(req.validate(), Ok(()));
+        req.translations = &translations;
+        assert_eq!(req.validate(),
+                   Err(SubmissionError::OriginalTextTooLong(MAX_ADD_ORIGINAL_CHARS + 1)));
+        req.translations = &translations[..1];
+        req.user = "";
+        assert_eq!(req.validate(), Err(SubmissionError::MissingUser));
+        let single = AddTranslationRequest {
+            original_text: "Hello",
+            translated_text: "Servus",
+            from: &en,
+            to: &de,
+            rating: Some(11),
+            content_type: None,
+            category: None,
+            user: "linguist",
+            uri: None,
+        };
+        assert_eq!(single.validate(), Err(SubmissionError::RatingOutOfRange(11)));
+    }
+
+    #[test]
+    fn add_translation_rejected_test() {
+        let resp = Response::new()
+            .with_status(StatusCode::BadRequest)
+            .with_body("Category is invalid");
+        match FutureAddTranslationResponse::from(Ok(resp)).wait() {
+            Err(super::Error::SubmissionError(SubmissionError::Rejected(StatusCode::BadRequest, ref message)))
+                if message == "Category is invalid" => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_profanity_test() {
+        let tagged = parse_profanity("Du <profanity>Idiot</profanity>, hör auf", ProfanityMarker::Tag);
+        assert_eq!(tagged.text, "Du Idiot, hör auf");
+        assert_eq!(tagged.spans, vec![3..8]);
+        let asterisks = parse_profanity("Tu es un ***, arrête ***", ProfanityMarker::Asterisk);
+        assert_eq!(asterisks.text, "Tu es un ***, arrête ***");
+        assert_eq!(asterisks.spans, vec![9..12, 21..24]);
+        // Asterisks that were in the text are indistinguishable from masked words
+        assert_eq!(parse_profanity("5 * 3", ProfanityMarker::Asterisk).spans, vec![2..3]);
+    }
+
+    #[test]
+    fn translate_request_test() {
+        let de = LanguageTag::parse("de").unwrap();
+        let req: Request = TranslateRequest {
+                text: "Hello",
+                from: None,
+                to: &de,
+                content_type: None,
+                category: None,
+                profanity_action: Some(ProfanityAction::Deleted),
+            }
+            .into();
+        assert_eq!(req.uri().query(),
+                   Some("to=de&text=Hello&profanityAction=Deleted"));
+    }
+
+    #[test]
+    fn owned_request_test() {
+        let de = LanguageTag::parse("de").unwrap();
+        let tech = CategoryId::parse("tech").unwrap();
+        let texts = ["Hello", "World"];
+        let owned: OwnedTranslateArrayRequest = TranslateArrayRequest {
+                texts: &texts,
+                from: None,
+                to: &de,
+                content_type: None,
+                category: Some(&tech),
+                state: None,
+                profanity_action: None,
+            }
+            .into();
+        let expected: Request = TranslateArrayRequest {
+                texts: &texts,
+                from: None,
+                to: &de,
+                content_type: None,
+                category: Some(&tech),
+                state: None,
+                profanity_action: None,
+            }
+            .into();
+        let req: Request = ::std::thread::spawn(move || owned).join().unwrap().into();
+        assert_eq!(body_string(req), body_string(expected));
+
+        let owned = OwnedTranslateRequest {
+            text: "Hello".to_string(),
+            from: None,
+            to: de.clone(),
+            content_type: None,
+            category: None,
+            profanity_action: None,
+        };
+        let req: Request = owned.into();
+        assert_eq!(req.uri().query(), Some("to=de&text=Hello"));
+    }
+
+    #[cfg(feature = "serialization")]
+    #[test]
+    fn owned_request_serialization_test() {
+        let owned = OwnedTranslateArrayRequest {
+            texts: vec!["Hello".to_string(), "World".to_string()],
+            from: Some(LanguageTag::parse("en").unwrap()),
+            to: LanguageTag::parse("zh-CHT").unwrap(),
+            content_type: Some(TranslateContentType::Html),
+            category: Some(CategoryId::parse("tech").unwrap()),
+            state: None,
+            profanity_action: Some(ProfanityAction::Marked),
+        };
+        let json = ::serde_json::to_string(&owned).unwrap();
+        assert!(json.contains(r#""to":"zh-Hant""#));
+        let replayed: OwnedTranslateArrayRequest = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(replayed, owned);
+        assert_eq!(body_string(replayed.into()), body_string(owned.into()));
+
+        let owned = OwnedAddTranslationArrayRequest {
+            translations: vec![OwnedAddedTranslation {
+                                   original_text: "Hello".to_string(),
+                                   translated_text: "Hallo".to_string(),
+                                   rating: Some(5),
+                                   sequence: None,
+                               }],
+            from: LanguageTag::parse("en").unwrap(),
+            to: LanguageTag::parse("de").unwrap(),
+            content_type: None,
+            category: None,
+            user: "tester".to_string(),
+            uri: None,
+        };
+        let json = ::serde_json::to_string(&owned).unwrap();
+        assert_eq!(::serde_json::from_str::<OwnedAddTranslationArrayRequest>(&json).unwrap(), owned);
+    }
+
+    #[test]
+    fn service_error_test() {
+        let resp = Response::new()
+            .with_status(StatusCode::BadRequest)
+            .with_body("Invalid language");
+        match FutureTranslateArrayResponse::from(Ok(resp)).wait() {
+            Err(super::Error::ServiceError(StatusCode::BadRequest, ref message)) if message == "Invalid language" => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,1008 @@
+//! This module holds Cogs for the JSON based v3 Translator API
+use hyper::client::Request;
+use hyper::client::Response;
+use hyper::{Method, StatusCode, Uri};
+use engine;
+use futures::{Future, Poll};
+use serde_json;
+use url::Url;
+use core::str::FromStr;
+use std::fmt;
+use std::ops::Range;
+use std::collections::BTreeMap;
+use super::*;
+use super::translation::{TranslateContentType, ProfanityAction, ProfanityMarker};
+use glossary::Glossary;
+use language::LanguageTag;
+use category::CategoryId;
+
+const BASE_URI: &'static str = "https://api.cognitive.microsofttranslator.com";
+const API_VERSION: &'static str = "3.0";
+
+/// A request for translating many texts into many languages at once.
+///
+/// Results are returned in the same order as the texts, each one holding a
+/// translation for every target language, in the same order as `to`.
+#[derive(Default)]
+pub struct TranslateRequest<'a> {
+    pub texts: &'a [&'a str],
+    pub to: &'a [LanguageTag],
+    pub from: Option<&'a LanguageTag>,
+    pub text_type: Option<TranslateContentType>,
+    pub category: Option<&'a CategoryId>,
+    /// Sent as profanityAction, the service leaves profanity alone when None
+    pub profanity_action: Option<ProfanityAction>,
+    /// Sent as profanityMarker, only used by the service with ProfanityAction::Marked
+    pub profanity_marker: Option<ProfanityMarker>,
+    pub include_alignment: bool,
+    pub include_sentence_length: bool,
+    /// Language to fall back to when the language of a text can't be detected
+    pub suggested_from: Option<&'a LanguageTag>,
+    pub from_script: Option<&'a str>,
+    pub to_script: Option<&'a str>,
+    /// Whether or not to fall back to a general model when the custom one for `category` doesn't exist
+    pub allow_fallback: Option<bool>,
+    /// Terms to leave untranslated or translate a certain way, sent as dynamic dictionary markup.
+    /// Use `Glossary::translate_v3` to also verify the translations.
+    pub glossary: Option<&'a Glossary>,
+}
+
+/// Translations of a single text
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranslationResult {
+    /// Set if `from` was not given
+    pub detected_language: Option<DetectedLanguage>,
+    /// Set if `from_script` was given, holding the input transliterated into the default script
+    pub source_text: Option<SourceText>,
+    pub translations: Vec<Translation>,
+}
+
+/// Language detected by the service
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DetectedLanguage {
+    pub language: String,
+    pub score: f64,
+}
+
+/// Input text in the default script of its language
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SourceText {
+    pub text: String,
+}
+
+/// Translation of a text into a single language
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Translation {
+    pub to: String,
+    pub text: String,
+    /// Set if `to_script` was given
+    pub transliteration: Option<Transliteration>,
+    /// Set if `include_alignment` was true
+    pub alignment: Option<Alignment>,
+    /// Set if `include_sentence_length` was true
+    #[serde(rename = "sentLen")]
+    pub sentence_lengths: Option<SentenceLengths>,
+}
+
+/// Text converted into another script
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Transliteration {
+    pub text: String,
+    pub script: String,
+}
+
+/// Raw alignment between a text and its translation, as returned by the service
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Alignment {
+    pub proj: String,
+}
+
+/// Lengths of the sentences of a text and of its translation, in characters
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SentenceLengths {
+    #[serde(rename = "srcSentLen")]
+    pub source: Vec<usize>,
+    #[serde(rename = "transSentLen")]
+    pub translated: Vec<usize>,
+}
+
+impl Alignment {
+    /// Parses the alignment into pairs of aligned source and target character ranges.
+    ///
+    /// The service sends pairs such as "0:4-6:10" with inclusive ends, these are
+    /// turned into the half-open ranges 0..5 and 6..11.
+    pub fn pairs(&self) -> Result<Vec<(Range<usize>, Range<usize>)>, Error> {
+        self.proj
+            .split_whitespace()
+            .map(|pair| parse_alignment_pair(pair).ok_or_else(|| Error::InvalidAlignment(pair.to_string())))
+            .collect()
+    }
+}
+
+/// Parses a single "a:b-c:d" alignment pair
+fn parse_alignment_pair(pair: &str) -> Option<(Range<usize>, Range<usize>)> {
+    let mut sides = pair.splitn(2, '-');
+    let source = sides.next().and_then(parse_inclusive_range)?;
+    let target = sides.next().and_then(parse_inclusive_range)?;
+    Some((source, target))
+}
+
+/// Parses an "a:b" inclusive range into a half-open one
+fn parse_inclusive_range(range: &str) -> Option<Range<usize>> {
+    let mut ends = range.splitn(2, ':');
+    let start: usize = ends.next()?.parse().ok()?;
+    let end: usize = ends.next()?.parse().ok()?;
+    if end < start {
+        None
+    } else {
+        Some(start..end + 1)
+    }
+}
+
+/// A translation along with its alignment and sentence lengths, if they were requested
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranslationDetail {
+    pub source: String,
+    pub text: String,
+    /// Aligned source and target character ranges
+    pub alignment: Vec<(Range<usize>, Range<usize>)>,
+    pub source_sentence_lengths: Vec<usize>,
+    pub translated_sentence_lengths: Vec<usize>,
+}
+
+impl TranslationDetail {
+    /// Builds the detail of a translation of the given source text
+    pub fn new(source: &str, translation: &Translation) -> Result<TranslationDetail, Error> {
+        let alignment = match translation.alignment {
+            Some(ref a) => a.pairs()?,
+            None => vec![],
+        };
+        let (source_lengths, translated_lengths) = match translation.sentence_lengths {
+            Some(ref l) => (l.source.clone(), l.translated.clone()),
+            None => (vec![], vec![]),
+        };
+        Ok(TranslationDetail {
+               source: source.to_string(),
+               text: translation.text.clone(),
+               alignment: alignment,
+               source_sentence_lengths: source_lengths,
+               translated_sentence_lengths: translated_lengths,
+           })
+    }
+
+    /// Projects a character range of the source onto the translation, returning
+    /// the smallest range covering every target range aligned with it.
+    ///
+    /// Returns None if no part of the given range is aligned.
+    pub fn project(&self, source: Range<usize>) -> Option<Range<usize>> {
+        self.alignment
+            .iter()
+            .filter(|&&(ref s, _)| s.start < source.end && source.start < s.end)
+            .fold(None, |acc: Option<Range<usize>>, &(_, ref t)| match acc {
+                Some(r) => Some(r.start.min(t.start)..r.end.max(t.end)),
+                None => Some(t.clone()),
+            })
+    }
+
+    /// Returns the part of the translation a character range of the source projects onto
+    pub fn project_text(&self, source: Range<usize>) -> Option<String> {
+        self.project(source)
+            .map(|r| self.text.chars().skip(r.start).take(r.end - r.start).collect())
+    }
+}
+
+impl TranslationResult {
+    /// Returns the detail of every translation of the given source text
+    pub fn details(&self, source: &str) -> Result<Vec<TranslationDetail>, Error> {
+        self.translations
+            .iter()
+            .map(|t| TranslationDetail::new(source, t))
+            .collect()
+    }
+}
+
+future_response!(
+    /// Wrapper type for our boxed future
+    FutureTranslateResponse, Vec<TranslationResult>);
+
+impl<'a> From<TranslateRequest<'a>> for Request {
+    fn from(t: TranslateRequest<'a>) -> Self {
+        let mut params: Vec<(&str, String)> = t.to.iter().map(|to| ("to", to.to_string())).collect();
+        if let Some(from) = t.from {
+            params.push(("from", from.to_string()));
+        }
+        match t.text_type {
+            Some(TranslateContentType::Html) => params.push(("textType", "html".to_string())),
+            Some(TranslateContentType::Plain) => params.push(("textType", "plain".to_string())),
+            _ => (),
+        }
+        if let Some(cat) = t.category {
+            params.push(("category", cat.as_str().to_string()));
+        }
+        if let Some(action) = t.profanity_action {
+            params.push(("profanityAction", action.as_str().to_string()));
+        }
+        if let Some(marker) = t.profanity_marker {
+            params.push(("profanityMarker", marker.as_str().to_string()));
+        }
+        if t.include_alignment {
+            params.push(("includeAlignment", "true".to_string()));
+        }
+        if t.include_sentence_length {
+            params.push(("includeSentenceLength", "true".to_string()));
+        }
+        if let Some(suggested) = t.suggested_from {
+            params.push(("suggestedFrom", suggested.to_string()));
+        }
+        if let Some(script) = t.from_script {
+            params.push(("fromScript", script.to_string()));
+        }
+        if let Some(script) = t.to_script {
+            params.push(("toScript", script.to_string()));
+        }
+        if let Some(fallback) = t.allow_fallback {
+            params.push(("allowFallback", fallback.to_string()));
+        }
+        match t.glossary {
+            Some(glossary) => {
+                let marked: Vec<String> = t.texts.iter().map(|text| glossary.mark_up(text)).collect();
+                let marked_refs: Vec<&str> = marked.iter().map(|m| m.as_str()).collect();
+                json_post(api_uri("translate", &params), &text_items(&marked_refs))
+            }
+            None => json_post(api_uri("translate", &params), &text_items(t.texts)),
+        }
+    }
+}
+
+impl<'a> Cog for TranslateRequest<'a> {
+    type Output = FutureTranslateResponse;
+    type Item = Vec<TranslationResult>;
+    type Error = Error;
+}
+
+impl From<Result<Response, engine::Error>> for FutureTranslateResponse {
+    fn from(result: Result<Response, engine::Error>) -> Self {
+        FutureTranslateResponse(read_json(result))
+    }
+}
+
+/// A request for converting texts from one script into another, without translating them
+pub struct TransliterateRequest<'a> {
+    pub texts: &'a [&'a str],
+    pub language: &'a LanguageTag,
+    pub from_script: &'a str,
+    pub to_script: &'a str,
+}
+
+future_response!(
+    /// Wrapper type for our boxed future
+    FutureTransliterateResponse, Vec<Transliteration>);
+
+impl<'a> From<TransliterateRequest<'a>> for Request {
+    fn from(t: TransliterateRequest<'a>) -> Self {
+        let params = [("language", t.language.to_string()),
+                      ("fromScript", t.from_script.to_string()),
+                      ("toScript", t.to_script.to_string())];
+        json_post(api_uri("transliterate", &params), &text_items(t.texts))
+    }
+}
+
+impl<'a> Cog for TransliterateRequest<'a> {
+    type Output = FutureTransliterateResponse;
+    type Item = Vec<Transliteration>;
+    type Error = Error;
+}
+
+impl From<Result<Response, engine::Error>> for FutureTransliterateResponse {
+    fn from(result: Result<Response, engine::Error>) -> Self {
+        FutureTransliterateResponse(read_json(result))
+    }
+}
+
+/// A request for the languages that support transliteration, and their scripts
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct TransliterationLanguagesRequest;
+
+/// A language that supports transliteration
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransliterationLanguage {
+    pub name: String,
+    pub native_name: String,
+    /// Scripts texts can be transliterated from
+    pub scripts: Vec<TransliterationScript>,
+}
+
+/// A script texts can be transliterated from
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransliterationScript {
+    pub code: String,
+    pub name: String,
+    pub native_name: String,
+    pub dir: String,
+    /// Scripts texts can be transliterated into
+    pub to_scripts: Vec<Script>,
+}
+
+/// A writing script
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Script {
+    pub code: String,
+    pub name: String,
+    pub native_name: String,
+    pub dir: String,
+}
+
+#[derive(Deserialize)]
+struct TransliterationLanguages {
+    transliteration: BTreeMap<String, TransliterationLanguage>,
+}
+
+future_response!(
+    /// Wrapper type for our boxed future
+    FutureTransliterationLanguagesResponse, BTreeMap<String, TransliterationLanguage>);
+
+impl From<TransliterationLanguagesRequest> for Request {
+    fn from(_: TransliterationLanguagesRequest) -> Self {
+        json_get("languages", &[("scope", "transliteration".to_string())])
+    }
+}
+
+impl Cog for TransliterationLanguagesRequest {
+    type Output = FutureTransliterationLanguagesResponse;
+    type Item = BTreeMap<String, TransliterationLanguage>;
+    type Error = Error;
+}
+
+impl From<Result<Response, engine::Error>> for FutureTransliterationLanguagesResponse {
+    fn from(result: Result<Response, engine::Error>) -> Self {
+        let f = read_json(result).map(|l: TransliterationLanguages| l.transliteration);
+        FutureTransliterationLanguagesResponse(Box::new(f))
+    }
+}
+
+/// A request for the alternative translations of single words or short phrases
+pub struct DictionaryLookupRequest<'a> {
+    pub texts: &'a [&'a str],
+    pub from: &'a LanguageTag,
+    pub to: &'a LanguageTag,
+}
+
+/// Dictionary entry for a single text
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DictionaryEntry {
+    /// Source term in the form to use when requesting examples
+    pub normalized_source: String,
+    /// Source term in the form best suited for displaying it
+    pub display_source: String,
+    pub translations: Vec<DictionaryTranslation>,
+}
+
+/// Alternative translation of a dictionary term
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DictionaryTranslation {
+    /// Translated term in the form to use when requesting examples
+    pub normalized_target: String,
+    pub display_target: String,
+    /// Part-of-speech tag, such as NOUN or VERB
+    pub pos_tag: String,
+    /// Between 0 and 1, the confidences of all translations of a term adding up to 1
+    pub confidence: f64,
+    /// Word to display before the translation, such as a masculine or feminine article
+    pub prefix_word: String,
+    pub back_translations: Vec<BackTranslation>,
+}
+
+/// Translation of a dictionary translation back into the source language
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackTranslation {
+    pub normalized_text: String,
+    pub display_text: String,
+    /// Number of examples available for this pair
+    pub num_examples: u32,
+    /// Frequency of this pair in the data the dictionary was built from
+    pub frequency_count: u32,
+}
+
+impl DictionaryEntry {
+    /// Returns the pairs to request examples for, one per translation
+    pub fn example_pairs<'a>(&'a self) -> Vec<ExamplePair<'a>> {
+        self.translations
+            .iter()
+            .map(|t| {
+                     ExamplePair {
+                         text: self.normalized_source.as_str(),
+                         translation: t.normalized_target.as_str(),
+                     }
+                 })
+            .collect()
+    }
+}
+
+future_response!(
+    /// Wrapper type for our boxed future
+    FutureDictionaryLookupResponse, Vec<DictionaryEntry>);
+
+impl<'a> From<DictionaryLookupRequest<'a>> for Request {
+    fn from(d: DictionaryLookupRequest<'a>) -> Self {
+        let params = [("from", d.from.to_string()), ("to", d.to.to_string())];
+        json_post(api_uri("dictionary/lookup", &params), &text_items(d.texts))
+    }
+}
+
+impl<'a> Cog for DictionaryLookupRequest<'a> {
+    type Output = FutureDictionaryLookupResponse;
+    type Item = Vec<DictionaryEntry>;
+    type Error = Error;
+}
+
+impl From<Result<Response, engine::Error>> for FutureDictionaryLookupResponse {
+    fn from(result: Result<Response, engine::Error>) -> Self {
+        FutureDictionaryLookupResponse(read_json(result))
+    }
+}
+
+/// A request for usage examples of dictionary translations.
+///
+/// Use DictionaryEntry::example_pairs to get pairs from a lookup.
+pub struct DictionaryExamplesRequest<'a> {
+    pub pairs: &'a [ExamplePair<'a>],
+    pub from: &'a LanguageTag,
+    pub to: &'a LanguageTag,
+}
+
+/// A normalized term and one of its normalized translations
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ExamplePair<'a> {
+    #[serde(rename = "Text")]
+    pub text: &'a str,
+    #[serde(rename = "Translation")]
+    pub translation: &'a str,
+}
+
+/// Usage examples for a single pair
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DictionaryExamples {
+    pub normalized_source: String,
+    pub normalized_target: String,
+    pub examples: Vec<DictionaryExample>,
+}
+
+/// A sentence using a term along with its translation, each split around the term
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DictionaryExample {
+    pub source_prefix: String,
+    pub source_term: String,
+    pub source_suffix: String,
+    pub target_prefix: String,
+    pub target_term: String,
+    pub target_suffix: String,
+}
+
+impl DictionaryExample {
+    /// Returns the full source sentence
+    pub fn source(&self) -> String {
+        format!("{}{}{}", self.source_prefix, self.source_term, self.source_suffix)
+    }
+
+    /// Returns the full translated sentence
+    pub fn target(&self) -> String {
+        format!("{}{}{}", self.target_prefix, self.target_term, self.target_suffix)
+    }
+}
+
+future_response!(
+    /// Wrapper type for our boxed future
+    FutureDictionaryExamplesResponse, Vec<DictionaryExamples>);
+
+impl<'a> From<DictionaryExamplesRequest<'a>> for Request {
+    fn from(d: DictionaryExamplesRequest<'a>) -> Self {
+        let params = [("from", d.from.to_string()), ("to", d.to.to_string())];
+        json_post(api_uri("dictionary/examples", &params), &d.pairs)
+    }
+}
+
+impl<'a> Cog for DictionaryExamplesRequest<'a> {
+    type Output = FutureDictionaryExamplesResponse;
+    type Item = Vec<DictionaryExamples>;
+    type Error = Error;
+}
+
+impl From<Result<Response, engine::Error>> for FutureDictionaryExamplesResponse {
+    fn from(result: Result<Response, engine::Error>) -> Self {
+        FutureDictionaryExamplesResponse(read_json(result))
+    }
+}
+
+/// Owned variant of TranslateRequest, which can be stored, sent across threads and serialized
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct OwnedTranslateRequest {
+    pub texts: Vec<String>,
+    pub to: Vec<LanguageTag>,
+    pub from: Option<LanguageTag>,
+    pub text_type: Option<TranslateContentType>,
+    pub category: Option<CategoryId>,
+    pub profanity_action: Option<ProfanityAction>,
+    pub profanity_marker: Option<ProfanityMarker>,
+    pub include_alignment: bool,
+    pub include_sentence_length: bool,
+    pub suggested_from: Option<LanguageTag>,
+    pub from_script: Option<String>,
+    pub to_script: Option<String>,
+    pub allow_fallback: Option<bool>,
+    pub glossary: Option<Glossary>,
+}
+
+impl<'a> From<TranslateRequest<'a>> for OwnedTranslateRequest {
+    fn from(t: TranslateRequest<'a>) -> Self {
+        OwnedTranslateRequest {
+            texts: owned_strings(t.texts),
+            to: t.to.to_vec(),
+            from: t.from.cloned(),
+            text_type: t.text_type,
+            category: t.category.cloned(),
+            profanity_action: t.profanity_action,
+            profanity_marker: t.profanity_marker,
+            include_alignment: t.include_alignment,
+            include_sentence_length: t.include_sentence_length,
+            suggested_from: t.suggested_from.cloned(),
+            from_script: t.from_script.map(|s| s.to_string()),
+            to_script: t.to_script.map(|s| s.to_string()),
+            allow_fallback: t.allow_fallback,
+            glossary: t.glossary.cloned(),
+        }
+    }
+}
+
+impl From<OwnedTranslateRequest> for Request {
+    fn from(t: OwnedTranslateRequest) -> Self {
+        let texts = str_refs(&t.texts);
+        TranslateRequest {
+                texts: &texts,
+                to: &t.to,
+                from: t.from.as_ref(),
+                text_type: t.text_type,
+                category: t.category.as_ref(),
+                profanity_action: t.profanity_action,
+                profanity_marker: t.profanity_marker,
+                include_alignment: t.include_alignment,
+                include_sentence_length: t.include_sentence_length,
+                suggested_from: t.suggested_from.as_ref(),
+                from_script: t.from_script.as_ref().map(|s| s.as_str()),
+                to_script: t.to_script.as_ref().map(|s| s.as_str()),
+                allow_fallback: t.allow_fallback,
+                glossary: t.glossary.as_ref(),
+            }
+            .into()
+    }
+}
+
+owned_cog!(OwnedTranslateRequest, TranslateRequest);
+
+/// Owned variant of TransliterateRequest
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct OwnedTransliterateRequest {
+    pub texts: Vec<String>,
+    pub language: LanguageTag,
+    pub from_script: String,
+    pub to_script: String,
+}
+
+impl<'a> From<TransliterateRequest<'a>> for OwnedTransliterateRequest {
+    fn from(t: TransliterateRequest<'a>) -> Self {
+        OwnedTransliterateRequest {
+            texts: owned_strings(t.texts),
+            language: t.language.clone(),
+            from_script: t.from_script.to_string(),
+            to_script: t.to_script.to_string(),
+        }
+    }
+}
+
+impl From<OwnedTransliterateRequest> for Request {
+    fn from(t: OwnedTransliterateRequest) -> Self {
+        let texts = str_refs(&t.texts);
+        TransliterateRequest {
+                texts: &texts,
+                language: &t.language,
+                from_script: &t.from_script,
+                to_script: &t.to_script,
+            }
+            .into()
+    }
+}
+
+owned_cog!(OwnedTransliterateRequest, TransliterateRequest);
+
+/// Owned variant of DictionaryLookupRequest
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct OwnedDictionaryLookupRequest {
+    pub texts: Vec<String>,
+    pub from: LanguageTag,
+    pub to: LanguageTag,
+}
+
+impl<'a> From<DictionaryLookupRequest<'a>> for OwnedDictionaryLookupRequest {
+    fn from(d: DictionaryLookupRequest<'a>) -> Self {
+        OwnedDictionaryLookupRequest {
+            texts: owned_strings(d.texts),
+            from: d.from.clone(),
+            to: d.to.clone(),
+        }
+    }
+}
+
+impl From<OwnedDictionaryLookupRequest> for Request {
+    fn from(d: OwnedDictionaryLookupRequest) -> Self {
+        let texts = str_refs(&d.texts);
+        DictionaryLookupRequest {
+                texts: &texts,
+                from: &d.from,
+                to: &d.to,
+            }
+            .into()
+    }
+}
+
+owned_cog!(OwnedDictionaryLookupRequest, DictionaryLookupRequest);
+
+/// Owned variant of DictionaryExamplesRequest, holding (text, translation) pairs
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct OwnedDictionaryExamplesRequest {
+    pub pairs: Vec<(String, String)>,
+    pub from: LanguageTag,
+    pub to: LanguageTag,
+}
+
+impl<'a> From<DictionaryExamplesRequest<'a>> for OwnedDictionaryExamplesRequest {
+    fn from(d: DictionaryExamplesRequest<'a>) -> Self {
+        OwnedDictionaryExamplesRequest {
+            pairs: d.pairs
+                .iter()
+                .map(|p| (p.text.to_string(), p.translation.to_string()))
+                .collect(),
+            from: d.from.clone(),
+            to: d.to.clone(),
+        }
+    }
+}
+
+impl From<OwnedDictionaryExamplesRequest> for Request {
+    fn from(d: OwnedDictionaryExamplesRequest) -> Self {
+        let pairs: Vec<ExamplePair> = d.pairs
+            .iter()
+            .map(|&(ref text, ref translation)| {
+                     ExamplePair {
+                         text: text,
+                         translation: translation,
+                     }
+                 })
+            .collect();
+        DictionaryExamplesRequest {
+                pairs: &pairs,
+                from: &d.from,
+                to: &d.to,
+            }
+            .into()
+    }
+}
+
+owned_cog!(OwnedDictionaryExamplesRequest, DictionaryExamplesRequest);
+
+/// A single text in the body of a request
+#[derive(Debug, Serialize)]
+struct TextItem<'a> {
+    #[serde(rename = "Text")]
+    text: &'a str,
+}
+
+fn text_items<'a>(texts: &'a [&'a str]) -> Vec<TextItem<'a>> {
+    texts.iter().map(|t| TextItem { text: t }).collect()
+}
+
+/// Returns the Uri for the given path and query parameters
+fn api_uri(path: &str, params: &[(&str, String)]) -> Uri {
+    let mut url = Url::parse(BASE_URI).unwrap().join(path).unwrap();
+    {
+        let mut mut_pairs = url.query_pairs_mut();
+        mut_pairs.append_pair("api-version", API_VERSION);
+        for &(k, ref v) in params {
+            mut_pairs.append_pair(k, v);
+        }
+        mut_pairs.finish();
+    }
+    Uri::from_str(url.as_str()).unwrap()
+}
+
+/// Builds a GET Request for the given path and query parameters
+fn json_get(path: &str, params: &[(&str, String)]) -> Request {
+    Request::new(Method::Get, api_uri(path, params))
+}
+
+/// Error details returned by the service
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ApiError {
+    /// Six digit error code, or 0 if the service did not return one
+    pub code: u32,
+    pub message: String,
+}
+
+/// Translator v3 error mapping
+#[derive(Debug)]
+pub enum Error {
+    JsonError(serde_json::Error),
+    ServiceError(StatusCode, ApiError),
+    InvalidAlignment(String),
+    EngineError(engine::Error),
+}
+
+impl JsonError for Error {
+    type Details = ApiError;
+
+    fn message_details(message: String) -> ApiError {
+        ApiError {
+            code: 0,
+            message: message,
+        }
+    }
+
+    fn json_error(e: serde_json::Error) -> Self {
+        Error::JsonError(e)
+    }
+
+    fn service_error(status: StatusCode, details: ApiError) -> Self {
+        Error::ServiceError(status, details)
+    }
+
+    fn engine_error(e: engine::Error) -> Self {
+        Error::EngineError(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::Stream;
+
+    fn body_string(req: Request) -> String {
+        let body = req.body().concat2().wait().unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    fn response(body: &str) -> Result<Response, engine::Error> {
+        Ok(Response::new().with_body(body.to_string()))
+    }
+
+    #[test]
+    fn translate_request_test() {
+        let en = LanguageTag::parse("en").unwrap();
+        let texts = ["Hello", "World"];
+        let to = [LanguageTag::parse("de").unwrap(), LanguageTag::parse("fr").unwrap()];
+        let req: Request = TranslateRequest {
+                texts: &texts,
+                to: &to,
+                from: Some(&en),
+                profanity_action: Some(ProfanityAction::Marked),
+                profanity_marker: Some(ProfanityMarker::Tag),
+                include_alignment: true,
+                allow_fallback: Some(false),
+                ..Default::default()
+            }
+            .into();
+        assert_eq!(req.uri().path(), "/translate");
+        assert_eq!(req.uri().query(),
+                   Some("api-version=3.0&to=de&to=fr&from=en&profanityAction=Marked&profanityMarker=Tag&includeAlignment=true&allowFallback=false"));
+        assert_eq!(body_string(req), r#"[{"Text":"Hello"},{"Text":"World"}]"#);
+    }
+
+    #[test]
+    fn translate_glossary_test() {
+        let texts = ["Cogs is fast"];
+        let to = [LanguageTag::parse("de").unwrap()];
+        let mut glossary = Glossary::new();
+        glossary.do_not_translate("Cogs");
+        let req: Request = TranslateRequest {
+                texts: &texts,
+                to: &to,
+                glossary: Some(&glossary),
+                ..Default::default()
+            }
+            .into();
+        assert_eq!(body_string(req),
+                   r#"[{"Text":"<mstrans:dictionary translation=\"Cogs\">Cogs</mstrans:dictionary> is fast"}]"#);
+    }
+
+    #[test]
+    fn translate_response_test() {
+        let body = r#"[{
+            "detectedLanguage": {"language": "en", "score": 1.0},
+            "translations": [
+                {"text": "Hallo", "to": "de", "alignment": {"proj": "0:4-0:4"},
+                 "sentLen": {"srcSentLen": [5], "transSentLen": [5]}},
+                {"text": "Bonjour", "to": "fr"}
+            ]
+        }]"#;
+        let result = FutureTranslateResponse::from(response(body)).wait().unwrap();
+        assert_eq!(result,
+                   vec![TranslationResult {
+                            detected_language: Some(DetectedLanguage {
+                                                        language: "en".to_string(),
+                                                        score: 1.0,
+                                                    }),
+                            source_text: None,
+                            translations: vec![Translation {
+                                                   to: "de".to_string(),
+                                                   text: "Hallo".to_string(),
+                                                   transliteration: None,
+                                                   alignment: Some(Alignment { proj: "0:4-0:4".to_string() }),
+                                                   sentence_lengths: Some(SentenceLengths {
+                                                                              source: vec![5],
+                                                                              translated: vec![5],
+                                                                          }),
+                                               },
+                                               Translation {
+                                                   to: "fr".to_string(),
+                                                   text: "Bonjour".to_string(),
+                                                   transliteration: None,
+                                                   alignment: None,
+                                                   sentence_lengths: None,
+                                               }],
+                        }]);
+    }
+
+    #[test]
+    fn translation_detail_test() {
+        let translation = Translation {
+            to: "de".to_string(),
+            text: "Hallo schöne Welt".to_string(),
+            transliteration: None,
+            alignment: Some(Alignment { proj: "0:4-0:4 6:14-6:11 16:20-13:16".to_string() }),
+            sentence_lengths: Some(SentenceLengths {
+                                       source: vec![21],
+                                       translated: vec![17],
+                                   }),
+        };
+        let detail = TranslationDetail::new("Hello wonderful world", &translation).unwrap();
+        assert_eq!(detail.alignment, vec![(0..5, 0..5), (6..15, 6..12), (16..21, 13..17)]);
+        assert_eq!(detail.source_sentence_lengths, vec![21]);
+        assert_eq!(detail.project(0..5), Some(0..5));
+        assert_eq!(detail.project_text(6..21), Some("schöne Welt".to_string()));
+        assert_eq!(detail.project(5..6), None);
+
+        let broken = Alignment { proj: "0:4-0:4 5-2".to_string() };
+        match broken.pairs() {
+            Err(Error::InvalidAlignment(ref pair)) if pair == "5-2" => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn transliterate_test() {
+        let ja = LanguageTag::parse("ja").unwrap();
+        let texts = ["こんにちは"];
+        let req: Request = TransliterateRequest {
+                texts: &texts,
+                language: &ja,
+                from_script: "Jpan",
+                to_script: "Latn",
+            }
+            .into();
+        assert_eq!(req.uri().query(),
+                   Some("api-version=3.0&language=ja&fromScript=Jpan&toScript=Latn"));
+        let body = r#"[{"text":"kon'nichiwa","script":"Latn"}]"#;
+        let result = FutureTransliterateResponse::from(response(body)).wait().unwrap();
+        assert_eq!(result,
+                   vec![Transliteration {
+                            text: "kon'nichiwa".to_string(),
+                            script: "Latn".to_string(),
+                        }]);
+    }
+
+    #[test]
+    fn transliteration_languages_test() {
+        let body = r#"{"transliteration": {"ja": {
+            "name": "Japanese", "nativeName": "日本語",
+            "scripts": [{"code": "Jpan", "name": "Japanese", "nativeName": "日本語", "dir": "ltr",
+                         "toScripts": [{"code": "Latn", "name": "Latin", "nativeName": "ラテン語", "dir": "ltr"}]}]
+        }}}"#;
+        let result = FutureTransliterationLanguagesResponse::from(response(body)).wait().unwrap();
+        let scripts = &result["ja"].scripts;
+        assert_eq!(scripts[0].code, "Jpan");
+        assert_eq!(scripts[0].to_scripts[0].code, "Latn");
+    }
+
+    #[test]
+    fn dictionary_lookup_test() {
+        let en = LanguageTag::parse("en").unwrap();
+        let es = LanguageTag::parse("es").unwrap();
+        let texts = ["fly"];
+        let req: Request = DictionaryLookupRequest {
+                texts: &texts,
+                from: &en,
+                to: &es,
+            }
+            .into();
+        assert_eq!(req.uri().path(), "/dictionary/lookup");
+        assert_eq!(req.uri().query(), Some("api-version=3.0&from=en&to=es"));
+        let body = r#"[{"normalizedSource": "fly", "displaySource": "fly", "translations": [
+            {"normalizedTarget": "volar", "displayTarget": "volar", "posTag": "VERB",
+             "confidence": 0.4081, "prefixWord": "",
+             "backTranslations": [{"normalizedText": "fly", "displayText": "fly",
+                                   "numExamples": 15, "frequencyCount": 4637}]},
+            {"normalizedTarget": "mosca", "displayTarget": "mosca", "posTag": "NOUN",
+             "confidence": 0.2668, "prefixWord": "", "backTranslations": []}
+        ]}]"#;
+        let result = FutureDictionaryLookupResponse::from(response(body)).wait().unwrap();
+        assert_eq!(result[0].translations[0].back_translations[0].num_examples, 15);
+
+        let pairs = result[0].example_pairs();
+        let req: Request = DictionaryExamplesRequest {
+                pairs: &pairs,
+                from: &en,
+                to: &es,
+            }
+            .into();
+        assert_eq!(req.uri().path(), "/dictionary/examples");
+        assert_eq!(body_string(req),
+                   r#"[{"Text":"fly","Translation":"volar"},{"Text":"fly","Translation":"mosca"}]"#);
+    }
+
+    #[test]
+    fn dictionary_examples_test() {
+        let body = r#"[{"normalizedSource": "fly", "normalizedTarget": "volar", "examples": [
+            {"sourcePrefix": "They need machines to ", "sourceTerm": "fly", "sourceSuffix": ".",
+             "targetPrefix": "Necesitan máquinas para ", "targetTerm": "volar", "targetSuffix": "."}
+        ]}]"#;
+        let result = FutureDictionaryExamplesResponse::from(response(body)).wait().unwrap();
+        let example = &result[0].examples[0];
+        assert_eq!(example.source(), "They need machines to fly.");
+        assert_eq!(example.target(), "Necesitan máquinas para volar.");
+    }
+
+    #[cfg(feature = "serialization")]
+    #[test]
+    fn owned_request_serialization_test() {
+        let texts = ["Cogs is fast"];
+        let to = [LanguageTag::parse("zh-CHS").unwrap()];
+        let mut glossary = Glossary::new();
+        glossary.do_not_translate("Cogs");
+        let owned: OwnedTranslateRequest = TranslateRequest {
+                texts: &texts,
+                to: &to,
+                text_type: Some(TranslateContentType::Html),
+                glossary: Some(&glossary),
+                ..Default::default()
+            }
+            .into();
+        let json = serde_json::to_string(&owned).unwrap();
+        assert!(json.contains(r#""to":["zh-Hans"]"#));
+        let replayed: OwnedTranslateRequest = serde_json::from_str(&json).unwrap();
+        assert_eq!(replayed, owned);
+        let req: Request = replayed.into();
+        assert_eq!(req.uri().query(), Some("api-version=3.0&to=zh-Hans&textType=html"));
+        assert!(serde_json::from_str::<OwnedTranslateRequest>(&json.replace("zh-Hans", "z")).is_err());
+    }
+
+    #[test]
+    fn service_error_test() {
+        let resp = Response::new()
+            .with_status(StatusCode::BadRequest)
+            .with_body(r#"{"error":{"code":400036,"message":"The target language is not valid."}}"#);
+        match FutureTranslateResponse::from(Ok(resp)).wait() {
+            Err(Error::ServiceError(StatusCode::BadRequest, ref e)) if e.code == 400036 => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+}
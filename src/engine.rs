@@ -4,14 +4,22 @@ use hyper::client::*;
 use hyper::Uri;
 use hyper::Body;
 use hyper::Method;
-use hyper::header::{Authorization, Bearer, ContentLength};
+use hyper::header::{Authorization, Bearer, ContentLength, RetryAfter};
+use tokio_core::reactor::Handle;
 use std::str::FromStr;
 use futures::future::Future;
 use futures::{future, Stream};
 use time::*;
 use std::sync::{RwLock, Arc};
+use std::rc::Rc;
+#[cfg(test)]
+use std::cell::RefCell;
 use cogs::*;
+use flow::{self, Flow};
+use operation::{self, Operation, PollSettings, Polling};
+use paging::{self, PagedCog, PageSettings, Paged};
 use std::convert::From;
+use std::time::{Duration as StdDuration, SystemTime};
 
 const TOKEN_EXPIRES_IN_MINS: i64 = 9;
 lazy_static! {
@@ -34,6 +42,17 @@ pub struct Engine<Connector>
     client: Arc<Client<Connector>>,
 }
 
+impl<Connector> Clone for Engine<Connector>
+    where Connector: Connect + Service
+{
+    fn clone(&self) -> Self {
+        Engine {
+            credentials: self.credentials.clone(),
+            client: self.client.clone(),
+        }
+    }
+}
+
 impl<Connector> Engine<Connector>
     where Connector: Connect,
           Connector: Service
@@ -92,6 +111,79 @@ impl<Connector> Engine<Connector>
         Box::new(f)
     }
 
+    /// Runs a Flow, renewing the token at most once for all the Cogs it is composed of
+    pub fn run_flow<F>(&self, flow: F) -> Box<Future<Item = <F as Flow>::Item, Error = flow::Error>>
+        where F: Flow
+    {
+        let client_ref = self.client.clone();
+        let f = self.renew_token()
+            .map_err(|e| flow::Error::EngineError(e))
+            .and_then(move |AccessToken { token, .. }| {
+                let dispatcher = Dispatcher::new(move |mut req: Request| {
+                    {
+                        let headers = req.headers_mut();
+                        headers.set(Authorization(Bearer { token: token.clone() }));
+                    }
+                    Box::new(client_ref.request(req).map_err(|e| Error::HyperError(e)))
+                });
+                flow.start(dispatcher)
+            });
+        Box::new(f)
+    }
+
+    /// Sends a Request after conditionally renewing the token, returning the raw Response
+    pub fn send(&self, mut req: Request) -> Box<Future<Item = Response, Error = Error>> {
+        let client_ref = self.client.clone();
+        let f = self.renew_token().and_then(move |AccessToken { token, .. }| {
+            {
+                let headers = req.headers_mut();
+                headers.set(Authorization(Bearer { token: token }));
+            }
+            client_ref.request(req).map_err(|e| Error::HyperError(e))
+        });
+        Box::new(f)
+    }
+
+    /// Submits a long-running Operation and polls it until it finishes
+    ///
+    /// The returned Polling exposes a handle that can be used to save the
+    /// operation's location for resuming later, or to stop polling.
+    pub fn run_operation<O>(&self, operation: O, settings: PollSettings) -> Polling<O::Item, O::Error>
+        where O: Operation + Into<Request>
+    {
+        operation::submit_and_poll::<O>(self.dispatcher(), self.handle().clone(), operation.into(), settings)
+    }
+
+    /// Resumes polling a long-running Operation from a previously saved location
+    pub fn resume_operation<O>(&self, location: Uri, settings: PollSettings) -> Polling<O::Item, O::Error>
+        where O: Operation
+    {
+        operation::poll::<O>(self.dispatcher(), self.handle().clone(), location, settings)
+    }
+
+    /// Asks the service to cancel the long-running operation at the given location
+    pub fn cancel_operation(&self, location: Uri) -> Box<Future<Item = Response, Error = Error>> {
+        self.send(Request::new(Method::Delete, location))
+    }
+
+    /// Returns a Stream of the items of a PagedCog, fetching pages as they are needed
+    pub fn stream<P>(&self, cog: P, settings: PageSettings) -> Paged<P::Item, P::Error>
+        where P: PagedCog
+    {
+        paging::stream(self.dispatcher(), self.handle().clone(), cog, settings)
+    }
+
+    /// Returns a Dispatcher that sends Requests with Engine::send, renewing the token as needed
+    fn dispatcher(&self) -> Dispatcher {
+        let engine_ref = self.clone();
+        Dispatcher::new(move |req| engine_ref.send(req))
+    }
+
+    /// Returns the handle to the event loop the Engine's Client runs on
+    pub fn handle(&self) -> &Handle {
+        self.client.handle()
+    }
+
     /// Conditionally renews the token and returns a valid value in a Future.
     ///
     /// Note, using Box<Future<_,_>> because heck, even Hyper does this (See FutureResponse).
@@ -132,7 +224,7 @@ impl<Connector> Engine<Connector>
                                 self.renew_token()
                             } else {
                                 let mut req: Request<Body> = Request::new(Method::Post,
-                                                                          ISSUE_TOKEN_URI.clone());
+                                                                          creds.token_uri.clone());
                                 {
                                     let headers = req.headers_mut();
                                     headers.set(SubscriptionKeyHeader(creds
@@ -194,6 +286,61 @@ impl<Connector> Engine<Connector>
     }
 }
 
+/// Sends Requests on behalf of a Flow.
+///
+/// The Engine hands one of these to a Flow once it has a valid access token,
+/// so every Cog in the Flow is sent using that same token.
+#[derive(Clone)]
+pub struct Dispatcher {
+    send: Rc<Fn(Request) -> Box<Future<Item = Response, Error = Error>>>,
+}
+
+impl Dispatcher {
+    /// Returns a new Dispatcher that sends Requests using the given function
+    pub fn new<F>(send: F) -> Dispatcher
+        where F: Fn(Request) -> Box<Future<Item = Response, Error = Error>> + 'static
+    {
+        Dispatcher { send: Rc::new(send) }
+    }
+
+    /// Sends a Request
+    pub fn send(&self, req: Request) -> Box<Future<Item = Response, Error = Error>> {
+        (self.send)(req)
+    }
+}
+
+/// A Request sent through a scripted Dispatcher
+#[cfg(test)]
+#[derive(Debug, Clone)]
+pub struct SentRequest {
+    pub uri: String,
+    pub body: String,
+}
+
+/// Returns a Dispatcher that answers with the given responses in order, along with
+/// the requests it was sent
+#[cfg(test)]
+pub fn scripted_dispatcher(responses: Vec<Response>) -> (Dispatcher, Rc<RefCell<Vec<SentRequest>>>) {
+    let sent = Rc::new(RefCell::new(vec![]));
+    let sent_ref = sent.clone();
+    let responses = RefCell::new(responses.into_iter());
+    let dispatcher = Dispatcher::new(move |req: Request| {
+        let uri = req.uri().to_string();
+        let body = req.body().concat2().wait().unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        sent_ref.borrow_mut().push(SentRequest {
+                                       uri: uri.clone(),
+                                       body: body,
+                                   });
+        let resp = responses
+            .borrow_mut()
+            .next()
+            .unwrap_or_else(|| panic!("no response left for {}", uri));
+        Box::new(future::ok(resp))
+    });
+    (dispatcher, sent)
+}
+
 /// Consumes the body and reads it into a String.
 pub fn read_to_string(resp: Response) -> Box<Future<Item = String, Error = Error>> {
     Box::new(read_to_bytes(resp).and_then(|b| {
@@ -217,6 +364,24 @@ pub fn read_to_bytes(resp: Response) -> Box<Future<Item = Vec<u8>, Error = Error
             }))
 }
 
+/// Streams the body of a response chunk by chunk, without buffering all of it
+pub fn stream_body(resp: Response) -> Box<Stream<Item = hyper::Chunk, Error = Error>> {
+    Box::new(resp.body().map_err(|e| Error::HyperError(e)))
+}
+
+/// Reads the Retry-After header of a Response as a delay from now
+pub fn retry_after(resp: &Response) -> Option<StdDuration> {
+    match resp.headers().get::<RetryAfter>() {
+        Some(&RetryAfter::Delay(d)) => Some(d),
+        Some(&RetryAfter::DateTime(date)) => {
+            Some(SystemTime::from(date)
+                     .duration_since(SystemTime::now())
+                     .unwrap_or(StdDuration::from_secs(0)))
+        }
+        None => None,
+    }
+}
+
 #[derive(Debug)]
 pub enum Error {
     CouldNotRetrieveToken,
@@ -229,6 +394,8 @@ pub enum Error {
 pub struct Credentials {
     pub subscription_key: SubscriptionKey,
     #[doc(hidden)]
+    token_uri: Uri,
+    #[doc(hidden)]
     access_token: Option<AccessToken>,
     #[doc(hidden)]
     renewing_token: bool,
@@ -239,11 +406,20 @@ impl Credentials {
     pub fn new(key: SubscriptionKey) -> Credentials {
         Credentials {
             subscription_key: key,
+            token_uri: ISSUE_TOKEN_URI.clone(),
             access_token: None,
             renewing_token: false,
         }
     }
 
+    /// Returns these credentials, getting access tokens from the given URI instead of the default one.
+    ///
+    /// Useful for sovereign clouds, and for testing against a local server.
+    pub fn with_token_uri(mut self, uri: Uri) -> Credentials {
+        self.token_uri = uri;
+        self
+    }
+
     /// Convenience method for determining whether we should renew the token
     pub fn should_renew_token(&self) -> bool {
         let now = now();
@@ -313,4 +489,11 @@ mod tests {
         let work = engine.renew_token();
         assert!(core.run(work).is_ok())
     }
+
+    #[test]
+    fn retry_after_test() {
+        let resp = Response::new().with_header(RetryAfter::Delay(StdDuration::from_secs(5)));
+        assert_eq!(retry_after(&resp), Some(StdDuration::from_secs(5)));
+        assert_eq!(retry_after(&Response::new()), None);
+    }
 }
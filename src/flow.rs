@@ -0,0 +1,430 @@
+//! Holds logic for composing Cogs into multi-step workflows, called Flows.
+//!
+//! A Flow is run by the Engine using a single access token for every Cog it
+//! is made of, and all the errors along the way are unified into flow::Error.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # extern crate hyper;
+//! # extern crate hyper_tls;
+//! # extern crate tokio_core;
+//! # extern crate cogs;
+//! # fn main() {
+//! # use cogs::engine::*;
+//! # use cogs::flow::{self, Flow};
+//! # use hyper_tls;
+//! # use std::env;
+//! # use cogs::cogs::translation::TranslateRequest;
+//! # use cogs::language::LanguageTag;
+//! let mut core = tokio_core::reactor::Core::new().unwrap();
+//! let handle = core.handle();
+//! let client = hyper::Client::configure()
+//!     .connector(hyper_tls::HttpsConnector::new(4, &handle).unwrap())
+//!     .keep_alive(true)
+//!     .build(&handle);
+//! # let sub_key = SubscriptionKey::new(env::var("AZURE_SUBSCRIPTION_KEY").unwrap().as_str());
+//! let credentials = Credentials::new(sub_key);
+//! let engine = Engine::new(credentials, client);
+//! let en = LanguageTag::parse("en").unwrap();
+//! let de = LanguageTag::parse("de").unwrap();
+//! let fr = LanguageTag::parse("fr").unwrap();
+//! let to_german = flow::cog(TranslateRequest {
+//!     text: "Hello",
+//!     from: Some(&en),
+//!     to: &de,
+//!     content_type: None,
+//!     category: None,
+//!     profanity_action: None,
+//! });
+//! let work = to_german.then_cog(move |german: String| {
+//!         flow::cog(TranslateRequest {
+//!             text: german.as_str(),
+//!             from: Some(&de),
+//!             to: &fr,
+//!             content_type: None,
+//!             category: None,
+//!             profanity_action: None,
+//!         })
+//!     })
+//!     .map_item(|french: String| french.to_uppercase());
+//! let result = core.run(engine.run_flow(work)).unwrap();
+//! # }
+//! ```
+use hyper::client::{Request, Response};
+use futures::{Future, Stream};
+use futures::{future, stream};
+use std::marker::PhantomData;
+use engine::{self, Dispatcher};
+use html;
+use cogs::*;
+
+/// Trait representing one or more Cogs that can be run by the Engine as a unit.
+///
+/// Flows are built by wrapping Cogs using `cog` and then composing them with
+/// the combinators provided here.
+pub trait Flow: 'static {
+    /// Item type
+    type Item: 'static;
+
+    /// Starts the Flow, sending all its Requests through the given Dispatcher
+    fn start(self, dispatcher: Dispatcher) -> Box<Future<Item = Self::Item, Error = Error>>;
+
+    /// Uses the Item of this Flow to build the next one, and runs it.
+    ///
+    /// Cogs that borrow their data should be wrapped using `cog` inside the
+    /// closure, since that turns them into a Request right away.
+    fn then_cog<F, B>(self, f: F) -> ThenCog<Self, F>
+        where Self: Sized,
+              F: FnOnce(Self::Item) -> B + 'static,
+              B: Flow
+    {
+        ThenCog { flow: self, f: f }
+    }
+
+    /// Transforms the Item of this Flow
+    fn map_item<F, T>(self, f: F) -> MapItem<Self, F>
+        where Self: Sized,
+              F: FnOnce(Self::Item) -> T + 'static,
+              T: 'static
+    {
+        MapItem { flow: self, f: f }
+    }
+
+    /// Runs this Flow and another one concurrently, returning both Items
+    fn zip<B>(self, other: B) -> Zip<Self, B>
+        where Self: Sized,
+              B: Flow
+    {
+        Zip { a: self, b: other }
+    }
+
+    /// Uses the Item of this Flow to build many more, and runs them concurrently.
+    ///
+    /// The resulting Items are returned in the same order as the Flows.
+    fn fan_out<F, B>(self, f: F) -> FanOut<Self, F>
+        where Self: Sized,
+              F: FnOnce(Self::Item) -> Vec<B> + 'static,
+              B: Flow
+    {
+        FanOut { flow: self, f: f }
+    }
+}
+
+/// Wraps a Cog so that it can be used as a Flow
+pub fn cog<A>(cog: A) -> CogFlow<<A as Cog>::Output>
+    where A: Cog,
+          <A as Cog>::Error: Into<Error>
+{
+    CogFlow {
+        req: cog.into(),
+        output: PhantomData,
+    }
+}
+
+/// A Flow made of a single Cog. See `cog`.
+pub struct CogFlow<Output> {
+    req: Request,
+    output: PhantomData<Output>,
+}
+
+impl<Output> Flow for CogFlow<Output>
+    where Output: Future + From<Result<Response, engine::Error>> + 'static,
+          Output::Item: 'static,
+          Output::Error: Into<Error>
+{
+    type Item = Output::Item;
+
+    fn start(self, dispatcher: Dispatcher) -> Box<Future<Item = Self::Item, Error = Error>> {
+        Box::new(dispatcher
+                     .send(self.req)
+                     .then(|r| Output::from(r))
+                     .map_err(|e| e.into()))
+    }
+}
+
+/// Flow for `Flow::then_cog`
+pub struct ThenCog<A, F> {
+    flow: A,
+    f: F,
+}
+
+impl<A, F, B> Flow for ThenCog<A, F>
+    where A: Flow,
+          F: FnOnce(A::Item) -> B + 'static,
+          B: Flow
+{
+    type Item = B::Item;
+
+    fn start(self, dispatcher: Dispatcher) -> Box<Future<Item = Self::Item, Error = Error>> {
+        let f = self.f;
+        let next_dispatcher = dispatcher.clone();
+        Box::new(self.flow
+                     .start(dispatcher)
+                     .and_then(move |item| f(item).start(next_dispatcher)))
+    }
+}
+
+/// Flow for `Flow::map_item`
+pub struct MapItem<A, F> {
+    flow: A,
+    f: F,
+}
+
+impl<A, F, T> Flow for MapItem<A, F>
+    where A: Flow,
+          F: FnOnce(A::Item) -> T + 'static,
+          T: 'static
+{
+    type Item = T;
+
+    fn start(self, dispatcher: Dispatcher) -> Box<Future<Item = Self::Item, Error = Error>> {
+        Box::new(self.flow.start(dispatcher).map(self.f))
+    }
+}
+
+/// Flow for `Flow::zip`
+pub struct Zip<A, B> {
+    a: A,
+    b: B,
+}
+
+impl<A, B> Flow for Zip<A, B>
+    where A: Flow,
+          B: Flow
+{
+    type Item = (A::Item, B::Item);
+
+    fn start(self, dispatcher: Dispatcher) -> Box<Future<Item = Self::Item, Error = Error>> {
+        let a_f = self.a.start(dispatcher.clone());
+        let b_f = self.b.start(dispatcher);
+        Box::new(a_f.join(b_f))
+    }
+}
+
+/// Flow for `Flow::fan_out`
+pub struct FanOut<A, F> {
+    flow: A,
+    f: F,
+}
+
+impl<A, F, B> Flow for FanOut<A, F>
+    where A: Flow,
+          F: FnOnce(A::Item) -> Vec<B> + 'static,
+          B: Flow
+{
+    type Item = Vec<B::Item>;
+
+    fn start(self, dispatcher: Dispatcher) -> Box<Future<Item = Self::Item, Error = Error>> {
+        let f = self.f;
+        let next_dispatcher = dispatcher.clone();
+        Box::new(self.flow
+                     .start(dispatcher)
+                     .and_then(move |item| {
+                                   let started: Vec<_> = f(item)
+                                       .into_iter()
+                                       .map(|b| b.start(next_dispatcher.clone()))
+                                       .collect();
+                                   future::join_all(started)
+                               }))
+    }
+}
+
+/// Runs the given Flows one after the other and concatenates their Items, in order.
+///
+/// Use `Concat::with_concurrency` to run several of them at the same time.
+pub fn concat<F, T>(flows: Vec<F>) -> Concat<F>
+    where F: Flow<Item = Vec<T>>,
+          T: 'static
+{
+    Concat {
+        flows: flows,
+        concurrency: 1,
+    }
+}
+
+/// Flow for `concat`
+pub struct Concat<F> {
+    flows: Vec<F>,
+    concurrency: usize,
+}
+
+impl<F> Concat<F> {
+    /// Lets at most the given number of Flows run at the same time
+    pub fn with_concurrency(self, concurrency: usize) -> Concat<F> {
+        Concat {
+            flows: self.flows,
+            concurrency: if concurrency == 0 { 1 } else { concurrency },
+        }
+    }
+}
+
+impl<F, T> Flow for Concat<F>
+    where F: Flow<Item = Vec<T>>,
+          T: 'static
+{
+    type Item = Vec<T>;
+
+    fn start(self, dispatcher: Dispatcher) -> Box<Future<Item = Self::Item, Error = Error>> {
+        let items = stream::iter_ok(self.flows)
+            .map(move |f| f.start(dispatcher.clone()))
+            .buffered(self.concurrency)
+            .collect();
+        Box::new(items.map(|items| items.into_iter().flat_map(|i| i).collect()))
+    }
+}
+
+/// Flow error mapping
+#[derive(Debug)]
+pub enum Error {
+    EngineError(engine::Error),
+    TranslationError(translation::Error),
+    TranslatorV3Error(translator_v3::Error),
+    DocumentTranslationError(document_translation::Error),
+    HtmlError(html::Error),
+}
+
+impl From<engine::Error> for Error {
+    fn from(e: engine::Error) -> Self {
+        Error::EngineError(e)
+    }
+}
+
+impl From<translation::Error> for Error {
+    fn from(e: translation::Error) -> Self {
+        Error::TranslationError(e)
+    }
+}
+
+impl From<translator_v3::Error> for Error {
+    fn from(e: translator_v3::Error) -> Self {
+        Error::TranslatorV3Error(e)
+    }
+}
+
+impl From<document_translation::Error> for Error {
+    fn from(e: document_translation::Error) -> Self {
+        Error::DocumentTranslationError(e)
+    }
+}
+
+impl From<html::Error> for Error {
+    fn from(e: html::Error) -> Self {
+        Error::HtmlError(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use url::Url;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use language::LanguageTag;
+
+    /// Dispatcher that "translates" by echoing back the text and target language
+    fn echo_dispatcher(sent: Rc<Cell<usize>>) -> Dispatcher {
+        Dispatcher::new(move |req: Request| {
+            sent.set(sent.get() + 1);
+            let url = Url::parse(&req.uri().to_string()).unwrap();
+            let param = |k: &str| {
+                url.query_pairs()
+                    .find(|&(ref key, _)| key == k)
+                    .map(|(_, v)| v.into_owned())
+                    .unwrap()
+            };
+            let body = format!("<string>{}-{}</string>", param("text"), param("to"));
+            Box::new(future::ok(Response::new().with_body(body)))
+        })
+    }
+
+    fn translate(text: &str, to: &str) -> CogFlow<translation::FutureTranslateResponse> {
+        cog(translation::TranslateRequest {
+                text: text,
+                from: None,
+                to: &LanguageTag::parse(to).unwrap(),
+                content_type: None,
+                category: None,
+                profanity_action: None,
+            })
+    }
+
+    #[test]
+    fn then_cog_and_map_item_test() {
+        let sent = Rc::new(Cell::new(0));
+        let work = translate("hi", "de")
+            .then_cog(|s: String| translate(s.as_str(), "fr"))
+            .map_item(|s: String| s.len());
+        let result = work.start(echo_dispatcher(sent.clone())).wait();
+        assert_eq!(result.unwrap(), "hi-de-fr".len());
+        assert_eq!(sent.get(), 2);
+    }
+
+    #[test]
+    fn zip_test() {
+        let sent = Rc::new(Cell::new(0));
+        let work = translate("hi", "de").zip(translate("bye", "fr"));
+        let result = work.start(echo_dispatcher(sent.clone())).wait();
+        assert_eq!(result.unwrap(),
+                   ("hi-de".to_string(), "bye-fr".to_string()));
+        assert_eq!(sent.get(), 2);
+    }
+
+    #[test]
+    fn fan_out_test() {
+        let sent = Rc::new(Cell::new(0));
+        let work = translate("hi", "de").fan_out(|s: String| {
+                                                      vec!["fr", "ja", "nl"]
+                                                          .into_iter()
+                                                          .map(|to| translate(s.as_str(), to))
+                                                          .collect()
+                                                  });
+        let result = work.start(echo_dispatcher(sent.clone())).wait();
+        assert_eq!(result.unwrap(),
+                   vec!["hi-de-fr".to_string(),
+                        "hi-de-ja".to_string(),
+                        "hi-de-nl".to_string()]);
+        assert_eq!(sent.get(), 4);
+    }
+
+    #[test]
+    fn concat_test() {
+        let in_flight = Rc::new(Cell::new(0));
+        let most_in_flight = Rc::new(Cell::new(0));
+        let (in_flight_ref, most_ref) = (in_flight.clone(), most_in_flight.clone());
+        let dispatcher = Dispatcher::new(move |req: Request| {
+            in_flight_ref.set(in_flight_ref.get() + 1);
+            most_ref.set(::std::cmp::max(most_ref.get(), in_flight_ref.get()));
+            let in_flight_ref = in_flight_ref.clone();
+            let text = req.uri().query().unwrap().split("text=").nth(1).unwrap().to_string();
+            Box::new(future::lazy(move || {
+                                      in_flight_ref.set(in_flight_ref.get() - 1);
+                                      Ok(Response::new().with_body(format!("<string>{}</string>", text)))
+                                  }))
+        });
+        let texts = ["a", "b", "c", "d", "e"];
+        let flows = || {
+            texts
+                .iter()
+                .map(|t| translate(t, "de").map_item(|s: String| vec![s]))
+                .collect()
+        };
+        let result = concat(flows()).start(dispatcher.clone()).wait().unwrap();
+        assert_eq!(result, texts);
+        assert_eq!(most_in_flight.get(), 1);
+        let result = concat(flows()).with_concurrency(2).start(dispatcher).wait().unwrap();
+        assert_eq!(result.len(), 5);
+        assert_eq!(most_in_flight.get(), 2);
+    }
+
+    #[test]
+    fn errors_are_unified_test() {
+        let dispatcher =
+            Dispatcher::new(|_| Box::new(future::err(engine::Error::CouldNotRetrieveToken)));
+        let work = translate("hi", "de").then_cog(|s: String| translate(s.as_str(), "fr"));
+        match work.start(dispatcher).wait() {
+            Err(Error::TranslationError(translation::Error::EngineError(engine::Error::CouldNotRetrieveToken))) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+}
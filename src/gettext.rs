@@ -0,0 +1,834 @@
+//! Holds logic for translating gettext catalogs.
+//!
+//! `parse` reads `.po` and `.pot` files into a Catalog, keeping comments,
+//! flags, contexts, plural forms and obsolete entries so that writing the
+//! Catalog back out with Display gives an equivalent file. PoTranslator fills
+//! in entries that are untranslated or fuzzy, protecting the placeholders of
+//! the formats given by their flags, such as `%s` for "c-format" and `{name}` for
+//! "python-brace-format", and marks them fuzzy for human review.
+use futures::Future;
+use std::fmt;
+use engine::Dispatcher;
+use flow::{self, Flow, CogFlow, Concat};
+use cogs::translation::{TranslateArrayRequest, FutureTranslateArrayResponse};
+use language::LanguageTag;
+use category::CategoryId;
+use placeholder::Protected;
+
+const FUZZY: &'static str = "fuzzy";
+
+/// Flags of formats using printf-style placeholders such as "%s"
+const PRINTF_FORMATS: &'static [&'static str] = &["c-format",
+                                                  "objc-format",
+                                                  "python-format",
+                                                  "php-format",
+                                                  "sh-format",
+                                                  "awk-format",
+                                                  "gcc-internal-format",
+                                                  "gfc-internal-format"];
+
+/// Flags of formats using brace-style placeholders such as "{0}"
+const BRACE_FORMATS: &'static [&'static str] = &["python-brace-format",
+                                                 "csharp-format",
+                                                 "java-format",
+                                                 "rust-format"];
+
+/// Plural-Forms header values of common languages
+const PLURAL_FORMS: &'static [(&'static [&'static str], &'static str)] =
+    &[(&["ja", "ko", "zh", "vi", "th", "id", "ms"], "nplurals=1; plural=0;"),
+      (&["en", "de", "es", "it", "nl", "sv", "da", "nb", "fi", "el", "he", "hu", "bg", "ca", "et", "tr"],
+       "nplurals=2; plural=(n != 1);"),
+      (&["fr"], "nplurals=2; plural=(n > 1);"),
+      (&["ru", "uk", "sr", "hr", "bs"],
+       "nplurals=3; plural=(n%10==1 && n%100!=11 ? 0 : n%10>=2 && n%10<=4 && (n%100<10 || n%100>=20) ? 1 : 2);"),
+      (&["pl"], "nplurals=3; plural=(n==1 ? 0 : n%10>=2 && n%10<=4 && (n%100<10 || n%100>=20) ? 1 : 2);"),
+      (&["cs", "sk"], "nplurals=3; plural=(n==1) ? 0 : (n>=2 && n<=4) ? 1 : 2;")];
+
+/// The entries of a .po or .pot file, in order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Catalog {
+    pub entries: Vec<Entry>,
+}
+
+/// A single message of a Catalog
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Entry {
+    /// Comments starting with "# "
+    pub translator_comments: Vec<String>,
+    /// Comments starting with "#."
+    pub extracted_comments: Vec<String>,
+    /// Comments starting with "#:"
+    pub references: Vec<String>,
+    /// Flags such as "fuzzy" or "c-format", from comments starting with "#,"
+    pub flags: Vec<String>,
+    /// Comments starting with "#|", holding the previous msgid of a fuzzy entry
+    pub previous: Vec<String>,
+    pub msgctxt: Option<String>,
+    pub msgid: String,
+    pub msgid_plural: Option<String>,
+    /// A single string, or one per plural form if msgid_plural is set
+    pub msgstr: Vec<String>,
+    /// Whether or not the entry was commented out with "#~"
+    pub obsolete: bool,
+}
+
+impl Entry {
+    /// Whether or not this is the header entry, holding the metadata of the catalog
+    pub fn is_header(&self) -> bool {
+        self.msgid.is_empty() && self.msgctxt.is_none()
+    }
+
+    pub fn is_fuzzy(&self) -> bool {
+        self.flags.iter().any(|f| f == FUZZY)
+    }
+
+    /// Adds or removes the fuzzy flag
+    pub fn set_fuzzy(&mut self, fuzzy: bool) {
+        if fuzzy && !self.is_fuzzy() {
+            self.flags.insert(0, FUZZY.to_string());
+        } else if !fuzzy {
+            self.flags.retain(|f| f != FUZZY);
+        }
+    }
+
+    /// Whether or not every msgstr is filled in
+    pub fn is_translated(&self) -> bool {
+        !self.msgstr.is_empty() && self.msgstr.iter().all(|s| !s.is_empty())
+    }
+
+    /// Whether or not PoTranslator would translate this entry
+    pub fn needs_translation(&self) -> bool {
+        !self.is_header() && !self.obsolete && (!self.is_translated() || self.is_fuzzy())
+    }
+}
+
+/// Parsing error mapping, holding 1-based line numbers
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    InvalidLine(usize, String),
+    InvalidString(usize),
+    MissingMsgid(usize),
+    MissingMsgstr(usize),
+}
+
+/// The part of an Entry that string continuation lines are appended to
+#[derive(Clone, Copy)]
+enum Field {
+    Msgctxt,
+    Msgid,
+    MsgidPlural,
+    Msgstr(usize),
+}
+
+/// Parses the contents of a .po or .pot file
+pub fn parse(input: &str) -> Result<Catalog, Error> {
+    let mut entries = vec![];
+    let mut entry = Entry::default();
+    let mut has_msgid = false;
+    let mut has_msgstr = false;
+    let mut field: Option<Field> = None;
+    for (index, raw) in input.lines().enumerate() {
+        let line_number = index + 1;
+        let mut line = raw.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut obsolete = false;
+        let mut previous = None;
+        if line.starts_with("#~") {
+            obsolete = true;
+            line = line[2..].trim_left();
+            if line.starts_with('|') {
+                previous = Some(line[1..].trim_left());
+            } else if line.is_empty() {
+                continue;
+            }
+        }
+        let starts_entry = previous.is_some() || line.starts_with('#') || line.starts_with("msgctxt") ||
+                           (line.starts_with("msgid") && !line.starts_with("msgid_plural"));
+        if starts_entry && has_msgid && !has_msgstr {
+            return Err(Error::MissingMsgstr(line_number));
+        }
+        if starts_entry && has_msgstr {
+            entries.push(entry);
+            entry = Entry::default();
+            has_msgid = false;
+            has_msgstr = false;
+            field = None;
+        }
+        entry.obsolete = entry.obsolete || obsolete;
+        if let Some(p) = previous {
+            entry.previous.push(p.to_string());
+            continue;
+        }
+        if line.starts_with('#') {
+            let rest = &line[1..];
+            let text = |s: &str| s.trim_left().to_string();
+            if rest.starts_with(',') {
+                entry.flags.extend(rest[1..].split(',').map(|f| f.trim().to_string()).filter(|f| !f.is_empty()));
+            } else if rest.starts_with('.') {
+                entry.extracted_comments.push(text(&rest[1..]));
+            } else if rest.starts_with(':') {
+                entry.references.push(text(&rest[1..]));
+            } else if rest.starts_with('|') {
+                entry.previous.push(text(&rest[1..]));
+            } else {
+                entry.translator_comments.push(rest.trim_left_matches(' ').to_string());
+            }
+            continue;
+        }
+        if line.starts_with('"') {
+            let s = unquote(line).ok_or(Error::InvalidString(line_number))?;
+            match field {
+                Some(Field::Msgctxt) => entry.msgctxt.get_or_insert_with(String::new).push_str(&s),
+                Some(Field::Msgid) => entry.msgid.push_str(&s),
+                Some(Field::MsgidPlural) => entry.msgid_plural.get_or_insert_with(String::new).push_str(&s),
+                Some(Field::Msgstr(i)) => entry.msgstr[i].push_str(&s),
+                None => return Err(Error::InvalidLine(line_number, raw.to_string())),
+            }
+            continue;
+        }
+        let split = line.find(|c: char| c.is_whitespace()).unwrap_or(line.len());
+        let (keyword, value) = (&line[..split], line[split..].trim());
+        let s = unquote(value).ok_or(Error::InvalidString(line_number))?;
+        let next = if keyword == "msgctxt" {
+            entry.msgctxt = Some(s);
+            Field::Msgctxt
+        } else if keyword == "msgid" {
+            entry.msgid = s;
+            has_msgid = true;
+            Field::Msgid
+        } else if keyword == "msgid_plural" {
+            entry.msgid_plural = Some(s);
+            Field::MsgidPlural
+        } else if keyword == "msgstr" || keyword.starts_with("msgstr[") {
+            if !has_msgid {
+                return Err(Error::MissingMsgid(line_number));
+            }
+            let i = if keyword == "msgstr" {
+                0
+            } else {
+                keyword["msgstr[".len()..]
+                    .trim_right_matches(']')
+                    .parse::<usize>()
+                    .map_err(|_| Error::InvalidLine(line_number, raw.to_string()))?
+            };
+            if entry.msgstr.len() <= i {
+                entry.msgstr.resize(i + 1, String::new());
+            }
+            entry.msgstr[i] = s;
+            has_msgstr = true;
+            Field::Msgstr(i)
+        } else {
+            return Err(Error::InvalidLine(line_number, raw.to_string()));
+        };
+        field = Some(next);
+    }
+    if has_msgid {
+        if !has_msgstr {
+            return Err(Error::MissingMsgstr(input.lines().count()));
+        }
+        entries.push(entry);
+    }
+    Ok(Catalog { entries: entries })
+}
+
+impl Catalog {
+    /// Returns the header entry, if there is one
+    pub fn header(&self) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.is_header() && !e.obsolete)
+    }
+
+    /// Returns the value of a header field, such as "Language"
+    pub fn header_field(&self, name: &str) -> Option<&str> {
+        self.header().and_then(|h| {
+            h.msgstr
+                .get(0)
+                .and_then(|s| {
+                    s.lines().filter_map(|l| split_field(l)).find(|&(k, _)| k == name).map(|(_, v)| v)
+                })
+        })
+    }
+
+    /// Sets the value of a header field, adding the header entry if there is none
+    pub fn set_header_field(&mut self, name: &str, value: &str) {
+        if self.header().is_none() {
+            self.entries.insert(0, Entry { msgstr: vec![String::new()], ..Entry::default() });
+        }
+        let header = self.entries.iter_mut().find(|e| e.is_header() && !e.obsolete).unwrap();
+        if header.msgstr.is_empty() {
+            header.msgstr.push(String::new());
+        }
+        let mut found = false;
+        let mut fields: Vec<String> = header.msgstr[0]
+            .lines()
+            .map(|l| match split_field(l) {
+                     Some((k, _)) if k == name => {
+                         found = true;
+                         format!("{}: {}", name, value)
+                     }
+                     _ => l.to_string(),
+                 })
+            .collect();
+        if !found {
+            fields.push(format!("{}: {}", name, value));
+        }
+        header.msgstr[0] = fields.iter().map(|f| format!("{}\n", f)).collect();
+    }
+
+    /// Returns the number of plural forms given by the Plural-Forms header
+    pub fn nplurals(&self) -> Option<usize> {
+        self.header_field("Plural-Forms").and_then(|forms| {
+            forms
+                .split(';')
+                .filter_map(|p| split_assignment(p))
+                .find(|&(k, _)| k == "nplurals")
+                .and_then(|(_, v)| v.parse().ok())
+        })
+    }
+}
+
+/// Returns the Plural-Forms header value of the given language, if it is known
+pub fn plural_forms(language: &LanguageTag) -> Option<&'static str> {
+    PLURAL_FORMS
+        .iter()
+        .find(|&&(languages, _)| languages.contains(&language.language()))
+        .map(|&(_, forms)| forms)
+}
+
+fn split_field(line: &str) -> Option<(&str, &str)> {
+    line.find(':').map(|i| (line[..i].trim(), line[i + 1..].trim()))
+}
+
+fn split_assignment(part: &str) -> Option<(&str, &str)> {
+    part.find('=').map(|i| (part[..i].trim(), part[i + 1..].trim()))
+}
+
+/// Reads a quoted string, undoing its C escapes.
+///
+/// Octal and hex escapes stand for bytes, which must make up valid UTF-8 along with the rest.
+fn unquote(s: &str) -> Option<String> {
+    if s.len() < 2 || !s.starts_with('"') || !s.ends_with('"') {
+        return None;
+    }
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut chars = s[1..s.len() - 1].chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let byte = match chars.next()? {
+                    'a' => 0x07,
+                    'b' => 0x08,
+                    'f' => 0x0c,
+                    'n' => b'\n',
+                    'r' => b'\r',
+                    't' => b'\t',
+                    'v' => 0x0b,
+                    c @ '\\' | c @ '"' | c @ '\'' | c @ '?' => c as u8,
+                    'x' => {
+                        let mut value = None;
+                        while let Some(digit) = chars.peek().and_then(|c| c.to_digit(16)) {
+                            value = Some(value.unwrap_or(0) * 16 + digit);
+                            chars.next();
+                            if value > Some(0xff) {
+                                return None;
+                            }
+                        }
+                        value? as u8
+                    }
+                    c => {
+                        let mut value = c.to_digit(8)?;
+                        for _ in 0..2 {
+                            match chars.peek().and_then(|c| c.to_digit(8)) {
+                                Some(digit) => value = value * 8 + digit,
+                                None => break,
+                            }
+                            chars.next();
+                        }
+                        if value > 0xff {
+                            return None;
+                        }
+                        value as u8
+                    }
+                };
+                bytes.push(byte);
+            }
+            '"' => return None,
+            _ => {
+                let mut buf = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Quotes a string, escaping what `unquote` would not read back as is
+fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            '\u{7}' => quoted.push_str("\\a"),
+            '\u{8}' => quoted.push_str("\\b"),
+            '\u{b}' => quoted.push_str("\\v"),
+            '\u{c}' => quoted.push_str("\\f"),
+            // Always three digits, so that a digit after it is not read as part of the escape
+            c if c.is_control() && (c as u32) < 0x80 => quoted.push_str(&format!("\\{:03o}", c as u32)),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Writes a keyword and its string, splitting strings with inner line breaks the way gettext does
+fn write_string(f: &mut fmt::Formatter, prefix: &str, keyword: &str, s: &str) -> fmt::Result {
+    let trimmed = s.trim_right_matches('\n');
+    if !trimmed.contains('\n') {
+        return writeln!(f, "{}{} {}", prefix, keyword, quote(s));
+    }
+    writeln!(f, "{}{} \"\"", prefix, keyword)?;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let end = rest.find('\n').map_or(rest.len(), |i| i + 1);
+        writeln!(f, "{}{}", prefix, quote(&rest[..end]))?;
+        rest = &rest[end..];
+    }
+    Ok(())
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in &self.translator_comments {
+            if c.is_empty() {
+                writeln!(f, "#")?;
+            } else {
+                writeln!(f, "# {}", c)?;
+            }
+        }
+        for c in &self.extracted_comments {
+            writeln!(f, "#. {}", c)?;
+        }
+        for r in &self.references {
+            writeln!(f, "#: {}", r)?;
+        }
+        if !self.flags.is_empty() {
+            writeln!(f, "#, {}", self.flags.join(", "))?;
+        }
+        for p in &self.previous {
+            writeln!(f, "{} {}", if self.obsolete { "#~|" } else { "#|" }, p)?;
+        }
+        let prefix = if self.obsolete { "#~ " } else { "" };
+        if let Some(ref ctxt) = self.msgctxt {
+            write_string(f, prefix, "msgctxt", ctxt)?;
+        }
+        write_string(f, prefix, "msgid", &self.msgid)?;
+        match self.msgid_plural {
+            Some(ref plural) => {
+                write_string(f, prefix, "msgid_plural", plural)?;
+                for (i, s) in self.msgstr.iter().enumerate() {
+                    write_string(f, prefix, &format!("msgstr[{}]", i), s)?;
+                }
+            }
+            None => write_string(f, prefix, "msgstr", self.msgstr.get(0).map_or("", |s| s.as_str()))?,
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Catalog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, entry) in self.entries.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the byte ranges of the placeholders of a text, for the formats given by the flags of its entry.
+///
+/// Printf-style placeholders are only looked for with flags such as "c-format", and brace-style
+/// ones with flags such as "python-brace-format", so that text such as "50% off" is left alone.
+/// Escapes such as "%%" and "{{" count as placeholders too, so that they are kept as they are.
+pub fn placeholders(text: &str, flags: &[String]) -> Vec<::std::ops::Range<usize>> {
+    let has_flag = |formats: &[&str]| flags.iter().any(|f| formats.contains(&f.as_str()));
+    let (printf, brace) = (has_flag(PRINTF_FORMATS), has_flag(BRACE_FORMATS));
+    let bytes = text.as_bytes();
+    let mut found = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let end = match bytes[i] {
+            b'%' if printf => printf_end(bytes, i),
+            b'{' if brace && bytes.get(i + 1) == Some(&b'{') => Some(i + 2),
+            b'}' if brace && bytes.get(i + 1) == Some(&b'}') => Some(i + 2),
+            b'{' if brace => brace_end(bytes, i),
+            _ => None,
+        };
+        match end {
+            Some(end) => {
+                found.push(i..end);
+                i = end;
+            }
+            None => i += 1,
+        }
+    }
+    found
+}
+
+/// Returns the end of a printf-style conversion such as "%s", "%1$d", "%-5.2f" or "%(name)s".
+///
+/// The space flag is not supported, so that prose such as "50% off" in a format string is left alone.
+fn printf_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut i = start + 1;
+    if bytes.get(i) == Some(&b'(') {
+        while i < bytes.len() && bytes[i] != b')' {
+            i += 1;
+        }
+        i += 1;
+    }
+    let skip = |i: &mut usize, set: &[u8]| while *i < bytes.len() && set.contains(&bytes[*i]) {
+        *i += 1;
+    };
+    let digits = b"0123456789";
+    let mut j = i;
+    skip(&mut j, digits);
+    if j > i && bytes.get(j) == Some(&b'$') {
+        i = j + 1;
+    }
+    skip(&mut i, b"-+#0'");
+    skip(&mut i, b"0123456789*");
+    if bytes.get(i) == Some(&b'.') {
+        i += 1;
+        skip(&mut i, b"0123456789*");
+    }
+    skip(&mut i, b"hlLqjzt");
+    match bytes.get(i) {
+        Some(c) if b"diouxXeEfFgGcrsap%".contains(c) => Some(i + 1),
+        _ => None,
+    }
+}
+
+/// Returns the end of a brace-style field such as "{}", "{0}" or "{name!r:>10}"
+fn brace_end(bytes: &[u8], start: usize) -> Option<usize> {
+    for (offset, &c) in bytes[start + 1..].iter().enumerate().take(64) {
+        match c {
+            b'}' => return Some(start + 1 + offset + 1),
+            b'{' => return None,
+            _ if (c as char).is_whitespace() => return None,
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Replaces the placeholders of a text with numbered tokens the service leaves alone
+fn protect(text: &str, flags: &[String]) -> Protected {
+    let mut protected = Protected::new();
+    let mut end = 0;
+    for range in placeholders(text, flags) {
+        protected.push_text(&text[end..range.start]);
+        protected.push_placeholder(&text[range.clone()]);
+        end = range.end;
+    }
+    protected.push_text(&text[end..]);
+    protected
+}
+
+/// Translates the entries of a Catalog that are untranslated or fuzzy, see `translate`.
+pub struct PoTranslator<'a> {
+    pub from: Option<&'a LanguageTag>,
+    pub to: &'a LanguageTag,
+    pub category: Option<&'a CategoryId>,
+}
+
+/// Which msgstr strings a translated text goes into
+#[derive(Clone, Copy)]
+enum Form {
+    Singular,
+    Plural,
+}
+
+impl<'a> PoTranslator<'a> {
+    /// Returns a Flow that translates the entries of the given Catalog that need it, in batches.
+    ///
+    /// The Language header is set to the target language, as is Plural-Forms when it is
+    /// missing or a template placeholder and the language is known to `plural_forms`.
+    /// Translated entries are marked fuzzy.
+    pub fn translate(&self, catalog: &Catalog) -> PoTranslation {
+        let mut catalog = catalog.clone();
+        catalog.set_header_field("Language", self.to.as_str());
+        let known_forms = catalog.nplurals().is_some();
+        if let (false, Some(forms)) = (known_forms, plural_forms(self.to)) {
+            catalog.set_header_field("Plural-Forms", forms);
+        }
+        let nplurals = catalog.nplurals().unwrap_or(2);
+        let mut targets = vec![];
+        let mut protected = vec![];
+        for (index, entry) in catalog.entries.iter().enumerate() {
+            if !entry.needs_translation() {
+                continue;
+            }
+            let sources = match entry.msgid_plural {
+                None => vec![(Form::Singular, &entry.msgid)],
+                Some(ref plural) if nplurals == 1 => vec![(Form::Plural, plural)],
+                Some(ref plural) => vec![(Form::Singular, &entry.msgid), (Form::Plural, plural)],
+            };
+            for (form, source) in sources {
+                targets.push((index, form));
+                protected.push(protect(source, &entry.flags));
+            }
+        }
+        let text_refs: Vec<&str> = protected.iter().map(|p| p.text()).collect();
+        let request = TranslateArrayRequest {
+            texts: &text_refs,
+            from: self.from,
+            to: self.to,
+            content_type: None,
+            category: self.category,
+            state: None,
+            profanity_action: None,
+        };
+        let flow = request.batched();
+        PoTranslation {
+            catalog: catalog,
+            nplurals: nplurals,
+            targets: targets,
+            placeholders: protected,
+            flow: flow,
+        }
+    }
+}
+
+/// Flow for `PoTranslator::translate`
+pub struct PoTranslation {
+    catalog: Catalog,
+    nplurals: usize,
+    targets: Vec<(usize, Form)>,
+    placeholders: Vec<Protected>,
+    flow: Concat<CogFlow<FutureTranslateArrayResponse>>,
+}
+
+/// A Catalog whose entries were translated
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranslatedCatalog {
+    pub catalog: Catalog,
+    /// Indices of the entries that were translated
+    pub translated: Vec<usize>,
+    /// Indices of the translated entries whose placeholders did not all come back exactly once
+    pub placeholder_mismatches: Vec<usize>,
+}
+
+impl Flow for PoTranslation {
+    type Item = TranslatedCatalog;
+
+    fn start(self, dispatcher: Dispatcher) -> Box<Future<Item = Self::Item, Error = flow::Error>> {
+        let PoTranslation { mut catalog, nplurals, targets, placeholders, flow } = self;
+        Box::new(flow.start(dispatcher).map(move |translations| {
+            let mut translated = vec![];
+            let mut mismatches = vec![];
+            for ((&(index, form), protected), translation) in targets.iter().zip(&placeholders).zip(translations) {
+                let (text, intact) = protected.restore(&translation.translated_text);
+                let entry = &mut catalog.entries[index];
+                let forms = if entry.msgid_plural.is_some() { nplurals } else { 1 };
+                entry.msgstr.resize(forms, String::new());
+                match form {
+                    Form::Singular => entry.msgstr[0] = text,
+                    Form::Plural if forms == 1 => entry.msgstr[0] = text,
+                    Form::Plural => {
+                        for s in entry.msgstr.iter_mut().skip(1) {
+                            *s = text.clone();
+                        }
+                    }
+                }
+                entry.set_fuzzy(true);
+                if translated.last() != Some(&index) {
+                    translated.push(index);
+                }
+                if !intact && mismatches.last() != Some(&index) {
+                    mismatches.push(index);
+                }
+            }
+            TranslatedCatalog {
+                catalog: catalog,
+                translated: translated,
+                placeholder_mismatches: mismatches,
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::client::Response;
+    use engine::scripted_dispatcher;
+
+    const PO: &'static str = r#"# Translations for the app
+msgid ""
+msgstr ""
+"Project-Id-Version: app 1.0\n"
+"Content-Type: text/plain; charset=UTF-8\n"
+"Plural-Forms: nplurals=INTEGER; plural=EXPRESSION;\n"
+
+#. Shown on the home page
+#: src/main.rs:10
+#, python-format, python-brace-format
+msgid "Hello %(name)s, you have {count} messages"
+msgstr ""
+
+#, python-format
+msgid "Get 50% off, {{today}} only"
+msgstr ""
+
+#: src/main.rs:20
+msgctxt "menu"
+msgid "Open"
+msgstr "Ouvrir"
+
+#, fuzzy, c-format
+#| msgid "One file"
+msgid ""
+"One file\n"
+"was deleted"
+msgid_plural "%d files were deleted"
+msgstr[0] "Un fichier"
+msgstr[1] ""
+
+#~ msgid "Old"
+#~ msgstr "Vieux"
+
+#~| msgid "Older"
+#~ msgid "Oldest"
+#~ msgstr "Le plus vieux"
+"#;
+
+    #[test]
+    fn parse_test() {
+        let catalog = parse(PO).unwrap();
+        assert_eq!(catalog.entries.len(), 7);
+        assert_eq!(catalog.header_field("Project-Id-Version"), Some("app 1.0"));
+        assert_eq!(catalog.entries[0].translator_comments, vec!["Translations for the app"]);
+        let hello = &catalog.entries[1];
+        assert_eq!(hello.flags, vec!["python-format", "python-brace-format"]);
+        assert_eq!(hello.references, vec!["src/main.rs:10"]);
+        assert!(hello.needs_translation());
+        let open = &catalog.entries[3];
+        assert_eq!(open.msgctxt, Some("menu".to_string()));
+        assert!(!open.needs_translation());
+        let deleted = &catalog.entries[4];
+        assert_eq!(deleted.msgid, "One file\nwas deleted");
+        assert_eq!(deleted.previous, vec!["msgid \"One file\""]);
+        assert!(deleted.is_fuzzy());
+        assert_eq!(deleted.msgstr, vec!["Un fichier", ""]);
+        let (old, oldest) = (&catalog.entries[5], &catalog.entries[6]);
+        assert!(old.obsolete && old.previous.is_empty());
+        assert!(oldest.obsolete);
+        assert_eq!(oldest.previous, vec!["msgid \"Older\""]);
+        assert_eq!(oldest.msgid, "Oldest");
+        assert_eq!(parse(PO).unwrap().to_string(), PO);
+        assert_eq!(parse("msgstr \"x\""), Err(Error::MissingMsgid(1)));
+        assert_eq!(parse("msgid \"x"), Err(Error::InvalidString(1)));
+        assert_eq!(parse("msgid \"x\"\nmsgid \"y\"\nmsgstr \"\""), Err(Error::MissingMsgstr(2)));
+        assert_eq!(parse("msgid \"x\"\n"), Err(Error::MissingMsgstr(1)));
+    }
+
+    #[test]
+    fn escapes_test() {
+        assert_eq!(unquote(r#""\a\b\f\v\n\r\t\\\"\'\?""#),
+                   Some("\u{7}\u{8}\u{c}\u{b}\n\r\t\\\"'?".to_string()));
+        assert_eq!(unquote(r#""\033[1m\x1b[0m\0""#), Some("\u{1b}[1m\u{1b}[0m\u{0}".to_string()));
+        assert_eq!(unquote(r#""caf\303\251 \x41\1012""#), Some("café AA2".to_string()));
+        assert_eq!(unquote(r#""\q""#), None);
+        assert_eq!(unquote(r#""\x""#), None);
+        assert_eq!(unquote(r#""\x100""#), None);
+        assert_eq!(unquote(r#""\400""#), None);
+        assert_eq!(unquote(r#""\377""#), None);
+        let text = "\u{7}\u{8}\u{c}\u{b}\u{1b}1\u{0}\u{7f} \\\"é\n";
+        assert_eq!(quote(text), r#""\a\b\f\v\0331\000\177 \\\"é\n""#);
+        assert_eq!(unquote(&quote(text)), Some(text.to_string()));
+    }
+
+    #[test]
+    fn placeholders_test() {
+        let flags = |f: &[&str]| f.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let text = "%s of %1$d, %-5.2f%% and %(name)s {0} {name!r} {{literal}} 100 % {not a field}";
+        let found = |f: &[String]| placeholders(text, f).into_iter().map(|r| &text[r]).collect::<Vec<_>>();
+        assert_eq!(found(&flags(&["c-format", "python-brace-format"])),
+                   vec!["%s", "%1$d", "%-5.2f", "%%", "%(name)s", "{0}", "{name!r}", "{{", "}}"]);
+        assert_eq!(found(&flags(&["python-format"])),
+                   vec!["%s", "%1$d", "%-5.2f", "%%", "%(name)s"]);
+        assert_eq!(found(&flags(&["python-brace-format"])), vec!["{0}", "{name!r}", "{{", "}}"]);
+        assert!(found(&flags(&["no-c-format"])).is_empty());
+        assert!(found(&[]).is_empty());
+
+        let protected = protect("Hi %s, {n} left", &flags(&["c-format", "python-brace-format"]));
+        assert_eq!(protected.text(), "Hi {{0}}, {{1}} left");
+        assert_eq!(protected.restore("{{ 1 }} übrig, Hallo {{0}}"),
+                   ("{n} übrig, Hallo %s".to_string(), true));
+        assert_eq!(protected.restore("Hallo {{0}}"), ("Hallo %s".to_string(), false));
+    }
+
+    #[test]
+    fn percent_prose_test() {
+        let prose = protect("50% off, 100% sure", &[]);
+        assert_eq!(prose.text(), "50% off, 100% sure");
+        let mut flags = vec!["c-format".to_string()];
+        assert_eq!(protect("%d items, 50% off", &flags).text(), "{{0}} items, 50% off");
+        flags[0] = "python-brace-format".to_string();
+        assert_eq!(protect("{0} items, 100% sure", &flags).text(), "{{0}} items, 100% sure");
+    }
+
+    #[test]
+    fn translate_test() {
+        let translated = ["Bonjour {{0}}, vous avez {{1}} messages",
+                          "50% de réduction, {{0}}aujourd'hui{{1}} seulement",
+                          "Un fichier\na été supprimé",
+                          "{{0}} fichiers ont été supprimés"];
+        let items: String = translated
+            .iter()
+            .map(|t| {
+                     format!("<TranslateArrayResponse><From>en</From><TranslatedText>{}</TranslatedText>\
+                              </TranslateArrayResponse>",
+                             t)
+                 })
+            .collect();
+        let resp = format!("<ArrayOfTranslateArrayResponse \
+                            xmlns=\"http://schemas.datacontract.org/2004/07/Microsoft.MT.Web.Service.V2\">\
+                            {}</ArrayOfTranslateArrayResponse>",
+                           items);
+        let (dispatcher, sent) = scripted_dispatcher(vec![Response::new().with_body(resp)]);
+        let fr = LanguageTag::parse("fr").unwrap();
+        let translator = PoTranslator {
+            from: None,
+            to: &fr,
+            category: None,
+        };
+        let catalog = parse(PO).unwrap();
+        let result = translator.translate(&catalog).start(dispatcher).wait().unwrap();
+        let body = &sent.borrow()[0].body;
+        assert!(body.contains("Hello {{0}}, you have {{1}} messages"));
+        assert!(body.contains("Get 50% off, {{0}}today{{1}} only"));
+        assert!(!body.contains("Open"));
+        assert_eq!(result.translated, vec![1, 2, 4]);
+        assert!(result.placeholder_mismatches.is_empty());
+        let translated = result.catalog;
+        assert_eq!(translated.header_field("Language"), Some("fr"));
+        assert_eq!(translated.nplurals(), Some(2));
+        assert_eq!(translated.entries[1].msgstr, vec!["Bonjour %(name)s, vous avez {count} messages"]);
+        assert_eq!(translated.entries[1].flags, vec!["fuzzy", "python-format", "python-brace-format"]);
+        assert_eq!(translated.entries[2].msgstr, vec!["50% de réduction, {{aujourd'hui}} seulement"]);
+        assert_eq!(translated.entries[3].msgstr, vec!["Ouvrir"]);
+        assert_eq!(translated.entries[4].msgstr,
+                   vec!["Un fichier\na été supprimé", "%d fichiers ont été supprimés"]);
+        assert_eq!(parse(&translated.to_string()).unwrap(), translated);
+    }
+}
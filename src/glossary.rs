@@ -0,0 +1,364 @@
+//! Holds logic for enforcing terminology when translating.
+//!
+//! A Glossary holds terms that must not be translated, such as product names,
+//! and terms that must always be translated a certain way. The v3 TranslateRequest
+//! takes a Glossary directly and sends it using the service's dynamic dictionary
+//! markup, `translate_v3` also verifies its results. For other cogs, `translate`
+//! swaps terms for placeholders before translating and puts the right terms back
+//! in afterwards.
+use futures::Future;
+use std::ops::Range;
+use engine::Dispatcher;
+use flow::{self, Flow, CogFlow};
+use cogs::translation::{TranslateRequest, FutureTranslateResponse};
+use cogs::translator_v3;
+use placeholder::Protected;
+
+/// A list of terms and how they must be translated
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct Glossary {
+    entries: Vec<GlossaryEntry>,
+}
+
+/// A single term of a Glossary
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct GlossaryEntry {
+    pub term: String,
+    /// What the term must become, None if it must be left as is
+    pub target: Option<String>,
+}
+
+impl GlossaryEntry {
+    /// Returns what the term must appear as in translations
+    pub fn expected(&self) -> &str {
+        self.target.as_ref().unwrap_or(&self.term)
+    }
+}
+
+/// A term that does not appear in a translation as often as it should
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub term: String,
+    pub expected: String,
+    /// Number of times the term appears in the original text
+    pub occurrences: usize,
+    /// Number of times what was expected appears in the translation
+    pub found: usize,
+}
+
+impl Glossary {
+    /// Returns an empty Glossary
+    pub fn new() -> Glossary {
+        Glossary { entries: vec![] }
+    }
+
+    /// Adds a term that must be left untranslated
+    pub fn do_not_translate<S: Into<String>>(&mut self, term: S) -> &mut Glossary {
+        self.entries.push(GlossaryEntry {
+                              term: term.into(),
+                              target: None,
+                          });
+        self
+    }
+
+    /// Adds a term that must always be translated into the given target
+    pub fn force<S: Into<String>, T: Into<String>>(&mut self, term: S, target: T) -> &mut Glossary {
+        self.entries.push(GlossaryEntry {
+                              term: term.into(),
+                              target: Some(target.into()),
+                          });
+        self
+    }
+
+    pub fn entries(&self) -> &[GlossaryEntry] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Wraps every term found in the text in dynamic dictionary markup
+    pub fn mark_up(&self, text: &str) -> String {
+        let mut marked = String::with_capacity(text.len());
+        let mut end = 0;
+        for (range, index) in self.find_terms(text) {
+            let entry = &self.entries[index];
+            marked.push_str(&text[end..range.start]);
+            marked.push_str(&format!("<mstrans:dictionary translation=\"{}\">{}</mstrans:dictionary>",
+                                     entry.expected().replace('"', "&quot;"),
+                                     entry.term));
+            end = range.end;
+        }
+        marked.push_str(&text[end..]);
+        marked
+    }
+
+    /// Replaces every term found in the text with a placeholder standing for what it must become.
+    ///
+    /// Use `Protected::restore` on the translation to put the terms back in.
+    pub fn protect(&self, text: &str) -> Protected {
+        let mut protected = Protected::new();
+        let mut end = 0;
+        for (range, index) in self.find_terms(text) {
+            protected.push_text(&text[end..range.start]);
+            protected.push_placeholder(self.entries[index].expected());
+            end = range.end;
+        }
+        protected.push_text(&text[end..]);
+        protected
+    }
+
+    /// Checks that every term of the original text appears in the translation as it should
+    pub fn verify(&self, source: &str, translated: &str) -> Result<(), Vec<Violation>> {
+        let violations: Vec<Violation> = self.entries
+            .iter()
+            .filter_map(|entry| {
+                let occurrences = find_term(source, &entry.term).len();
+                let found = find_term(translated, entry.expected()).len();
+                if found < occurrences {
+                    Some(Violation {
+                             term: entry.term.clone(),
+                             expected: entry.expected().to_string(),
+                             occurrences: occurrences,
+                             found: found,
+                         })
+                } else {
+                    None
+                }
+            })
+            .collect();
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    /// Returns a Flow that translates the text of the given request using placeholders,
+    /// and verifies the result
+    pub fn translate(&self, req: TranslateRequest) -> GlossaryTranslation {
+        let protected = self.protect(req.text);
+        let flow = flow::cog(TranslateRequest { text: protected.text(), ..req });
+        GlossaryTranslation {
+            glossary: self.clone(),
+            source: req.text.to_string(),
+            protected: protected,
+            flow: flow,
+        }
+    }
+
+    /// Returns a Flow that sends the given v3 request with this Glossary as dynamic dictionary
+    /// markup, and verifies every translation
+    pub fn translate_v3<'a>(&'a self, req: translator_v3::TranslateRequest<'a>) -> GlossaryV3Translation {
+        GlossaryV3Translation {
+            glossary: self.clone(),
+            sources: req.texts.iter().map(|s| s.to_string()).collect(),
+            flow: flow::cog(translator_v3::TranslateRequest { glossary: Some(self), ..req }),
+        }
+    }
+
+    /// Returns the whole word occurrences of every term along with the index of its entry,
+    /// in order and preferring longer terms when they overlap
+    fn find_terms(&self, text: &str) -> Vec<(Range<usize>, usize)> {
+        let mut found: Vec<(Range<usize>, usize)> = vec![];
+        let mut entries: Vec<(usize, &GlossaryEntry)> = self.entries.iter().enumerate().collect();
+        entries.sort_by(|a, b| b.1.term.len().cmp(&a.1.term.len()));
+        for (index, entry) in entries {
+            for range in find_term(text, &entry.term) {
+                if !found.iter().any(|&(ref r, _)| r.start < range.end && range.start < r.end) {
+                    found.push((range, index));
+                }
+            }
+        }
+        found.sort_by_key(|&(ref r, _)| r.start);
+        found
+    }
+}
+
+/// Returns the byte ranges of the whole word occurrences of a term in a text
+fn find_term(text: &str, term: &str) -> Vec<Range<usize>> {
+    if term.is_empty() {
+        return vec![];
+    }
+    text.match_indices(term)
+        .map(|(i, _)| i..i + term.len())
+        .filter(|r| {
+                    !text[..r.start].chars().next_back().map_or(false, |c| c.is_alphanumeric()) &&
+                    !text[r.end..].chars().next().map_or(false, |c| c.is_alphanumeric())
+                })
+        .collect()
+}
+
+/// Flow for `Glossary::translate`
+pub struct GlossaryTranslation {
+    glossary: Glossary,
+    source: String,
+    protected: Protected,
+    flow: CogFlow<FutureTranslateResponse>,
+}
+
+/// A translation along with the glossary terms it is missing
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckedTranslation {
+    pub text: String,
+    pub violations: Vec<Violation>,
+}
+
+impl Flow for GlossaryTranslation {
+    type Item = CheckedTranslation;
+
+    fn start(self, dispatcher: Dispatcher) -> Box<Future<Item = Self::Item, Error = flow::Error>> {
+        let GlossaryTranslation { glossary, source, protected, flow } = self;
+        Box::new(flow.start(dispatcher).map(move |translated| {
+            let (text, _) = protected.restore(&translated);
+            let violations = glossary.verify(&source, &text).err().unwrap_or_default();
+            CheckedTranslation {
+                text: text,
+                violations: violations,
+            }
+        }))
+    }
+}
+
+/// Flow for `Glossary::translate_v3`
+pub struct GlossaryV3Translation {
+    glossary: Glossary,
+    sources: Vec<String>,
+    flow: CogFlow<translator_v3::FutureTranslateResponse>,
+}
+
+/// Translations of a single text along with the glossary terms each one is missing
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckedTranslationResult {
+    pub result: translator_v3::TranslationResult,
+    /// Violations of each translation, in the same order as `result.translations`
+    pub violations: Vec<Vec<Violation>>,
+}
+
+impl Flow for GlossaryV3Translation {
+    type Item = Vec<CheckedTranslationResult>;
+
+    fn start(self, dispatcher: Dispatcher) -> Box<Future<Item = Self::Item, Error = flow::Error>> {
+        let GlossaryV3Translation { glossary, sources, flow } = self;
+        Box::new(flow.start(dispatcher).map(move |results| {
+            results
+                .into_iter()
+                .zip(sources)
+                .map(|(result, source)| {
+                    let violations = result
+                        .translations
+                        .iter()
+                        .map(|t| glossary.verify(&source, &t.text).err().unwrap_or_default())
+                        .collect();
+                    CheckedTranslationResult {
+                        result: result,
+                        violations: violations,
+                    }
+                })
+                .collect()
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::client::Response;
+    use engine::scripted_dispatcher;
+    use url::Url;
+    use language::LanguageTag;
+
+    fn glossary() -> Glossary {
+        let mut glossary = Glossary::new();
+        glossary
+            .do_not_translate("Cogs")
+            .do_not_translate("Cogs Pro")
+            .force("engine", "Motor");
+        glossary
+    }
+
+    #[test]
+    fn mark_up_test() {
+        assert_eq!(glossary().mark_up("Cogs Pro has an engine, Cogsworth doesn't."),
+                   "<mstrans:dictionary translation=\"Cogs Pro\">Cogs Pro</mstrans:dictionary> has an \
+                    <mstrans:dictionary translation=\"Motor\">engine</mstrans:dictionary>, Cogsworth doesn't.");
+    }
+
+    #[test]
+    fn protect_and_restore_test() {
+        let protected = glossary().protect("Cogs and Cogs Pro have an engine");
+        assert_eq!(protected.text(), "{{0}} and {{1}} have an {{2}}");
+        assert_eq!(protected.restore("{{0}} und {{ 1 }} haben einen {{2}}, {{9}}"),
+                   ("Cogs und Cogs Pro haben einen Motor, {{9}}".to_string(), true));
+    }
+
+    #[test]
+    fn protect_existing_placeholders_test() {
+        let protected = glossary().protect("Cogs prints {{0}}");
+        assert_eq!(protected.text(), "{{0}} prints {{1}}0{{2}}");
+        assert_eq!(protected.restore("{{0}} druckt {{1}}0{{2}}").0, "Cogs druckt {{0}}");
+    }
+
+    #[test]
+    fn verify_test() {
+        let glossary = glossary();
+        let source = "Cogs has an engine";
+        assert_eq!(glossary.verify(source, "Cogs hat einen Motor"), Ok(()));
+        assert_eq!(glossary.verify(source, "Zahnräder hat einen Motor"),
+                   Err(vec![Violation {
+                                term: "Cogs".to_string(),
+                                expected: "Cogs".to_string(),
+                                occurrences: 1,
+                                found: 0,
+                            }]));
+    }
+
+    #[test]
+    fn translate_test() {
+        let resp = Response::new().with_body("<string>{{0}} hat einen Antrieb</string>");
+        let (dispatcher, sent) = scripted_dispatcher(vec![resp]);
+        let (en, de) = (LanguageTag::parse("en").unwrap(), LanguageTag::parse("de").unwrap());
+        let req = TranslateRequest {
+            text: "Cogs has an engine",
+            from: Some(&en),
+            to: &de,
+            content_type: None,
+            category: None,
+            profanity_action: None,
+        };
+        let result = glossary().translate(req).start(dispatcher).wait().unwrap();
+        let url = Url::parse(&sent.borrow()[0].uri).unwrap();
+        let text = url.query_pairs()
+            .find(|&(ref k, _)| k == "text")
+            .map(|(_, v)| v.into_owned());
+        assert_eq!(text, Some("{{0}} has an {{1}}".to_string()));
+        assert_eq!(result.text, "Cogs hat einen Antrieb");
+        assert_eq!(result.violations.len(), 1);
+        assert_eq!(result.violations[0].expected, "Motor");
+    }
+
+    #[test]
+    fn translate_v3_test() {
+        let body = r#"[{"translations": [{"text": "Cogs hat einen Motor", "to": "de"},
+                                         {"text": "Rouages a un moteur", "to": "fr"}]}]"#;
+        let (dispatcher, sent) = scripted_dispatcher(vec![Response::new().with_body(body)]);
+        let texts = ["Cogs has an engine"];
+        let to = [LanguageTag::parse("de").unwrap(), LanguageTag::parse("fr").unwrap()];
+        let glossary = glossary();
+        let req = translator_v3::TranslateRequest {
+            texts: &texts,
+            to: &to,
+            ..Default::default()
+        };
+        let results = glossary.translate_v3(req).start(dispatcher).wait().unwrap();
+        assert!(sent.borrow()[0].uri.contains("/translate?"));
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].result.translations.len(), 2);
+        assert_eq!(results[0].violations[0], vec![]);
+        let terms: Vec<&str> = results[0].violations[1].iter().map(|v| v.term.as_str()).collect();
+        assert_eq!(terms, vec!["Cogs", "engine"]);
+    }
+}
@@ -0,0 +1,696 @@
+//! Holds logic for translating HTML documents without mangling them.
+//!
+//! On top of translating the document itself with TranslateContentType::Html,
+//! this translates the `alt`, `title` and `placeholder` attributes, keeps
+//! regions marked with `translate="no"` or `class="notranslate"` exactly as they
+//! were, and checks that the translation has the same tag structure as the original.
+//! Documents longer than a single text of a TranslateArrayRequest may hold are
+//! split into chunks between tags, preferring the least nested places.
+use futures::Future;
+use std::ops::Range;
+use engine::Dispatcher;
+use flow::{self, Flow, CogFlow, Concat, Zip};
+use cogs::translation::{TranslateArrayRequest, TranslateContentType, FutureTranslateArrayResponse,
+                        ArrayTranslation, MAX_ARRAY_CHARS};
+use language::LanguageTag;
+use category::CategoryId;
+
+/// Attributes whose values are translated
+pub const TRANSLATABLE_ATTRIBUTES: &'static [&'static str] = &["alt", "title", "placeholder"];
+
+const VOID_ELEMENTS: &'static [&'static str] = &["area", "base", "br", "col", "embed", "hr", "img", "input",
+                                                 "link", "meta", "param", "source", "track", "wbr"];
+const RAW_TEXT_ELEMENTS: &'static [&'static str] = &["script", "style"];
+
+/// Translates HTML documents, see `translate`.
+pub struct HtmlTranslator<'a> {
+    pub from: Option<&'a LanguageTag>,
+    pub to: &'a LanguageTag,
+    pub category: Option<&'a CategoryId>,
+}
+
+impl<'a> HtmlTranslator<'a> {
+    /// Returns a Flow that translates the given document along with its translatable attributes
+    pub fn translate(&self, html: &str) -> HtmlTranslation {
+        let doc = Document::parse(html);
+        let values: Vec<String> = doc.attributes
+            .iter()
+            .map(|a| unescape(&html[a.value.clone()]))
+            .collect();
+        let value_refs: Vec<&str> = values.iter().map(|v| v.as_str()).collect();
+        let chunks = doc.chunks(html, MAX_ARRAY_CHARS);
+        let chunk_refs: Vec<&str> = chunks.iter().map(|c| &html[c.clone()]).collect();
+        let document = self.request(&chunk_refs, TranslateContentType::Html).batched();
+        let attributes = self.request(&value_refs, TranslateContentType::Plain).batched();
+        HtmlTranslation {
+            source: html.to_string(),
+            doc: doc,
+            chunks: chunks,
+            flow: document.zip(attributes),
+        }
+    }
+
+    fn request<'t>(&self, texts: &'t [&'t str], content_type: TranslateContentType) -> TranslateArrayRequest<'t>
+        where 'a: 't
+    {
+        TranslateArrayRequest {
+            texts: texts,
+            from: self.from,
+            to: self.to,
+            content_type: Some(content_type),
+            category: self.category,
+            state: None,
+            profanity_action: None,
+        }
+    }
+}
+
+type Translations = Concat<CogFlow<FutureTranslateArrayResponse>>;
+
+/// Flow for `HtmlTranslator::translate`
+pub struct HtmlTranslation {
+    source: String,
+    doc: Document,
+    chunks: Vec<Range<usize>>,
+    flow: Zip<Translations, Translations>,
+}
+
+impl Flow for HtmlTranslation {
+    type Item = String;
+
+    fn start(self, dispatcher: Dispatcher) -> Box<Future<Item = Self::Item, Error = flow::Error>> {
+        let HtmlTranslation { source, doc, chunks, flow } = self;
+        Box::new(flow.start(dispatcher).and_then(move |(document, attributes)| {
+            // Whatever lies between chunks is whitespace, which is kept as it was
+            let mut translated = String::with_capacity(source.len());
+            let mut end = 0;
+            for (chunk, translation) in chunks.iter().zip(document) {
+                translated.push_str(&source[end..chunk.start]);
+                translated.push_str(&translation.translated_text);
+                end = chunk.end;
+            }
+            translated.push_str(&source[end..]);
+            restore(&source, &doc, &translated, attributes).map_err(|e| e.into())
+        }))
+    }
+}
+
+/// HTML error mapping
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    StructureMismatch(Mismatch),
+}
+
+/// First difference between the tag structure of a document and its translation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    /// Index of the tag, counting from the start of the document
+    pub index: usize,
+    /// Tag of the original document, None if it has fewer tags
+    pub expected: Option<String>,
+    /// Tag of the translation, None if it has fewer tags
+    pub found: Option<String>,
+}
+
+/// Checks that a translated document has the same tags, in the same order, as the original
+pub fn verify_structure(source: &str, translated: &str) -> Result<(), Mismatch> {
+    let source_doc = Document::parse(source);
+    let translated_doc = Document::parse(translated);
+    compare(source, &source_doc, translated, &translated_doc)
+}
+
+fn compare(source: &str, source_doc: &Document, translated: &str, translated_doc: &Document) -> Result<(), Mismatch> {
+    let expected = source_doc.tags.iter().map(|t| t.describe(source));
+    let found = translated_doc.tags.iter().map(|t| t.describe(translated));
+    let mut expected = expected.map(Some).chain(::std::iter::repeat(None));
+    let mut found = found.map(Some).chain(::std::iter::repeat(None));
+    let len = source_doc.tags.len().max(translated_doc.tags.len());
+    for index in 0..len {
+        let (e, f) = (expected.next().unwrap(), found.next().unwrap());
+        if e != f {
+            return Err(Mismatch {
+                           index: index,
+                           expected: e,
+                           found: f,
+                       });
+        }
+    }
+    Ok(())
+}
+
+/// Verifies the translated document, then puts back protected regions and translated attributes
+fn restore(source: &str,
+           doc: &Document,
+           translated: &str,
+           attributes: Vec<ArrayTranslation>)
+           -> Result<String, Error> {
+    let translated_doc = Document::parse(translated);
+    compare(source, doc, translated, &translated_doc).map_err(Error::StructureMismatch)?;
+    let mut values = doc.attributes.iter().zip(attributes).peekable();
+    let mut out = String::with_capacity(translated.len());
+    let mut end = 0;
+    let mut index = 0;
+    while index < translated_doc.tags.len() {
+        let tag = &translated_doc.tags[index];
+        out.push_str(&translated[end..tag.range.start]);
+        if let Some(region) = doc.protected.iter().find(|r| r.start_tag == index) {
+            let source_range = doc.tags[region.start_tag].range.start..doc.tags[region.end_tag].range.end;
+            out.push_str(&source[source_range]);
+            end = translated_doc.tags[region.end_tag].range.end;
+            // Attributes in protected regions are never collected, nothing to skip there
+            index = region.end_tag + 1;
+            continue;
+        }
+        let mut tag_end = tag.range.start;
+        while values.peek().map_or(false, |&(a, _)| a.tag == index) {
+            let (attribute, value) = values.next().unwrap();
+            // Same structure, so the same attribute is found at the same tag
+            match translated_doc.tags[index]
+                      .attributes
+                      .iter()
+                      .find(|&&(ref name, ref range)| *name == attribute.name && range.start >= tag_end) {
+                Some(&(_, ref range)) => {
+                    out.push_str(&translated[tag_end..range.start]);
+                    out.push_str(&escape(&value.translated_text));
+                    tag_end = range.end;
+                }
+                None => (),
+            }
+        }
+        out.push_str(&translated[tag_end..tag.range.end]);
+        end = tag.range.end;
+        index += 1;
+    }
+    out.push_str(&translated[end..]);
+    Ok(out)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TagKind {
+    Open,
+    Close,
+    Void,
+}
+
+#[derive(Debug)]
+struct Tag {
+    kind: TagKind,
+    name: String,
+    range: Range<usize>,
+    /// Names of the attributes with a value, and the byte range of that value
+    attributes: Vec<(String, Range<usize>)>,
+}
+
+impl Tag {
+    fn describe(&self, html: &str) -> String {
+        match self.kind {
+            TagKind::Close => format!("</{}>", self.name),
+            _ => {
+                let protected = if self.is_protected(html) { " notranslate" } else { "" };
+                format!("<{}{}>", self.name, protected)
+            }
+        }
+    }
+
+    fn attribute<'h>(&self, html: &'h str, name: &str) -> Option<&'h str> {
+        self.attributes
+            .iter()
+            .find(|&&(ref n, _)| n == name)
+            .map(|&(_, ref r)| &html[r.clone()])
+    }
+
+    fn is_protected(&self, html: &str) -> bool {
+        self.attribute(html, "translate").map_or(false, |v| v.eq_ignore_ascii_case("no")) ||
+        self.attribute(html, "class").map_or(false, |v| v.split_whitespace().any(|c| c == "notranslate"))
+    }
+}
+
+/// A region of the document between a protected tag and its closing tag
+#[derive(Debug)]
+struct Protected {
+    start_tag: usize,
+    end_tag: usize,
+}
+
+/// A translatable attribute value
+#[derive(Debug)]
+struct Attribute {
+    tag: usize,
+    name: String,
+    value: Range<usize>,
+}
+
+/// The tags of an HTML document, along with what needs special care when translating it
+#[derive(Debug)]
+struct Document {
+    tags: Vec<Tag>,
+    protected: Vec<Protected>,
+    attributes: Vec<Attribute>,
+}
+
+impl Document {
+    fn parse(html: &str) -> Document {
+        let tags = tokenize(html);
+        let mut protected = vec![];
+        let mut i = 0;
+        while i < tags.len() {
+            if tags[i].kind != TagKind::Close && tags[i].is_protected(html) {
+                let end = matching_close(&tags, i);
+                protected.push(Protected {
+                                   start_tag: i,
+                                   end_tag: end,
+                               });
+                i = end + 1;
+            } else {
+                i += 1;
+            }
+        }
+        let attributes = tags.iter()
+            .enumerate()
+            .filter(|&(i, _)| !protected.iter().any(|p| p.start_tag <= i && i <= p.end_tag))
+            .flat_map(|(i, tag)| {
+                tag.attributes
+                    .iter()
+                    .filter(|&&(ref name, ref value)| {
+                                TRANSLATABLE_ATTRIBUTES.contains(&name.as_str()) && !html[value.clone()].trim().is_empty()
+                            })
+                    .map(move |&(ref name, ref value)| {
+                             Attribute {
+                                 tag: i,
+                                 name: name.clone(),
+                                 value: value.clone(),
+                             }
+                         })
+            })
+            .collect();
+        Document {
+            tags: tags,
+            protected: protected,
+            attributes: attributes,
+        }
+    }
+}
+
+impl Document {
+    /// Splits a document into chunks of at most max_chars characters, without leading or
+    /// trailing whitespace.
+    ///
+    /// Chunks end right before or after a tag, choosing the least nested place that still
+    /// fits, and never inside a protected region or the contents of a script. Text that is
+    /// too long on its own is split at whitespace, or anywhere as a last resort.
+    fn chunks(&self, html: &str, max_chars: usize) -> Vec<Range<usize>> {
+        let max_chars = max_chars.max(1);
+        // Number of chars before each byte offset that is a char boundary
+        let mut char_index = vec![0; html.len() + 1];
+        for (chars, (i, c)) in html.char_indices().enumerate() {
+            char_index[i] = chars;
+            for b in 1..c.len_utf8() {
+                char_index[i + b] = chars + 1;
+            }
+        }
+        char_index[html.len()] = html.chars().count();
+        // Places a chunk may end at, with how deeply nested they are
+        let mut boundaries: Vec<(usize, usize)> = vec![];
+        let mut depth = 0;
+        let mut text_start = 0;
+        let in_protected = |i: usize, closing: bool| {
+            self.protected.iter().any(|p| if closing {
+                                          p.start_tag <= i && i < p.end_tag
+                                      } else {
+                                          p.start_tag < i && i <= p.end_tag
+                                      })
+        };
+        for (i, tag) in self.tags.iter().enumerate() {
+            let raw_text = i > 0 && self.tags[i - 1].kind == TagKind::Open &&
+                           RAW_TEXT_ELEMENTS.contains(&self.tags[i - 1].name.as_str());
+            if !raw_text && !in_protected(i, false) {
+                push_whitespace(html, text_start..tag.range.start, depth, &mut boundaries);
+                boundaries.push((tag.range.start, depth));
+            }
+            match tag.kind {
+                TagKind::Open => depth += 1,
+                TagKind::Close => depth = depth.saturating_sub(1),
+                TagKind::Void => (),
+            }
+            if !in_protected(i, true) {
+                boundaries.push((tag.range.end, depth));
+            }
+            text_start = tag.range.end;
+        }
+        push_whitespace(html, text_start..html.len(), depth, &mut boundaries);
+        boundaries.push((html.len(), 0));
+
+        let mut chunks = vec![];
+        let mut start = 0;
+        let mut next = 0;
+        while start < html.len() {
+            let limit = char_index[start] + max_chars;
+            while next < boundaries.len() && boundaries[next].0 <= start {
+                next += 1;
+            }
+            let end = boundaries[next..]
+                .iter()
+                .take_while(|&&(pos, _)| char_index[pos] <= limit)
+                .min_by_key(|&&(pos, depth)| (depth, html.len() - pos))
+                .map(|&(pos, _)| pos)
+                .unwrap_or_else(|| {
+                    html[start..]
+                        .char_indices()
+                        .nth(max_chars)
+                        .map_or(html.len(), |(i, _)| start + i)
+                });
+            let chunk = &html[start..end];
+            let chunk_start = start + (chunk.len() - chunk.trim_left().len());
+            let chunk_end = start + chunk.trim_right().len();
+            if chunk_start < chunk_end {
+                chunks.push(chunk_start..chunk_end);
+            }
+            start = end;
+        }
+        chunks
+    }
+}
+
+/// Adds the whitespace of a piece of text to the places a chunk may end at
+fn push_whitespace(html: &str, text: Range<usize>, depth: usize, boundaries: &mut Vec<(usize, usize)>) {
+    let offset = text.start;
+    for (i, c) in html[text].char_indices() {
+        if c.is_whitespace() && offset + i > 0 {
+            boundaries.push((offset + i, depth));
+        }
+    }
+}
+
+/// Returns the index of the tag closing the one at the given index, or the index itself for void tags
+fn matching_close(tags: &[Tag], open: usize) -> usize {
+    if tags[open].kind == TagKind::Void {
+        return open;
+    }
+    let mut depth = 0;
+    for (i, tag) in tags.iter().enumerate().skip(open) {
+        if tag.name != tags[open].name {
+            continue;
+        }
+        match tag.kind {
+            TagKind::Open => depth += 1,
+            TagKind::Close => depth -= 1,
+            TagKind::Void => (),
+        }
+        if depth == 0 {
+            return i;
+        }
+    }
+    // Unclosed, so everything up to the end is protected
+    tags.len() - 1
+}
+
+/// Finds every tag of an HTML document, skipping comments, doctypes and the contents of scripts
+fn tokenize(html: &str) -> Vec<Tag> {
+    let bytes = html.as_bytes();
+    let mut tags = vec![];
+    let mut pos = 0;
+    while let Some(offset) = html[pos..].find('<') {
+        let start = pos + offset;
+        let rest = &html[start..];
+        if rest.starts_with("<!--") {
+            pos = rest.find("-->").map_or(html.len(), |e| start + e + 3);
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            pos = rest.find('>').map_or(html.len(), |e| start + e + 1);
+            continue;
+        }
+        let closing = rest.starts_with("</");
+        let name_start = if closing { start + 2 } else { start + 1 };
+        if !bytes.get(name_start).map_or(false, |b| b.is_ascii_alphabetic()) {
+            pos = start + 1;
+            continue;
+        }
+        let name_end = html[name_start..]
+            .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+            .map_or(html.len(), |e| name_start + e);
+        let name = html[name_start..name_end].to_ascii_lowercase();
+        let (attributes, self_closing, end) = parse_attributes(html, name_end);
+        let kind = if closing {
+            TagKind::Close
+        } else if self_closing || VOID_ELEMENTS.contains(&name.as_str()) {
+            TagKind::Void
+        } else {
+            TagKind::Open
+        };
+        pos = end;
+        if kind == TagKind::Open && RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+            let close = format!("</{}", name);
+            if let Some(e) = html[end..].to_ascii_lowercase().find(&close) {
+                pos = end + e;
+            }
+        }
+        tags.push(Tag {
+                      kind: kind,
+                      name: name,
+                      range: start..end,
+                      attributes: if closing { vec![] } else { attributes },
+                  });
+    }
+    tags
+}
+
+/// Parses the attributes of a tag starting at the given position, returning them
+/// along with whether the tag is self-closing and the position right after it
+fn parse_attributes(html: &str, mut pos: usize) -> (Vec<(String, Range<usize>)>, bool, usize) {
+    let bytes = html.as_bytes();
+    let mut attributes = vec![];
+    loop {
+        while pos < bytes.len() && (bytes[pos] as char).is_whitespace() {
+            pos += 1;
+        }
+        if pos >= bytes.len() {
+            return (attributes, false, html.len());
+        }
+        match bytes[pos] {
+            b'>' => return (attributes, false, pos + 1),
+            b'/' if bytes.get(pos + 1) == Some(&b'>') => return (attributes, true, pos + 2),
+            b'/' => {
+                pos += 1;
+                continue;
+            }
+            _ => (),
+        }
+        let name_start = pos;
+        while pos < bytes.len() && !(bytes[pos] as char).is_whitespace() && !b"=>/".contains(&bytes[pos]) {
+            pos += 1;
+        }
+        let name = html[name_start..pos].to_ascii_lowercase();
+        while pos < bytes.len() && (bytes[pos] as char).is_whitespace() {
+            pos += 1;
+        }
+        if bytes.get(pos) != Some(&b'=') {
+            continue;
+        }
+        pos += 1;
+        while pos < bytes.len() && (bytes[pos] as char).is_whitespace() {
+            pos += 1;
+        }
+        let value = match bytes.get(pos) {
+            Some(&q) if q == b'"' || q == b'\'' => {
+                let value_start = pos + 1;
+                let value_end = html[value_start..]
+                    .find(q as char)
+                    .map_or(html.len(), |e| value_start + e);
+                pos = (value_end + 1).min(html.len());
+                value_start..value_end
+            }
+            _ => {
+                let value_start = pos;
+                while pos < bytes.len() && !(bytes[pos] as char).is_whitespace() && bytes[pos] != b'>' {
+                    pos += 1;
+                }
+                value_start..pos
+            }
+        };
+        attributes.push((name, value));
+    }
+}
+
+const ENTITIES: &'static [(&'static str, &'static str)] = &[("&amp;", "&"),
+                                                          ("&lt;", "<"),
+                                                          ("&gt;", ">"),
+                                                          ("&quot;", "\""),
+                                                          ("&#39;", "'")];
+
+/// Decodes the basic character references of an attribute value
+fn unescape(value: &str) -> String {
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(i) = rest.find('&') {
+        decoded.push_str(&rest[..i]);
+        rest = &rest[i..];
+        match ENTITIES.iter().find(|&&(entity, _)| rest.starts_with(entity)) {
+            Some(&(entity, c)) => {
+                decoded.push_str(c);
+                rest = &rest[entity.len()..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Encodes a translated value so it can be put back in double or single quotes
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::client::{Request, Response};
+    use futures::{future, Stream};
+    use elementtree::Element;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn verify_structure_test() {
+        let source = "<p>Hello <b>world</b><br></p>";
+        assert_eq!(verify_structure(source, "<p>Hallo <b>Welt</b><br></p>"), Ok(()));
+        assert_eq!(verify_structure(source, "<p>Hallo <i>Welt</i><br></p>"),
+                   Err(Mismatch {
+                           index: 1,
+                           expected: Some("<b>".to_string()),
+                           found: Some("<i>".to_string()),
+                       }));
+        assert_eq!(verify_structure(source, "<p>Hallo <b>Welt</b></p>"),
+                   Err(Mismatch {
+                           index: 3,
+                           expected: Some("<br>".to_string()),
+                           found: Some("</p>".to_string()),
+                       }));
+    }
+
+    #[test]
+    fn document_test() {
+        let html = r#"<!-- <b> --><img alt="A &amp; B" src=x.png><script>if (a<b) {}</script>
+            <div translate="no"><span title="Keep">Brand</span></div><input placeholder='Search'/>"#;
+        let doc = Document::parse(html);
+        let tags: Vec<String> = doc.tags.iter().map(|t| t.describe(html)).collect();
+        assert_eq!(tags,
+                   vec!["<img>", "<script>", "</script>", "<div notranslate>", "<span>", "</span>", "</div>",
+                        "<input>"]);
+        assert_eq!(doc.protected.len(), 1);
+        assert_eq!((doc.protected[0].start_tag, doc.protected[0].end_tag), (3, 6));
+        let values: Vec<String> = doc.attributes.iter().map(|a| unescape(&html[a.value.clone()])).collect();
+        assert_eq!(values, vec!["A & B", "Search"]);
+    }
+
+    /// Body of an ArrayOfTranslateArrayResponse holding the given translations
+    fn array_response(translations: &[&str]) -> String {
+        let items: String = translations
+            .iter()
+            .map(|t| {
+                     format!("<TranslateArrayResponse><From>en</From><TranslatedText>{}</TranslatedText>\
+                              </TranslateArrayResponse>",
+                             t.replace("&", "&amp;").replace("<", "&lt;").replace(">", "&gt;"))
+                 })
+            .collect();
+        format!("<ArrayOfTranslateArrayResponse xmlns=\"http://schemas.datacontract.org/2004/07/Microsoft.MT.Web.Service.V2\">{}</ArrayOfTranslateArrayResponse>",
+                items)
+    }
+
+    fn translate(html: &str, document: &'static str) -> Result<String, flow::Error> {
+        let dispatcher = Dispatcher::new(move |req: Request| {
+            let body = req.body().concat2().wait().unwrap();
+            let body = String::from_utf8(body.to_vec()).unwrap();
+            let resp = if body.contains("text/html") {
+                array_response(&[document])
+            } else {
+                array_response(&["Bild", "Suchen"])
+            };
+            Box::new(future::ok(Response::new().with_body(resp)))
+        });
+        let (en, de) = (LanguageTag::parse("en").unwrap(), LanguageTag::parse("de").unwrap());
+        let translator = HtmlTranslator {
+            from: Some(&en),
+            to: &de,
+            category: None,
+        };
+        translator.translate(html).start(dispatcher).wait()
+    }
+
+    #[test]
+    fn translate_test() {
+        let html = r#"<p title="Picture">Hello <span class="x notranslate">Cogs Pro</span></p><input placeholder="Search">"#;
+        let result = translate(html,
+                               r#"<p title="Picture">Hallo <span class="x notranslate">Zahnräder Pro</span></p><input placeholder="Search">"#);
+        assert_eq!(result.unwrap(),
+                   r#"<p title="Bild">Hallo <span class="x notranslate">Cogs Pro</span></p><input placeholder="Suchen">"#);
+    }
+
+    #[test]
+    fn chunks_test() {
+        let html = "<div><p>One two</p> <p>Three four five</p></div>\n<p>Six</p>";
+        let doc = Document::parse(html);
+        let chunks: Vec<&str> = doc.chunks(html, 30).into_iter().map(|c| &html[c]).collect();
+        assert_eq!(chunks, vec!["<div><p>One two</p>", "<p>Three four five</p></div>", "<p>Six</p>"]);
+        let chunks: Vec<&str> = doc.chunks(html, 12).into_iter().map(|c| &html[c]).collect();
+        assert_eq!(chunks.concat().replace(' ', ""), html.replace(|c: char| c.is_whitespace(), ""));
+        assert!(chunks.iter().all(|c| c.chars().count() <= 12), "{:?}", chunks);
+
+        let protected = r#"<p>Hi</p><div translate="no"><b>Brand</b> <i>Name</i></div>"#;
+        let doc = Document::parse(protected);
+        let chunks: Vec<&str> = doc.chunks(protected, 55).into_iter().map(|c| &protected[c]).collect();
+        assert_eq!(chunks, vec!["<p>Hi</p>", r#"<div translate="no"><b>Brand</b> <i>Name</i></div>"#]);
+    }
+
+    #[test]
+    fn translate_long_document_test() {
+        let html: String = (0..2000).map(|i| format!("<p title=\"Picture\">Hello number {}</p>\n", i)).collect();
+        assert!(html.chars().count() > MAX_ARRAY_CHARS * 2);
+        let requests = Rc::new(Cell::new(0));
+        let requests_ref = requests.clone();
+        let dispatcher = Dispatcher::new(move |req: Request| {
+            let body = req.body().concat2().wait().unwrap();
+            let root = Element::from_reader(&body[..]).unwrap();
+            let texts: Vec<String> = root.find("Texts")
+                .unwrap()
+                .children()
+                .map(|t| t.text().to_string())
+                .collect();
+            assert!(texts.iter().map(|t| t.chars().count()).sum::<usize>() <= MAX_ARRAY_CHARS);
+            let translated: Vec<String> = texts
+                .iter()
+                .map(|t| t.replace("Hello", "Hallo").replace("Picture", "Bild"))
+                .collect();
+            let translated_refs: Vec<&str> = translated.iter().map(|t| t.as_str()).collect();
+            requests_ref.set(requests_ref.get() + 1);
+            Box::new(future::ok(Response::new().with_body(array_response(&translated_refs))))
+        });
+        let de = LanguageTag::parse("de").unwrap();
+        let translator = HtmlTranslator {
+            from: None,
+            to: &de,
+            category: None,
+        };
+        let result = translator.translate(&html).start(dispatcher).wait().unwrap();
+        assert_eq!(result, html.replace("Hello", "Hallo").replace("Picture", "Bild"));
+        assert!(requests.get() > 3);
+    }
+
+    #[test]
+    fn translate_mismatch_test() {
+        let html = r#"<p title="Picture">Hello</p><input placeholder="Search">"#;
+        match translate(html, r#"<p title="Picture">Hallo<input placeholder="Search">"#) {
+            Err(flow::Error::HtmlError(Error::StructureMismatch(ref m))) if m.index == 1 => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,248 @@
+//! Holds the LanguageTag type used by Cogs to identify languages.
+//!
+//! Tags are parsed from BCP-47 strings such as "en", "zh-Hans" or "pt-BR" and
+//! normalized so that equal languages compare equal. The legacy "zh-CHS" and
+//! "zh-CHT" codes of the v2 Translator API are understood too.
+use std::fmt;
+use std::str::FromStr;
+#[cfg(feature = "serialization")]
+use serde::{de, Serialize, Serializer, Deserialize, Deserializer};
+
+/// Translation languages supported by the service at the time of writing.
+///
+/// Use a LanguageCatalog to get the current list.
+pub const EMBEDDED_LANGUAGES: &'static [&'static str] =
+    &["af", "ar", "bg", "bn", "bs", "ca", "cs", "cy", "da", "de", "el", "en", "es", "et", "fa", "fi", "fil",
+      "fj", "fr", "ga", "he", "hi", "hr", "ht", "hu", "id", "is", "it", "ja", "kn", "ko", "lt", "lv", "mg",
+      "mi", "ml", "ms", "mt", "mww", "nb", "nl", "otq", "pa", "pl", "pt", "pt-PT", "ro", "ru", "sk", "sl",
+      "sm", "sr-Cyrl", "sr-Latn", "sv", "sw", "ta", "te", "th", "tlh-Latn", "tlh-Piqd", "to", "tr", "ty",
+      "uk", "ur", "vi", "yua", "yue", "zh-Hans", "zh-Hant"];
+
+/// Deprecated language subtags and what they became
+const LANGUAGE_ALIASES: &'static [(&'static str, &'static str)] = &[("in", "id"),
+                                                                  ("iw", "he"),
+                                                                  ("ji", "yi"),
+                                                                  ("no", "nb"),
+                                                                  ("tl", "fil")];
+
+/// Legacy Chinese subtags of the v2 API and the scripts they stand for
+const V2_SCRIPT_ALIASES: &'static [(&'static str, &'static str)] = &[("CHS", "Hans"), ("CHT", "Hant")];
+
+/// A normalized BCP-47 language tag
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LanguageTag {
+    tag: String,
+    language: String,
+    script: Option<String>,
+    region: Option<String>,
+    variants: Vec<String>,
+}
+
+/// Language tag error mapping
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    Empty,
+    InvalidSubtag(String),
+    Unsupported(String),
+}
+
+impl LanguageTag {
+    /// Parses and normalizes a tag, accepting both '-' and '_' as separators
+    pub fn parse(s: &str) -> Result<LanguageTag, Error> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(Error::Empty);
+        }
+        let mut subtags = s.split(|c| c == '-' || c == '_').peekable();
+        let first = subtags.next().unwrap();
+        if first.len() < 2 || first.len() > 8 || !first.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(Error::InvalidSubtag(first.to_string()));
+        }
+        let first = first.to_ascii_lowercase();
+        let language = LANGUAGE_ALIASES
+            .iter()
+            .find(|&&(old, _)| old == first)
+            .map_or(first.clone(), |&(_, new)| new.to_string());
+
+        let mut script = None;
+        if let Some(&next) = subtags.peek() {
+            let v2_alias = V2_SCRIPT_ALIASES
+                .iter()
+                .find(|&&(code, _)| language == "zh" && next.eq_ignore_ascii_case(code));
+            if let Some(&(_, s)) = v2_alias {
+                script = Some(s.to_string());
+                subtags.next();
+            } else if next.len() == 4 && next.chars().all(|c| c.is_ascii_alphabetic()) {
+                script = Some(title_case(next));
+                subtags.next();
+            }
+        }
+        let mut region = None;
+        if let Some(&next) = subtags.peek() {
+            if (next.len() == 2 && next.chars().all(|c| c.is_ascii_alphabetic())) ||
+               (next.len() == 3 && next.chars().all(|c| c.is_ascii_digit())) {
+                region = Some(next.to_ascii_uppercase());
+                subtags.next();
+            }
+        }
+        let mut variants = vec![];
+        for subtag in subtags {
+            if subtag.is_empty() || subtag.len() > 8 || !subtag.chars().all(|c| c.is_ascii_alphanumeric()) {
+                return Err(Error::InvalidSubtag(subtag.to_string()));
+            }
+            variants.push(subtag.to_ascii_lowercase());
+        }
+        Ok(LanguageTag::from_parts(language, script, region, variants))
+    }
+
+    fn from_parts(language: String,
+                  script: Option<String>,
+                  region: Option<String>,
+                  variants: Vec<String>)
+                  -> LanguageTag {
+        let mut tag = language.clone();
+        for part in script.iter().chain(region.iter()).chain(variants.iter()) {
+            tag.push('-');
+            tag.push_str(part);
+        }
+        LanguageTag {
+            tag: tag,
+            language: language,
+            script: script,
+            region: region,
+            variants: variants,
+        }
+    }
+
+    /// Parses a tag and checks that it is in the given list of supported languages
+    pub fn parse_supported<S: AsRef<str>>(s: &str, supported: &[S]) -> Result<LanguageTag, Error> {
+        let tag = LanguageTag::parse(s)?;
+        if tag.is_supported_by(supported) {
+            Ok(tag)
+        } else {
+            Err(Error::Unsupported(tag.tag))
+        }
+    }
+
+    /// Whether or not the given list of languages holds this one, or this one without its region
+    pub fn is_supported_by<S: AsRef<str>>(&self, supported: &[S]) -> bool {
+        supported
+            .iter()
+            .filter_map(|s| LanguageTag::parse(s.as_ref()).ok())
+            .any(|s| s == *self || s == self.without_region())
+    }
+
+    /// Returns this tag without its region, such as "pt" for "pt-BR"
+    pub fn without_region(&self) -> LanguageTag {
+        LanguageTag::from_parts(self.language.clone(),
+                                self.script.clone(),
+                                None,
+                                self.variants.clone())
+    }
+
+    /// Returns the normalized tag, as understood by the v3 API
+    pub fn as_str(&self) -> &str {
+        &self.tag
+    }
+
+    /// Returns the code the v2 API uses for this language, which differs for Chinese
+    pub fn v2_code(&self) -> &str {
+        match (self.language.as_str(), self.script.as_ref().map(|s| s.as_str()), &self.region) {
+            ("zh", Some("Hans"), &None) => "zh-CHS",
+            ("zh", Some("Hant"), &None) => "zh-CHT",
+            _ => &self.tag,
+        }
+    }
+
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    pub fn script(&self) -> Option<&str> {
+        self.script.as_ref().map(|s| s.as_str())
+    }
+
+    pub fn region(&self) -> Option<&str> {
+        self.region.as_ref().map(|s| s.as_str())
+    }
+}
+
+impl FromStr for LanguageTag {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        LanguageTag::parse(s)
+    }
+}
+
+impl fmt::Display for LanguageTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.tag)
+    }
+}
+
+/// Serialized as the normalized tag
+#[cfg(feature = "serialization")]
+impl Serialize for LanguageTag {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.tag)
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl<'de> Deserialize<'de> for LanguageTag {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        LanguageTag::parse(&s).map_err(|e| de::Error::custom(format!("invalid language tag '{}': {:?}", s, e)))
+    }
+}
+
+fn title_case(s: &str) -> String {
+    s.chars()
+        .enumerate()
+        .map(|(i, c)| if i == 0 {
+                 c.to_ascii_uppercase()
+             } else {
+                 c.to_ascii_lowercase()
+             })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let tag = LanguageTag::parse("ZH_hant-tw").unwrap();
+        assert_eq!(tag.as_str(), "zh-Hant-TW");
+        assert_eq!(tag.language(), "zh");
+        assert_eq!(tag.script(), Some("Hant"));
+        assert_eq!(tag.region(), Some("TW"));
+        assert_eq!(LanguageTag::parse("es-419").unwrap().region(), Some("419"));
+        assert_eq!(LanguageTag::parse("iw").unwrap().as_str(), "he");
+        assert_eq!(LanguageTag::parse(" "), Err(Error::Empty));
+        assert_eq!(LanguageTag::parse("e"), Err(Error::InvalidSubtag("e".to_string())));
+        assert_eq!(LanguageTag::parse("en-"), Err(Error::InvalidSubtag("".to_string())));
+    }
+
+    #[test]
+    fn v2_alias_test() {
+        let chs = LanguageTag::parse("zh-CHS").unwrap();
+        assert_eq!(chs, LanguageTag::parse("zh-Hans").unwrap());
+        assert_eq!(chs.as_str(), "zh-Hans");
+        assert_eq!(chs.v2_code(), "zh-CHS");
+        assert_eq!(LanguageTag::parse("zh-cht").unwrap().v2_code(), "zh-CHT");
+        assert_eq!(LanguageTag::parse("pt-PT").unwrap().v2_code(), "pt-PT");
+    }
+
+    #[test]
+    fn supported_test() {
+        assert!(LanguageTag::parse_supported("en-US", EMBEDDED_LANGUAGES).is_ok());
+        assert!(LanguageTag::parse_supported("zh-CHS", EMBEDDED_LANGUAGES).is_ok());
+        assert!(LanguageTag::parse_supported("pt-pt", EMBEDDED_LANGUAGES).is_ok());
+        assert_eq!(LanguageTag::parse_supported("ger", EMBEDDED_LANGUAGES),
+                   Err(Error::Unsupported("ger".to_string())));
+        assert!(!LanguageTag::parse("zh").unwrap().is_supported_by(EMBEDDED_LANGUAGES));
+    }
+}
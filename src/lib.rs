@@ -17,6 +17,7 @@
 //! # use hyper_tls;
 //! # use std::env;
 //! # use cogs::cogs::translation::TranslateRequest;
+//! # use cogs::language::LanguageTag;
 //! let mut core = tokio_core::reactor::Core::new().unwrap();
 //! let handle = core.handle();
 //! let client = hyper::Client::configure()
@@ -26,12 +27,14 @@
 //! # let sub_key = SubscriptionKey::new(env::var("AZURE_SUBSCRIPTION_KEY").unwrap().as_str());
 //! let credentials = Credentials::new(sub_key);
 //! let engine = Engine::new(credentials, client);
+//! let (en, de) = (LanguageTag::parse("en").unwrap(), LanguageTag::parse("de").unwrap());
 //! let translate_req = TranslateRequest {
 //!     text: "Hello",
-//!     from: Some("en"),
-//!     to: "de",
+//!     from: Some(&en),
+//!     to: &de,
 //!     content_type: None,
 //!     category: None,
+//!     profanity_action: None,
 //! };
 //! let work = engine.run(translate_req);
 //! // TODO: get a sandbox key so this actually comes back as "Hallo"
@@ -46,13 +49,29 @@ extern crate core;
 #[macro_use]
 extern crate lazy_static;
 extern crate elementtree;
+extern crate xml;
 extern crate url;
+extern crate tokio_core;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
 pub mod engine;
 pub mod cogs;
+pub mod flow;
+pub mod operation;
+pub mod paging;
+pub mod long_text;
+pub mod html;
+pub mod glossary;
+pub mod placeholder;
+pub mod memory;
+pub mod language;
+pub mod category;
+pub mod gettext;
+pub mod xliff;
 
 pub use cogs::*;
 
-#[cfg(test)]
-extern crate tokio_core;
 #[cfg(test)]
 extern crate hyper_tls;
@@ -0,0 +1,331 @@
+//! Holds logic for translating texts that are too long for a single request.
+//!
+//! Texts are split into chunks at paragraph and sentence boundaries, the
+//! chunks are translated concurrently, and the translations are put back
+//! together with the original whitespace in between.
+use hyper::client::Request;
+use futures::{Future, Stream};
+use futures::stream;
+use std::ops::Range;
+use engine::Dispatcher;
+use flow::{self, Flow};
+use cogs::translation::{self, TranslateRequest, FutureTranslateResponse};
+use language::LanguageTag;
+use category::CategoryId;
+
+/// Default maximum number of characters in a chunk, keeping the query string of a request short
+pub const DEFAULT_MAX_CHUNK_CHARS: usize = 1000;
+/// Default number of chunks translated at the same time
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Translates texts of any length, see `translate`.
+pub struct LongTextTranslator<'a> {
+    pub from: Option<&'a LanguageTag>,
+    pub to: &'a LanguageTag,
+    pub category: Option<&'a CategoryId>,
+    pub max_chunk_chars: usize,
+    pub concurrency: usize,
+}
+
+impl<'a> LongTextTranslator<'a> {
+    /// Returns a translator into the given language using the default limits
+    pub fn new(to: &'a LanguageTag) -> LongTextTranslator<'a> {
+        LongTextTranslator {
+            from: None,
+            to: to,
+            category: None,
+            max_chunk_chars: DEFAULT_MAX_CHUNK_CHARS,
+            concurrency: DEFAULT_CONCURRENCY,
+        }
+    }
+
+    /// Returns the byte ranges of the chunks the given text would be translated in.
+    ///
+    /// Chunks never span a paragraph break, and are only split inside a sentence
+    /// when the sentence alone is longer than max_chunk_chars.
+    pub fn chunks(&self, text: &str) -> Vec<Range<usize>> {
+        let max = if self.max_chunk_chars == 0 { 1 } else { self.max_chunk_chars };
+        let mut chunks = vec![];
+        for paragraph in paragraphs(text) {
+            let mut current: Option<(Range<usize>, usize)> = None;
+            let pieces = sentences(text, paragraph)
+                .into_iter()
+                .flat_map(|s| split_long(text, s, max));
+            for piece in pieces {
+                let added = text[current.as_ref().map_or(piece.start, |c| c.0.end)..piece.end]
+                    .chars()
+                    .count();
+                current = match current {
+                    Some((c, chars)) if chars + added <= max => Some((c.start..piece.end, chars + added)),
+                    Some((c, _)) => {
+                        chunks.push(c);
+                        Some((piece.clone(), piece_chars(text, &piece)))
+                    }
+                    None => Some((piece.clone(), added)),
+                };
+            }
+            chunks.extend(current.map(|c| c.0));
+        }
+        chunks
+    }
+
+    /// Returns a Flow that translates the given text chunk by chunk.
+    ///
+    /// At most `concurrency` chunks are in flight at once. Chunks that fail to
+    /// translate are left as they are and reported in LongTranslation::failures.
+    pub fn translate(&self, text: &str) -> LongTextTranslation {
+        let chunks = self.chunks(text);
+        let requests = chunks
+            .iter()
+            .map(|c| {
+                     TranslateRequest {
+                             text: &text[c.clone()],
+                             from: self.from,
+                             to: self.to,
+                             content_type: None,
+                             category: self.category,
+                             profanity_action: None,
+                         }
+                         .into()
+                 })
+            .collect();
+        LongTextTranslation {
+            text: text.to_string(),
+            chunks: chunks,
+            requests: requests,
+            concurrency: if self.concurrency == 0 { 1 } else { self.concurrency },
+        }
+    }
+}
+
+/// Flow for `LongTextTranslator::translate`
+pub struct LongTextTranslation {
+    text: String,
+    chunks: Vec<Range<usize>>,
+    requests: Vec<Request>,
+    concurrency: usize,
+}
+
+/// Result of translating a long text
+#[derive(Debug)]
+pub struct LongTranslation {
+    /// The translated text, holding the original of any chunk that failed
+    pub text: String,
+    pub failures: Vec<ChunkFailure>,
+}
+
+impl LongTranslation {
+    /// Whether or not every chunk was translated
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// A chunk that could not be translated
+#[derive(Debug)]
+pub struct ChunkFailure {
+    /// Index of the chunk
+    pub index: usize,
+    /// Byte range of the chunk in the original text
+    pub source: Range<usize>,
+    pub error: translation::Error,
+}
+
+impl Flow for LongTextTranslation {
+    type Item = LongTranslation;
+
+    fn start(self, dispatcher: Dispatcher) -> Box<Future<Item = Self::Item, Error = flow::Error>> {
+        let LongTextTranslation { text, chunks, requests, concurrency } = self;
+        let translated = stream::iter_ok(requests)
+            .map(move |req| {
+                     dispatcher
+                         .send(req)
+                         .then(|r| FutureTranslateResponse::from(r))
+                         .then(|r| Ok(r))
+                 })
+            .buffered(concurrency)
+            .collect();
+        Box::new(translated.map(move |results| assemble(&text, &chunks, results)))
+    }
+}
+
+/// Puts the translated chunks back together with the whitespace around them
+fn assemble(text: &str,
+            chunks: &[Range<usize>],
+            results: Vec<Result<String, translation::Error>>)
+            -> LongTranslation {
+    let mut translated = String::with_capacity(text.len());
+    let mut failures = vec![];
+    let mut end = 0;
+    for (index, (chunk, result)) in chunks.iter().zip(results).enumerate() {
+        translated.push_str(&text[end..chunk.start]);
+        match result {
+            Ok(t) => translated.push_str(&t),
+            Err(e) => {
+                translated.push_str(&text[chunk.clone()]);
+                failures.push(ChunkFailure {
+                                  index: index,
+                                  source: chunk.clone(),
+                                  error: e,
+                              });
+            }
+        }
+        end = chunk.end;
+    }
+    translated.push_str(&text[end..]);
+    LongTranslation {
+        text: translated,
+        failures: failures,
+    }
+}
+
+fn piece_chars(text: &str, piece: &Range<usize>) -> usize {
+    text[piece.clone()].chars().count()
+}
+
+/// Returns the byte ranges of the maximal runs of whitespace within the given range
+fn whitespace_runs(text: &str, within: Range<usize>) -> Vec<Range<usize>> {
+    let mut runs = vec![];
+    let mut start = None;
+    for (i, c) in text[within.clone()].char_indices() {
+        let i = within.start + i;
+        match (c.is_whitespace(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                runs.push(s..i);
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    if let Some(s) = start {
+        runs.push(s..within.end);
+    }
+    runs
+}
+
+/// Returns the byte ranges of the paragraphs of a text, without surrounding whitespace
+fn paragraphs(text: &str) -> Vec<Range<usize>> {
+    let breaks = whitespace_runs(text, 0..text.len())
+        .into_iter()
+        .filter(|r| r.start == 0 || r.end == text.len() || text[r.clone()].matches('\n').count() >= 2);
+    complement(0..text.len(), breaks)
+}
+
+/// Returns the byte ranges of the sentences of a paragraph
+fn sentences(text: &str, paragraph: Range<usize>) -> Vec<Range<usize>> {
+    let breaks = whitespace_runs(text, paragraph.clone())
+        .into_iter()
+        .filter(|r| {
+                    text[..r.start]
+                        .trim_right_matches(|c| CLOSING_PUNCTUATION.contains(&c))
+                        .ends_with(|c| SENTENCE_TERMINATORS.contains(&c))
+                });
+    complement(paragraph, breaks)
+}
+
+/// Splits a range longer than max characters at whitespace, and words longer than max anywhere
+fn split_long(text: &str, range: Range<usize>, max: usize) -> Vec<Range<usize>> {
+    if piece_chars(text, &range) <= max {
+        return vec![range];
+    }
+    let words = complement(range.clone(), whitespace_runs(text, range));
+    let mut pieces = vec![];
+    for word in words {
+        let mut start = word.start;
+        for (count, (i, _)) in text[word.clone()].char_indices().enumerate() {
+            if count > 0 && count % max == 0 {
+                pieces.push(start..word.start + i);
+                start = word.start + i;
+            }
+        }
+        pieces.push(start..word.end);
+    }
+    pieces
+}
+
+/// Returns the non-empty parts of a range that are not covered by the given ordered gaps
+fn complement<I>(range: Range<usize>, gaps: I) -> Vec<Range<usize>>
+    where I: IntoIterator<Item = Range<usize>>
+{
+    let mut parts = vec![];
+    let mut start = range.start;
+    for gap in gaps {
+        if gap.start > start {
+            parts.push(start..gap.start);
+        }
+        start = gap.end;
+    }
+    if range.end > start {
+        parts.push(start..range.end);
+    }
+    parts
+}
+
+const SENTENCE_TERMINATORS: &'static [char] = &['.', '!', '?', '。', '！', '？', '…'];
+const CLOSING_PUNCTUATION: &'static [char] = &['"', '\'', ')', ']', '»', '”', '’', '」'];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::client::Response;
+    use hyper::StatusCode;
+    use futures::future;
+    use url::Url;
+
+    fn chunk_texts<'t>(translator: &LongTextTranslator, text: &'t str) -> Vec<&'t str> {
+        translator
+            .chunks(text)
+            .into_iter()
+            .map(|c| &text[c])
+            .collect()
+    }
+
+    #[test]
+    fn chunks_test() {
+        let de = LanguageTag::parse("de").unwrap();
+        let translator = LongTextTranslator {
+            max_chunk_chars: 12,
+            ..LongTextTranslator::new(&de)
+        };
+        let text = "  One. Two!  Three?\n\nFour five six seven.\n \n Eightnineteneleven ";
+        assert_eq!(chunk_texts(&translator, text),
+                   vec!["One. Two!", "Three?", "Four five", "six seven.", "Eightnineten", "eleven"]);
+    }
+
+    #[test]
+    fn translate_test() {
+        let de = LanguageTag::parse("de").unwrap();
+        let translator = LongTextTranslator {
+            max_chunk_chars: 16,
+            concurrency: 2,
+            ..LongTextTranslator::new(&de)
+        };
+        let dispatcher = Dispatcher::new(|req: Request| {
+            let url = Url::parse(&req.uri().to_string()).unwrap();
+            let text = url.query_pairs()
+                .find(|&(ref k, _)| k == "text")
+                .map(|(_, v)| v.into_owned())
+                .unwrap();
+            let resp = if text.contains("fail") {
+                Response::new()
+                    .with_status(StatusCode::BadRequest)
+                    .with_body("Bad text")
+            } else {
+                Response::new().with_body(format!("<string>{}</string>", text.to_uppercase()))
+            };
+            Box::new(future::ok(resp))
+        });
+        let text = "One. Two!\n\nThis will fail.  Three?\n";
+        let result = translator.translate(text).start(dispatcher).wait().unwrap();
+        assert_eq!(result.text, "ONE. TWO!\n\nThis will fail.  THREE?\n");
+        assert!(!result.is_complete());
+        assert_eq!(result.failures.len(), 1);
+        assert_eq!(result.failures[0].index, 1);
+        assert_eq!(&text[result.failures[0].source.clone()], "This will fail.");
+        match result.failures[0].error {
+            translation::Error::ServiceError(StatusCode::BadRequest, ref message) if message == "Bad text" => (),
+            ref other => panic!("Unexpected error {:?}", other),
+        }
+    }
+}
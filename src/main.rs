@@ -5,16 +5,28 @@ extern crate hyper;
 extern crate hyper_tls;
 extern crate native_tls;
 
-use std::io;
-use clap::{Arg, App};
+use std::io::{self, Read, Write};
+use std::fs::File;
+use clap::{Arg, App, ArgMatches, SubCommand};
 use cogs::engine::*;
 use cogs::translation::*;
+use cogs::translator_v3;
+use cogs::language::{LanguageTag, EMBEDDED_LANGUAGES};
+use cogs::category::{self, CategoryId};
+use cogs::gettext::{self, PoTranslator};
+use cogs::xliff::{Document, XliffTranslator};
 use hyper::client::*;
 
 const FROM_KEY: &'static str = "from";
 const TO_KEY: &'static str = "to";
 const SUBSCRIPTION_KEY: &'static str = "subscription-key";
+const CATEGORY_KEY: &'static str = "category";
 const REPL_MODE_KEY: &'static str = "repl";
+const PO_COMMAND: &'static str = "po";
+const XLIFF_COMMAND: &'static str = "xliff";
+const INPUT_KEY: &'static str = "input";
+const OUTPUT_KEY: &'static str = "output";
+const DROP_COMMENTS_KEY: &'static str = "drop-comments";
 const AZURE_SUBSCRIPTION_KEY: &'static str = "AZURE_SUBSCRIPTION_KEY";
 
 const GREET: &'static str = r#"
@@ -65,12 +77,51 @@ fn main() {
                  .number_of_values(1)
                  .help(sub_key_help.as_ref())
                  .required(false))
+        .arg(Arg::with_name(CATEGORY_KEY)
+                 .short("c")
+                 .long(CATEGORY_KEY)
+                 .takes_value(true)
+                 .number_of_values(1)
+                 .help("Category of the Custom Translator model to translate with. The model used is reported \
+                        for texts, while the po and xliff commands can't tell if the service fell back to the \
+                        general model.")
+                 .required(false))
         .arg(Arg::with_name(REPL_MODE_KEY)
                  .long(REPL_MODE_KEY)
                  .short("r")
                  .takes_value(false)
                  .help("Set this flag to start a REPL session.")
-                 .required(false));
+                 .required(false))
+        .subcommand(SubCommand::with_name(PO_COMMAND)
+                        .about("Translate the untranslated and fuzzy entries of a gettext .po or .pot file")
+                        .arg(Arg::with_name(INPUT_KEY)
+                                 .help("The .po or .pot file to translate")
+                                 .required(true))
+                        .arg(Arg::with_name(OUTPUT_KEY)
+                                 .short("o")
+                                 .long(OUTPUT_KEY)
+                                 .takes_value(true)
+                                 .number_of_values(1)
+                                 .help("File to write the translated catalog to. Defaults to stdout.")
+                                 .required(false)))
+        .subcommand(SubCommand::with_name(XLIFF_COMMAND)
+                        .about("Translate the untranslated units of an XLIFF 1.2 or 2.0 file")
+                        .arg(Arg::with_name(INPUT_KEY)
+                                 .help("The XLIFF file to translate")
+                                 .required(true))
+                        .arg(Arg::with_name(OUTPUT_KEY)
+                                 .short("o")
+                                 .long(OUTPUT_KEY)
+                                 .takes_value(true)
+                                 .number_of_values(1)
+                                 .help("File to write the translated XLIFF to. Defaults to stdout.")
+                                 .required(false))
+                        .arg(Arg::with_name(DROP_COMMENTS_KEY)
+                                 .long(DROP_COMMENTS_KEY)
+                                 .takes_value(false)
+                                 .help("Write the translated XLIFF even though its XML comments, processing \
+                                        instructions and DOCTYPE cannot be kept.")
+                                 .required(false)));
     let mut app_clone = app.clone();
 
     let matches = app.get_matches();
@@ -87,35 +138,36 @@ fn main() {
 
     match (matches.value_of(FROM_KEY), matches.value_of(TO_KEY), sub_str) {
         (Some(from), Some(to), Some(sub)) => {
+            let from = parse_language(from);
+            let to = parse_language(to);
+            let category = matches.value_of(CATEGORY_KEY).map(parse_category);
             let (mut core, engine) = build_engine(SubscriptionKey::new(sub)).unwrap();
-            if matches.is_present(REPL_MODE_KEY) {
+            if let Some(po_matches) = matches.subcommand_matches(PO_COMMAND) {
+                let translator = PoTranslator {
+                    from: Some(&from),
+                    to: &to,
+                    category: category.as_ref(),
+                };
+                translate_po(po_matches, &translator, &mut core, &engine);
+            } else if let Some(xliff_matches) = matches.subcommand_matches(XLIFF_COMMAND) {
+                let translator = XliffTranslator {
+                    from: Some(&from),
+                    to: &to,
+                    category: category.as_ref(),
+                };
+                translate_xliff(xliff_matches, &translator, &mut core, &engine);
+            } else if matches.is_present(REPL_MODE_KEY) {
                 println!("{}", GREET);
                 loop {
                     let mut buffer = String::new();
                     io::stdin().read_line(&mut buffer).unwrap();
-                    let translate_req = TranslateRequest {
-                        text: buffer.as_str(),
-                        from: Some(from),
-                        to: to,
-                        content_type: None,
-                        category: None,
-                    };
-                    let work = engine.run(translate_req);
-                    let result = core.run(work).unwrap();
+                    let result = translate_text(&buffer, &from, &to, category.as_ref(), &mut core, &engine);
                     println!("{}\n", result)
                 }
             } else {
                 let mut buffer = String::new();
                 io::stdin().read_line(&mut buffer).unwrap();
-                let translate_req = TranslateRequest {
-                    text: buffer.as_str(),
-                    from: Some(from),
-                    to: to,
-                    content_type: None,
-                    category: None,
-                };
-                let work = engine.run(translate_req);
-                let result = core.run(work).unwrap();
+                let result = translate_text(&buffer, &from, &to, category.as_ref(), &mut core, &engine);
                 println!("{}", result)
             }
         }
@@ -126,6 +178,186 @@ fn main() {
 
 }
 
+/// Translates a text, with the model of the given category if any.
+///
+/// The v2 API quietly falls back to the general model, so texts with a category are
+/// translated with v3 instead, reporting on stderr which model was used.
+fn translate_text(text: &str,
+                  from: &LanguageTag,
+                  to: &LanguageTag,
+                  category: Option<&CategoryId>,
+                  core: &mut tokio_core::reactor::Core,
+                  engine: &Engine<hyper_tls::HttpsConnector<HttpConnector>>)
+                  -> String {
+    let category = match category {
+        Some(category) => category,
+        None => {
+            let translate_req = TranslateRequest {
+                text: text,
+                from: Some(from),
+                to: to,
+                content_type: None,
+                category: None,
+                profanity_action: None,
+            };
+            return core.run(engine.run(translate_req)).unwrap();
+        }
+    };
+    let texts = [text];
+    let to = [to.clone()];
+    let translate_req = translator_v3::TranslateRequest {
+        texts: &texts,
+        to: &to,
+        from: Some(from),
+        ..Default::default()
+    };
+    let translation = core.run(engine.run_flow(category::translate(translate_req, category))).unwrap();
+    match translation.fallback_reason {
+        Some(ref reason) => {
+            eprintln!("Used the general model, the model for category {} is not available: {} ({})",
+                      category,
+                      reason.message,
+                      reason.code)
+        }
+        None => eprintln!("Used the model for category {}", category),
+    }
+    translation.results[0].translations[0].text.clone()
+}
+
+/// Translates the .po file given to the po subcommand, exiting with a message on failure
+fn translate_po(matches: &ArgMatches,
+                translator: &PoTranslator,
+                core: &mut tokio_core::reactor::Core,
+                engine: &Engine<hyper_tls::HttpsConnector<HttpConnector>>) {
+    let input = matches.value_of(INPUT_KEY).unwrap();
+    let mut contents = String::new();
+    if let Err(e) = File::open(input).and_then(|mut f| f.read_to_string(&mut contents)) {
+        eprintln!("Could not read '{}': {}", input, e);
+        std::process::exit(1)
+    }
+    let catalog = match gettext::parse(&contents) {
+        Ok(catalog) => catalog,
+        Err(e) => {
+            eprintln!("Invalid catalog '{}': {:?}", input, e);
+            std::process::exit(1)
+        }
+    };
+    let result = match core.run(engine.run_flow(translator.translate(&catalog))) {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("Translation failed: {:?}", e);
+            std::process::exit(1)
+        }
+    };
+    for index in &result.placeholder_mismatches {
+        eprintln!("Placeholders of msgid {:?} were not all kept, please review it",
+                  result.catalog.entries[*index].msgid);
+    }
+    let written = match matches.value_of(OUTPUT_KEY) {
+        Some(output) => File::create(output).and_then(|mut f| write!(f, "{}", result.catalog)),
+        None => write!(io::stdout(), "{}", result.catalog),
+    };
+    if let Err(e) = written {
+        eprintln!("Could not write the translated catalog: {}", e);
+        std::process::exit(1)
+    }
+    eprintln!("Translated {} entries, marked fuzzy for review", result.translated.len());
+}
+
+/// Translates the XLIFF file given to the xliff subcommand, exiting with a message on failure
+fn translate_xliff(matches: &ArgMatches,
+                   translator: &XliffTranslator,
+                   core: &mut tokio_core::reactor::Core,
+                   engine: &Engine<hyper_tls::HttpsConnector<HttpConnector>>) {
+    let input = matches.value_of(INPUT_KEY).unwrap();
+    let document = match File::open(input) {
+        Ok(f) => Document::from_reader(f),
+        Err(e) => {
+            eprintln!("Could not read '{}': {}", input, e);
+            std::process::exit(1)
+        }
+    };
+    let mut document = match document {
+        Ok(document) => document,
+        Err(e) => {
+            eprintln!("Invalid XLIFF '{}': {:?}", input, e);
+            std::process::exit(1)
+        }
+    };
+    if document.dropped_nodes() > 0 {
+        if !matches.is_present(DROP_COMMENTS_KEY) {
+            eprintln!("'{}' has {} XML comments, processing instructions or DOCTYPEs that cannot be written \
+                       back, pass --{} to translate it without them",
+                      input,
+                      document.dropped_nodes(),
+                      DROP_COMMENTS_KEY);
+            std::process::exit(1)
+        }
+        document.drop_unsupported_nodes();
+    }
+    let translation = match translator.translate(&document) {
+        Ok(translation) => translation,
+        Err(e) => {
+            eprintln!("Cannot translate '{}': {:?}", input, e);
+            std::process::exit(1)
+        }
+    };
+    let result = match core.run(engine.run_flow(translation)) {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("Translation failed: {:?}", e);
+            std::process::exit(1)
+        }
+    };
+    for id in &result.code_mismatches {
+        eprintln!("Inline codes of unit {} were not all kept in place, please review it",
+                  id.as_ref().map_or("without id", |id| id.as_str()));
+    }
+    let written = match matches.value_of(OUTPUT_KEY) {
+        Some(output) => File::create(output).map_err(|e| format!("{}", e)).and_then(|f| {
+            result.document.to_writer(f).map_err(|e| format!("{:?}", e))
+        }),
+        None => result.document.to_writer(io::stdout()).map_err(|e| format!("{:?}", e)),
+    };
+    if let Err(e) = written {
+        eprintln!("Could not write the translated XLIFF: {}", e);
+        std::process::exit(1)
+    }
+    eprintln!("Translated {} units, marked for review", result.translated);
+}
+
+/// Parses a language given on the command line, exiting with a message if it is not valid.
+///
+/// Languages missing from the embedded list only get a warning, since the service may
+/// have added them since.
+fn parse_language(s: &str) -> LanguageTag {
+    match LanguageTag::parse(s) {
+        Ok(tag) => {
+            if !tag.is_supported_by(EMBEDDED_LANGUAGES) {
+                eprintln!("Warning: '{}' is not a known language, the service may reject it. Known languages are: {}",
+                          tag,
+                          EMBEDDED_LANGUAGES.join(", "));
+            }
+            tag
+        }
+        Err(e) => {
+            eprintln!("Invalid language '{}': {:?}", s, e);
+            std::process::exit(1)
+        }
+    }
+}
+
+/// Parses a category given on the command line, exiting with a message if it is not valid
+fn parse_category(s: &str) -> CategoryId {
+    match CategoryId::parse(s) {
+        Ok(category) => category,
+        Err(e) => {
+            eprintln!("Invalid category '{}': {:?}", s, e);
+            std::process::exit(1)
+        }
+    }
+}
+
 fn build_engine(sub_key: SubscriptionKey)
                 -> Result<(tokio_core::reactor::Core,
                            Engine<hyper_tls::HttpsConnector<HttpConnector>>),
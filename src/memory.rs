@@ -0,0 +1,390 @@
+//! Holds a local translation memory.
+//!
+//! A TranslationMemory stores approved translations of segments per language
+//! pair. Looking up a text returns the stored translation of the closest
+//! segment, scored by edit distance, so that approved translations can be used
+//! instead of asking the service. Memories can be imported from and exported
+//! to TMX files.
+use futures::Future;
+use futures::future;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use elementtree::{self, Element};
+use engine::Dispatcher;
+use flow::{self, Flow, CogFlow};
+use cogs::translation::{TranslateRequest, FutureTranslateResponse};
+use language::LanguageTag;
+
+const XML_NS: &'static str = "http://www.w3.org/XML/1998/namespace";
+const ALL_LANGUAGES: &'static str = "*all*";
+
+/// Approved translations of segments, per language pair
+#[derive(Debug, Clone, Default)]
+pub struct TranslationMemory {
+    pairs: HashMap<(String, String), Segments>,
+}
+
+/// Segments of a language pair, indexed by normalized source
+#[derive(Debug, Clone, Default)]
+struct Segments {
+    list: Vec<Segment>,
+    by_source: HashMap<String, usize>,
+}
+
+/// A segment and its approved translation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub source: String,
+    pub target: String,
+}
+
+/// A segment found in a TranslationMemory
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    pub source: String,
+    pub target: String,
+    /// Between 0 and 1, 1 meaning the segment is the text that was looked up
+    pub score: f64,
+}
+
+impl Match {
+    pub fn is_exact(&self) -> bool {
+        self.score >= 1.0
+    }
+}
+
+/// A translation coming either from the memory or from the service
+#[derive(Debug, Clone, PartialEq)]
+pub enum Suggestion {
+    Memory(Match),
+    Machine(String),
+}
+
+impl Suggestion {
+    pub fn text(&self) -> &str {
+        match *self {
+            Suggestion::Memory(ref m) => &m.target,
+            Suggestion::Machine(ref t) => t,
+        }
+    }
+}
+
+/// Translation memory error mapping
+#[derive(Debug)]
+pub enum Error {
+    XmlError(elementtree::Error),
+    InvalidTmx(String),
+}
+
+impl TranslationMemory {
+    pub fn new() -> TranslationMemory {
+        TranslationMemory { pairs: HashMap::new() }
+    }
+
+    /// Stores an approved translation, replacing any previous one of the same segment
+    pub fn add(&mut self, from: &str, to: &str, source: &str, target: &str) {
+        let segments = self.pairs.entry(pair_key(from, to)).or_insert_with(Segments::default);
+        match segments.by_source.get(&normalize(source)) {
+            Some(&i) => segments.list[i].target = target.to_string(),
+            None => {
+                segments.by_source.insert(normalize(source), segments.list.len());
+                segments.list.push(Segment {
+                                       source: source.to_string(),
+                                       target: target.to_string(),
+                                   })
+            }
+        }
+    }
+
+    /// Returns the segments stored for a language pair
+    pub fn segments(&self, from: &str, to: &str) -> &[Segment] {
+        self.pairs.get(&pair_key(from, to)).map_or(&[], |s| s.list.as_slice())
+    }
+
+    /// Returns the number of segments stored across all language pairs
+    pub fn len(&self) -> usize {
+        self.pairs.values().map(|s| s.list.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the matches for a text scoring at least min_score, best first.
+    ///
+    /// Texts are compared ignoring surrounding whitespace and how much whitespace
+    /// separates words.
+    pub fn matches(&self, from: &str, to: &str, text: &str, min_score: f64) -> Vec<Match> {
+        let normalized: Vec<char> = normalize(text).chars().collect();
+        let mut matches: Vec<Match> = self.segments(from, to)
+            .iter()
+            .filter_map(|segment| {
+                let candidate: Vec<char> = normalize(&segment.source).chars().collect();
+                let longest = normalized.len().max(candidate.len());
+                let shortest = normalized.len().min(candidate.len());
+                // The distance is at least the difference in length
+                if longest > 0 && ((shortest as f64) / (longest as f64)) < min_score {
+                    return None;
+                }
+                let score = similarity(&normalized, &candidate);
+                if score >= min_score {
+                    Some(Match {
+                             source: segment.source.clone(),
+                             target: segment.target.clone(),
+                             score: score,
+                         })
+                } else {
+                    None
+                }
+            })
+            .collect();
+        matches.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+        matches
+    }
+
+    /// Returns the best match for a text scoring at least min_score
+    pub fn lookup(&self, from: &str, to: &str, text: &str, min_score: f64) -> Option<Match> {
+        self.matches(from, to, text, min_score).into_iter().next()
+    }
+
+    /// Returns a Flow that uses the best match scoring at least min_score if
+    /// there is one, and translates the text with the service otherwise.
+    ///
+    /// The memory can only be used when the request has a `from` language.
+    pub fn translate(&self, req: TranslateRequest, min_score: f64) -> MemoryTranslation {
+        let found = req.from
+            .and_then(|from| self.lookup(from.as_str(), req.to.as_str(), req.text, min_score));
+        match found {
+            Some(m) => MemoryTranslation::Hit(m),
+            None => MemoryTranslation::Miss(flow::cog(req)),
+        }
+    }
+
+    /// Adds every translation unit of a TMX document, returning how many segments were added.
+    ///
+    /// Units are paired from their source language, as given by the unit or the
+    /// header, to each of their other languages.
+    pub fn import_tmx<R: Read>(&mut self, r: R) -> Result<usize, Error> {
+        let root = Element::from_reader(r).map_err(|e| Error::XmlError(e))?;
+        if root.tag().name() != "tmx" {
+            return Err(Error::InvalidTmx(format!("unexpected root element {}", root.tag().name())));
+        }
+        let header_lang = source_language(root.find("header").and_then(|h| h.get_attr("srclang")));
+        let body = root.find("body")
+            .ok_or_else(|| Error::InvalidTmx("missing body".to_string()))?;
+        let mut added = 0;
+        for tu in body.find_all("tu") {
+            let variants: Vec<(String, String)> = tu.find_all("tuv")
+                .filter_map(|tuv| {
+                                let lang = tuv.get_attr((XML_NS, "lang")).or_else(|| tuv.get_attr("lang"));
+                                let seg = tuv.find("seg");
+                                match (lang, seg) {
+                                    (Some(l), Some(s)) => Some((l.to_string(), segment_text(s))),
+                                    _ => None,
+                                }
+                            })
+                .collect();
+            let source_lang = source_language(tu.get_attr("srclang")).or(header_lang);
+            let source = match source_lang {
+                Some(lang) => variants.iter().find(|&&(ref l, _)| l.eq_ignore_ascii_case(lang)),
+                None => variants.first(),
+            };
+            if let Some(&(ref from, ref source)) = source {
+                for &(ref to, ref target) in &variants {
+                    if !to.eq_ignore_ascii_case(from) {
+                        self.add(from, to, source, target);
+                        added += 1;
+                    }
+                }
+            }
+        }
+        Ok(added)
+    }
+
+    /// Writes every segment as a TMX 1.4 document
+    pub fn export_tmx<W: Write>(&self, w: W) -> Result<(), Error> {
+        let mut root = Element::new("tmx");
+        root.set_attr("version", "1.4");
+        root.append_new_child("header")
+            .set_attr("creationtool", "cogs")
+            .set_attr("creationtoolversion", env!("CARGO_PKG_VERSION"))
+            .set_attr("segtype", "sentence")
+            .set_attr("o-tmf", "cogs")
+            .set_attr("adminlang", "en")
+            .set_attr("srclang", ALL_LANGUAGES)
+            .set_attr("datatype", "plaintext");
+        {
+            let body = root.append_new_child("body");
+            let mut pairs: Vec<_> = self.pairs.iter().collect();
+            pairs.sort_by(|a, b| a.0.cmp(b.0));
+            for (&(ref from, ref to), segments) in pairs {
+                for segment in &segments.list {
+                    let tu = body.append_new_child("tu");
+                    tu.set_attr("srclang", from.as_str());
+                    for &(lang, text) in &[(from, &segment.source), (to, &segment.target)] {
+                        let tuv = tu.append_new_child("tuv");
+                        tuv.set_attr((XML_NS, "lang"), lang.as_str());
+                        tuv.append_new_child("seg").set_text(text.as_str());
+                    }
+                }
+            }
+        }
+        root.to_writer(w).map_err(|e| Error::XmlError(e))
+    }
+}
+
+/// Flow for `TranslationMemory::translate`
+pub enum MemoryTranslation {
+    /// A good enough match was found in the memory
+    Hit(Match),
+    /// The text must be translated by the service
+    Miss(CogFlow<FutureTranslateResponse>),
+}
+
+impl Flow for MemoryTranslation {
+    type Item = Suggestion;
+
+    fn start(self, dispatcher: Dispatcher) -> Box<Future<Item = Self::Item, Error = flow::Error>> {
+        match self {
+            MemoryTranslation::Hit(m) => Box::new(future::ok(Suggestion::Memory(m))),
+            MemoryTranslation::Miss(cog) => Box::new(cog.start(dispatcher).map(Suggestion::Machine)),
+        }
+    }
+}
+
+/// Returns the given source language, treating "*all*" as no language at all
+fn source_language(lang: Option<&str>) -> Option<&str> {
+    lang.and_then(|l| if l == ALL_LANGUAGES { None } else { Some(l) })
+}
+
+/// Returns the text of a seg element, keeping the text of inline elements such as placeholders
+fn segment_text(seg: &Element) -> String {
+    let mut text = seg.text().to_string();
+    for child in seg.children() {
+        text.push_str(&segment_text(child));
+        text.push_str(child.tail());
+    }
+    text
+}
+
+/// Returns the key of a language pair, normalizing codes that are valid language tags
+fn pair_key(from: &str, to: &str) -> (String, String) {
+    let key = |code: &str| {
+        LanguageTag::parse(code)
+            .map(|t| t.as_str().to_lowercase())
+            .unwrap_or_else(|_| code.to_lowercase())
+    };
+    (key(from), key(to))
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Returns 1 minus the edit distance between two texts divided by the length of the longest
+fn similarity(a: &[char], b: &[char]) -> f64 {
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    1.0 - (levenshtein(a, b) as f64) / (longest as f64)
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..b.len() + 1).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == cb { 0 } else { 1 };
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        ::std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::client::Response;
+    use engine::scripted_dispatcher;
+
+    fn memory() -> TranslationMemory {
+        let mut memory = TranslationMemory::new();
+        memory.add("en", "de", "Save the file", "Datei speichern");
+        memory.add("en", "de", "Open the file", "Datei öffnen");
+        memory.add("en", "fr", "Save the file", "Enregistrer le fichier");
+        memory.add("en", "zh-CHS", "Save the file", "保存文件");
+        memory
+    }
+
+    #[test]
+    fn lookup_test() {
+        let memory = memory();
+        let exact = memory.lookup("EN", "de", "  Save  the file ", 1.0).unwrap();
+        assert!(exact.is_exact());
+        assert_eq!(exact.target, "Datei speichern");
+        let fuzzy = memory.lookup("en", "de", "Save the files", 0.9).unwrap();
+        assert_eq!(fuzzy.target, "Datei speichern");
+        assert!(!fuzzy.is_exact());
+        assert_eq!(memory.lookup("en", "de", "Close the window", 0.9), None);
+        assert_eq!(memory.matches("en", "de", "Save the file", 0.5).len(), 2);
+        assert_eq!(memory.lookup("en", "zh-Hans", "Save the file", 1.0).unwrap().target, "保存文件");
+    }
+
+    #[test]
+    fn add_test() {
+        let mut memory = memory();
+        memory.add("en", "de", " Save the  file", "Die Datei speichern");
+        assert_eq!(memory.segments("en", "de").len(), 2);
+        assert_eq!(memory.segments("en", "de")[0].target, "Die Datei speichern");
+        assert_eq!(memory.len(), 4);
+    }
+
+    #[test]
+    fn tmx_test() {
+        let mut exported = vec![];
+        memory().export_tmx(&mut exported).unwrap();
+        let mut imported = TranslationMemory::new();
+        assert_eq!(imported.import_tmx(exported.as_slice()).unwrap(), 4);
+        assert_eq!(imported.segments("en", "fr"), memory().segments("en", "fr"));
+
+        let tmx = r#"<tmx version="1.4"><header srclang="en" /><body>
+            <tu><tuv xml:lang="de"><seg>Hallo <ph>{0}</ph>!</seg></tuv><tuv xml:lang="en"><seg>Hello <ph>{0}</ph>!</seg></tuv></tu>
+            </body></tmx>"#;
+        let mut memory = TranslationMemory::new();
+        assert_eq!(memory.import_tmx(tmx.as_bytes()).unwrap(), 1);
+        assert_eq!(memory.segments("en", "de"),
+                   &[Segment {
+                         source: "Hello {0}!".to_string(),
+                         target: "Hallo {0}!".to_string(),
+                     }]);
+    }
+
+    #[test]
+    fn translate_test() {
+        let resp = Response::new().with_body("<string>Fenster schließen</string>");
+        let (dispatcher, sent) = scripted_dispatcher(vec![resp]);
+        let (en, de) = (LanguageTag::parse("en").unwrap(), LanguageTag::parse("de").unwrap());
+        let request = |text| {
+            TranslateRequest {
+                text: text,
+                from: Some(&en),
+                to: &de,
+                content_type: None,
+                category: None,
+                profanity_action: None,
+            }
+        };
+        let memory = memory();
+        let from_memory = memory.translate(request("Save the file"), 0.8);
+        let result = from_memory.start(dispatcher.clone()).wait().unwrap();
+        assert_eq!(result.text(), "Datei speichern");
+        assert!(sent.borrow().is_empty());
+        let from_service = memory.translate(request("Close the window"), 0.8);
+        let result = from_service.start(dispatcher).wait().unwrap();
+        assert_eq!(result, Suggestion::Machine("Fenster schließen".to_string()));
+        assert_eq!(sent.borrow().len(), 1);
+    }
+}
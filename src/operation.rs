@@ -0,0 +1,424 @@
+//! Holds logic for long-running operations.
+//!
+//! Some Cognitive Service endpoints accept a request with 202 Accepted and an
+//! Operation-Location header instead of answering right away. The location is then
+//! polled until the operation finishes. Implement Operation for the status responses of
+//! such an endpoint and use Engine::run_operation to submit and poll it.
+use hyper::client::{Request, Response};
+use hyper::{Method, StatusCode, Uri};
+use futures::{Future, Poll};
+use futures::future::{self, Loop};
+use tokio_core::reactor::{Handle, Timeout};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::io;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use engine::{self, Dispatcher, retry_after};
+
+header! {
+    (OperationLocation, "Operation-Location") => [String]
+}
+
+/// Trait representing the status endpoint of a long-running operation.
+///
+/// The implementing type is also what gets submitted when used with
+/// Engine::run_operation, so it must own its data.
+pub trait Operation: 'static {
+    /// What the operation finally returns
+    type Item: 'static;
+
+    /// Error type
+    type Error: 'static;
+
+    /// Turns a successful response from the operation's location into its current Status
+    fn status(resp: Response) -> Box<Future<Item = Status<Self::Item>, Error = Self::Error>>;
+}
+
+/// Status of a long-running operation
+#[derive(Debug, PartialEq)]
+pub enum Status<Item> {
+    InProgress,
+    Done(Item),
+}
+
+/// Settings for polling a long-running operation.
+///
+/// Delays between polls start at initial_delay and are multiplied by
+/// backoff_factor each time, up to max_delay. A Retry-After header sent by the
+/// service takes precedence over the computed delay.
+#[derive(Debug, Clone, Copy)]
+pub struct PollSettings {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub backoff_factor: u32,
+    pub max_polls: Option<usize>,
+}
+
+impl PollSettings {
+    /// Returns the delay to use after the given one
+    pub fn next_delay(&self, previous: Duration) -> Duration {
+        match previous.checked_mul(self.backoff_factor) {
+            Some(d) if d < self.max_delay => d,
+            _ => self.max_delay,
+        }
+    }
+}
+
+impl Default for PollSettings {
+    fn default() -> Self {
+        PollSettings {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            backoff_factor: 2,
+            max_polls: None,
+        }
+    }
+}
+
+/// Handle to a long-running operation that is being polled.
+///
+/// Cancelling stops polling before the next status request is sent. Use
+/// Engine::cancel_operation to also cancel the operation on the service side.
+#[derive(Clone)]
+pub struct OperationHandle {
+    location: Rc<RefCell<Option<Uri>>>,
+    cancelled: Rc<Cell<bool>>,
+}
+
+impl OperationHandle {
+    fn new() -> OperationHandle {
+        OperationHandle {
+            location: Rc::new(RefCell::new(None)),
+            cancelled: Rc::new(Cell::new(false)),
+        }
+    }
+
+    /// Returns the location of the operation once it has been accepted by the service.
+    ///
+    /// Save this to resume polling later with Engine::resume_operation.
+    pub fn location(&self) -> Option<Uri> {
+        self.location.borrow().clone()
+    }
+
+    /// Stops polling the operation
+    pub fn cancel(&self) {
+        self.cancelled.set(true)
+    }
+
+    /// Whether or not polling was cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.get()
+    }
+
+    fn set_location(&self, location: Uri) {
+        *self.location.borrow_mut() = Some(location);
+    }
+}
+
+/// Future for a long-running operation
+pub struct Polling<Item, E> {
+    handle: OperationHandle,
+    inner: Box<Future<Item = Item, Error = Error<E>>>,
+}
+
+impl<Item, E> Polling<Item, E> {
+    /// Returns a handle to the operation being polled
+    pub fn handle(&self) -> OperationHandle {
+        self.handle.clone()
+    }
+}
+
+impl<Item, E> fmt::Debug for Polling<Item, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("Polling")
+    }
+}
+
+impl<Item, E> Future for Polling<Item, E> {
+    type Item = Item;
+    type Error = Error<E>;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.inner.poll()
+    }
+}
+
+/// Long-running operation error mapping
+#[derive(Debug)]
+pub enum Error<E> {
+    MissingOperationLocation,
+    InvalidOperationLocation(String),
+    UnexpectedStatus(StatusCode),
+    TooManyPolls,
+    Cancelled,
+    TimerError(io::Error),
+    OperationError(E),
+    EngineError(engine::Error),
+}
+
+/// Submits a Request and polls the resulting operation, sending Requests through the given
+/// Dispatcher. See Engine::run_operation.
+pub fn submit_and_poll<O>(dispatcher: Dispatcher,
+                          timer: Handle,
+                          req: Request,
+                          settings: PollSettings)
+                          -> Polling<O::Item, O::Error>
+    where O: Operation
+{
+    let handle = OperationHandle::new();
+    let handle_ref = handle.clone();
+    let dispatcher_ref = dispatcher.clone();
+    let f = dispatcher
+        .send(req)
+        .map_err(|e| Error::EngineError(e))
+        .and_then(move |resp| {
+            let status = resp.status();
+            if !status.is_success() {
+                return Err(Error::UnexpectedStatus(status));
+            }
+            let location = operation_location(&resp)?;
+            handle_ref.set_location(location.clone());
+            Ok((location, retry_after(&resp), handle_ref))
+        })
+        .and_then(move |(location, first_delay, handle_ref)| {
+                      poll_loop::<O>(dispatcher_ref, timer, location, settings, first_delay, handle_ref)
+                  });
+    Polling {
+        handle: handle,
+        inner: Box::new(f),
+    }
+}
+
+/// Polls an already submitted operation. See Engine::resume_operation.
+pub fn poll<O>(dispatcher: Dispatcher,
+               timer: Handle,
+               location: Uri,
+               settings: PollSettings)
+               -> Polling<O::Item, O::Error>
+    where O: Operation
+{
+    let handle = OperationHandle::new();
+    handle.set_location(location.clone());
+    let inner = poll_loop::<O>(dispatcher, timer, location, settings, None, handle.clone());
+    Polling {
+        handle: handle,
+        inner: inner,
+    }
+}
+
+fn poll_loop<O>(dispatcher: Dispatcher,
+                timer: Handle,
+                location: Uri,
+                settings: PollSettings,
+                first_delay: Option<Duration>,
+                handle: OperationHandle)
+                -> Box<Future<Item = O::Item, Error = Error<O::Error>>>
+    where O: Operation
+{
+    let initial = (0, first_delay.unwrap_or(settings.initial_delay));
+    let f = future::loop_fn(initial, move |(polls, delay)| {
+        let step: Box<Future<Item = Loop<O::Item, (usize, Duration)>, Error = Error<O::Error>>> =
+            match settings.max_polls {
+                Some(max) if polls >= max => Box::new(future::err(Error::TooManyPolls)),
+                _ if handle.is_cancelled() => Box::new(future::err(Error::Cancelled)),
+                _ => {
+                    match Timeout::new(delay, &timer) {
+                        Ok(timeout) => {
+                            let dispatcher_ref = dispatcher.clone();
+                            let handle_ref = handle.clone();
+                            let req = Request::new(Method::Get, location.clone());
+                            Box::new(timeout
+                                         .map_err(|e| Error::TimerError(e))
+                                         .and_then(move |_| if handle_ref.is_cancelled() {
+                                                       Err(Error::Cancelled)
+                                                   } else {
+                                                       Ok(())
+                                                   })
+                                         .and_then(move |_| {
+                                                       dispatcher_ref
+                                                           .send(req)
+                                                           .map_err(|e| Error::EngineError(e))
+                                                   })
+                                         .and_then(move |resp| {
+                                                       poll_step::<O>(resp, polls, delay, &settings)
+                                                   }))
+                        }
+                        Err(e) => Box::new(future::err(Error::TimerError(e))),
+                    }
+                }
+            };
+        step
+    });
+    Box::new(f)
+}
+
+/// Decides what to do with a single status Response
+fn poll_step<O>(resp: Response,
+                polls: usize,
+                delay: Duration,
+                settings: &PollSettings)
+                -> Box<Future<Item = Loop<O::Item, (usize, Duration)>, Error = Error<O::Error>>>
+    where O: Operation
+{
+    let next_delay = retry_after(&resp).unwrap_or_else(|| settings.next_delay(delay));
+    let status = resp.status();
+    match status {
+        StatusCode::TooManyRequests |
+        StatusCode::ServiceUnavailable => Box::new(future::ok(Loop::Continue((polls + 1, next_delay)))),
+        _ if !status.is_success() => Box::new(future::err(Error::UnexpectedStatus(status))),
+        _ => {
+            Box::new(O::status(resp)
+                         .map_err(|e| Error::OperationError(e))
+                         .map(move |s| match s {
+                                  Status::InProgress => Loop::Continue((polls + 1, next_delay)),
+                                  Status::Done(item) => Loop::Break(item),
+                              }))
+        }
+    }
+}
+
+/// Reads the Operation-Location header of a Response
+fn operation_location<E>(resp: &Response) -> Result<Uri, Error<E>> {
+    match resp.headers().get::<OperationLocation>() {
+        Some(&OperationLocation(ref s)) => {
+            Uri::from_str(s).map_err(|_| Error::InvalidOperationLocation(s.clone()))
+        }
+        None => Err(Error::MissingOperationLocation),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::RetryAfter;
+    use engine::{read_to_string, scripted_dispatcher};
+    use tokio_core;
+    use std::time::Instant;
+
+    /// Operation whose status body is either "running" or "done:<result>"
+    struct Job;
+
+    impl Operation for Job {
+        type Item = String;
+        type Error = ();
+
+        fn status(resp: Response) -> Box<Future<Item = Status<Self::Item>, Error = Self::Error>> {
+            Box::new(read_to_string(resp)
+                         .map_err(|_| ())
+                         .map(|body| if body.starts_with("done:") {
+                                  Status::Done(body["done:".len()..].to_string())
+                              } else {
+                                  Status::InProgress
+                              }))
+        }
+    }
+
+    const LOCATION: &'static str = "http://example.com/operations/1";
+
+
+    fn accepted() -> Response {
+        Response::new()
+            .with_status(StatusCode::Accepted)
+            .with_header(OperationLocation(LOCATION.to_string()))
+    }
+
+    fn settings() -> PollSettings {
+        PollSettings {
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+            backoff_factor: 2,
+            max_polls: None,
+        }
+    }
+
+    fn submit(responses: Vec<Response>,
+              settings: PollSettings)
+              -> (Result<String, Error<()>>, Vec<String>, Duration) {
+        let mut core = tokio_core::reactor::Core::new().unwrap();
+        let (dispatcher, sent) = scripted_dispatcher(responses);
+        let req = Request::new(Method::Post, Uri::from_str("http://example.com/jobs").unwrap());
+        let started = Instant::now();
+        let polling = submit_and_poll::<Job>(dispatcher, core.handle(), req, settings);
+        let handle = polling.handle();
+        let result = core.run(polling);
+        assert_eq!(handle.location(), Some(Uri::from_str(LOCATION).unwrap()));
+        let requested = sent.borrow().iter().map(|r| r.uri.clone()).collect();
+        (result, requested, started.elapsed())
+    }
+
+    #[test]
+    fn submit_and_poll_test() {
+        let responses = vec![accepted(),
+                             Response::new().with_body("running"),
+                             Response::new().with_status(StatusCode::ServiceUnavailable),
+                             Response::new().with_body("done:42")];
+        let (result, requested, _) = submit(responses, settings());
+        assert_eq!(result.unwrap(), "42");
+        assert_eq!(requested,
+                   vec!["http://example.com/jobs", LOCATION, LOCATION, LOCATION]);
+    }
+
+    #[test]
+    fn retry_after_poll_test() {
+        let responses = vec![accepted(),
+                             Response::new()
+                                 .with_body("running")
+                                 .with_header(RetryAfter::Delay(Duration::from_secs(1))),
+                             Response::new().with_body("done:42")];
+        let (result, requested, elapsed) = submit(responses, settings());
+        assert_eq!(result.unwrap(), "42");
+        assert_eq!(requested.len(), 3);
+        assert!(elapsed >= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn too_many_polls_test() {
+        let responses = vec![accepted(),
+                             Response::new().with_body("running"),
+                             Response::new().with_body("running")];
+        let settings = PollSettings { max_polls: Some(2), ..settings() };
+        match submit(responses, settings) {
+            (Err(Error::TooManyPolls), ref requested, _) if requested.len() == 3 => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn next_delay_test() {
+        let settings = PollSettings {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+            backoff_factor: 3,
+            max_polls: None,
+        };
+        assert_eq!(settings.next_delay(Duration::from_secs(1)),
+                   Duration::from_secs(3));
+        assert_eq!(settings.next_delay(Duration::from_secs(3)),
+                   Duration::from_secs(9));
+        assert_eq!(settings.next_delay(Duration::from_secs(9)),
+                   Duration::from_secs(10));
+    }
+
+    #[test]
+    fn operation_location_test() {
+        let location = "https://example.com/operations/123";
+        let resp = Response::new().with_header(OperationLocation(location.to_string()));
+        let uri: Result<Uri, Error<()>> = operation_location(&resp);
+        assert_eq!(uri.unwrap(), Uri::from_str(location).unwrap());
+        match operation_location::<()>(&Response::new()) {
+            Err(Error::MissingOperationLocation) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn handle_test() {
+        let handle = OperationHandle::new();
+        assert_eq!(handle.location(), None);
+        assert!(!handle.is_cancelled());
+        handle.clone().cancel();
+        assert!(handle.is_cancelled());
+    }
+}
@@ -0,0 +1,314 @@
+//! Holds logic for endpoints that return their results one page at a time.
+//!
+//! Implement PagedCog for such an endpoint and use Engine::stream to get a
+//! Stream of all its items. Pages are only fetched once the items of the
+//! previous one have been consumed.
+use hyper::client::{Request, Response};
+use hyper::{StatusCode, Uri};
+use futures::{Future, Poll, Stream};
+use futures::future::{self, Loop};
+use futures::stream;
+use tokio_core::reactor::{Handle, Timeout};
+use std::rc::Rc;
+use std::cmp;
+use std::io;
+use std::fmt;
+use std::time::Duration;
+use engine::{self, Dispatcher, retry_after};
+
+/// Longest computed delay between retries of a page, unless retry_delay itself is longer
+const MAX_RETRY_DELAY_SECS: u64 = 60;
+
+/// Trait representing an endpoint whose results are split across pages.
+///
+/// It must own its data, since it is used to build the Request of every page.
+pub trait PagedCog: 'static {
+    /// Type of the items on each page
+    type Item: 'static;
+
+    /// Error type
+    type Error: 'static;
+
+    /// Returns the Request for the first page when given None, otherwise the
+    /// Request for the given next page
+    fn page_request(&self, next: Option<&NextPage>) -> Request;
+
+    /// Turns a successful Response into a Page
+    fn read_page(resp: Response) -> Box<Future<Item = Page<Self::Item>, Error = Self::Error>>;
+}
+
+/// A single page of results
+#[derive(Debug, PartialEq)]
+pub struct Page<Item> {
+    pub items: Vec<Item>,
+    pub next: Option<NextPage>,
+}
+
+/// Where the next page of results can be found
+#[derive(Debug, Clone, PartialEq)]
+pub enum NextPage {
+    Link(Uri),
+    ContinuationToken(String),
+    Offset(usize),
+}
+
+/// Settings for streaming a PagedCog.
+///
+/// Each page is retried up to `retries` times when the request fails or the
+/// service answers with 429 or a 5xx status, waiting `retry_delay` (doubled on each
+/// attempt, up to a minute) or whatever the Retry-After header says in between.
+#[derive(Debug, Clone, Copy)]
+pub struct PageSettings {
+    pub max_items: Option<usize>,
+    pub retries: u32,
+    pub retry_delay: Duration,
+}
+
+impl Default for PageSettings {
+    fn default() -> Self {
+        PageSettings {
+            max_items: None,
+            retries: 3,
+            retry_delay: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Stream of the items of a PagedCog
+pub struct Paged<Item, E>(Box<Stream<Item = Item, Error = Error<E>>>);
+
+impl<Item, E> fmt::Debug for Paged<Item, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("Paged")
+    }
+}
+
+impl<Item, E> Stream for Paged<Item, E> {
+    type Item = Item;
+    type Error = Error<E>;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.0.poll()
+    }
+}
+
+/// Paging error mapping
+#[derive(Debug)]
+pub enum Error<E> {
+    UnexpectedStatus(StatusCode),
+    TimerError(io::Error),
+    PageError(E),
+    EngineError(engine::Error),
+}
+
+/// Streams the items of a PagedCog, sending page Requests through the given Dispatcher.
+///
+/// See Engine::stream.
+pub fn stream<P>(dispatcher: Dispatcher,
+                 handle: Handle,
+                 cog: P,
+                 settings: PageSettings)
+                 -> Paged<P::Item, P::Error>
+    where P: PagedCog
+{
+    let cog = Rc::new(cog);
+    // None means we're done, Some(None) means we still need the first page.
+    let pages = stream::unfold(Some(None), move |next: Option<Option<NextPage>>| {
+        next.map(|page| {
+            fetch_page(dispatcher.clone(), handle.clone(), cog.clone(), page, settings)
+                .map(|Page { items, next }| (stream::iter_ok(items), next.map(Some)))
+        })
+    });
+    let items = pages.flatten();
+    match settings.max_items {
+        Some(max) => Paged(Box::new(items.take(max as u64))),
+        None => Paged(Box::new(items)),
+    }
+}
+
+/// Fetches a single page, retrying when it makes sense to
+fn fetch_page<P>(dispatcher: Dispatcher,
+                 handle: Handle,
+                 cog: Rc<P>,
+                 next: Option<NextPage>,
+                 settings: PageSettings)
+                 -> Box<Future<Item = Page<P::Item>, Error = Error<P::Error>>>
+    where P: PagedCog
+{
+    let f = future::loop_fn(0, move |attempt| {
+        let req = cog.page_request(next.as_ref());
+        let handle_ref = handle.clone();
+        dispatcher
+            .send(req)
+            .then(move |result| {
+                let retry_in = if attempt < settings.retries {
+                    retry_delay(&result, attempt, &settings)
+                } else {
+                    None
+                };
+                let step: Box<Future<Item = Loop<Page<P::Item>, u32>, Error = Error<P::Error>>> =
+                    match (retry_in, result) {
+                        (Some(delay), _) => {
+                            match Timeout::new(delay, &handle_ref) {
+                                Ok(timeout) => {
+                                    Box::new(timeout
+                                                 .map_err(|e| Error::TimerError(e))
+                                                 .map(move |_| Loop::Continue(attempt + 1)))
+                                }
+                                Err(e) => Box::new(future::err(Error::TimerError(e))),
+                            }
+                        }
+                        (None, Err(e)) => Box::new(future::err(Error::EngineError(e))),
+                        (None, Ok(resp)) => {
+                            let status = resp.status();
+                            if status.is_success() {
+                                Box::new(P::read_page(resp)
+                                             .map_err(|e| Error::PageError(e))
+                                             .map(Loop::Break))
+                            } else {
+                                Box::new(future::err(Error::UnexpectedStatus(status)))
+                            }
+                        }
+                    };
+                step
+            })
+    });
+    Box::new(f)
+}
+
+/// Returns how long to wait before retrying a page, or None if it should not be retried
+fn retry_delay(result: &Result<Response, engine::Error>,
+               attempt: u32,
+               settings: &PageSettings)
+               -> Option<Duration> {
+    let max_delay = cmp::max(Duration::from_secs(MAX_RETRY_DELAY_SECS), settings.retry_delay);
+    let backoff = match 2u32.checked_pow(attempt).and_then(|m| settings.retry_delay.checked_mul(m)) {
+        Some(d) if d < max_delay => d,
+        _ => max_delay,
+    };
+    match *result {
+        Ok(ref resp) => {
+            let status = resp.status();
+            if status == StatusCode::TooManyRequests || status.is_server_error() {
+                Some(retry_after(resp).unwrap_or(backoff))
+            } else {
+                None
+            }
+        }
+        Err(_) => Some(backoff),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Method;
+    use tokio_core;
+    use std::str::FromStr;
+    use engine::scripted_dispatcher;
+
+    /// Pages of numbers, where the body of each page looks like "1,2,3;offset"
+    struct Numbers;
+
+    impl PagedCog for Numbers {
+        type Item = u32;
+        type Error = ();
+
+        fn page_request(&self, next: Option<&NextPage>) -> Request {
+            let offset = match next {
+                Some(&NextPage::Offset(o)) => o,
+                _ => 0,
+            };
+            let uri = Uri::from_str(&format!("http://example.com/numbers?offset={}", offset));
+            Request::new(Method::Get, uri.unwrap())
+        }
+
+        fn read_page(resp: Response) -> Box<Future<Item = Page<Self::Item>, Error = Self::Error>> {
+            Box::new(engine::read_to_string(resp)
+                         .map_err(|_| ())
+                         .map(|body| {
+                let mut parts = body.split(';');
+                let items = parts
+                    .next()
+                    .unwrap()
+                    .split(',')
+                    .filter(|s| !s.is_empty())
+                    .map(|s| s.parse().unwrap())
+                    .collect();
+                let next = parts.next().map(|o| NextPage::Offset(o.parse().unwrap()));
+                Page {
+                    items: items,
+                    next: next,
+                }
+            }))
+        }
+    }
+
+
+    fn settings() -> PageSettings {
+        PageSettings {
+            max_items: None,
+            retries: 1,
+            retry_delay: Duration::from_millis(1),
+        }
+    }
+
+    #[test]
+    fn stream_test() {
+        let mut core = tokio_core::reactor::Core::new().unwrap();
+        let responses = vec![Response::new().with_body("1,2;2"),
+                             Response::new().with_status(StatusCode::ServiceUnavailable),
+                             Response::new().with_body(";3"),
+                             Response::new().with_body("3")];
+        let (dispatcher, sent) = scripted_dispatcher(responses);
+        let items = stream(dispatcher, core.handle(), Numbers, settings()).collect();
+        assert_eq!(core.run(items).unwrap(), vec![1, 2, 3]);
+        let requested: Vec<String> = sent.borrow().iter().map(|r| r.uri.clone()).collect();
+        assert_eq!(requested,
+                   vec!["http://example.com/numbers?offset=0",
+                        "http://example.com/numbers?offset=2",
+                        "http://example.com/numbers?offset=2",
+                        "http://example.com/numbers?offset=3"]);
+    }
+
+    #[test]
+    fn max_items_test() {
+        let mut core = tokio_core::reactor::Core::new().unwrap();
+        let responses = vec![Response::new().with_body("1,2;2"),
+                             Response::new().with_body("3,4;4")];
+        let (dispatcher, sent) = scripted_dispatcher(responses);
+        let settings = PageSettings { max_items: Some(2), ..settings() };
+        let items = stream(dispatcher, core.handle(), Numbers, settings).collect();
+        assert_eq!(core.run(items).unwrap(), vec![1, 2]);
+        assert_eq!(sent.borrow().len(), 1);
+    }
+
+    #[test]
+    fn retry_delay_test() {
+        let failed: Result<Response, engine::Error> = Err(engine::Error::CouldNotRetrieveToken);
+        let settings = PageSettings {
+            retry_delay: Duration::from_secs(1),
+            ..settings()
+        };
+        assert_eq!(retry_delay(&failed, 0, &settings), Some(Duration::from_secs(1)));
+        assert_eq!(retry_delay(&failed, 3, &settings), Some(Duration::from_secs(8)));
+        assert_eq!(retry_delay(&failed, 10, &settings), Some(Duration::from_secs(60)));
+        assert_eq!(retry_delay(&failed, 40, &settings), Some(Duration::from_secs(60)));
+        let ok = Ok(Response::new());
+        assert_eq!(retry_delay(&ok, 40, &settings), None);
+    }
+
+    #[test]
+    fn retries_exhausted_test() {
+        let mut core = tokio_core::reactor::Core::new().unwrap();
+        let responses = vec![Response::new().with_status(StatusCode::InternalServerError),
+                             Response::new().with_status(StatusCode::InternalServerError)];
+        let (dispatcher, sent) = scripted_dispatcher(responses);
+        let items = stream(dispatcher, core.handle(), Numbers, settings()).collect();
+        match core.run(items) {
+            Err(Error::UnexpectedStatus(StatusCode::InternalServerError)) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+        assert_eq!(sent.borrow().len(), 2);
+    }
+}
@@ -0,0 +1,160 @@
+//! Holds logic for swapping parts of a text for placeholders the service leaves alone.
+//!
+//! Parts that must come back unchanged, such as glossary terms, format strings or
+//! inline markup, are replaced with numbered placeholders like "{{0}}". Pairs of
+//! braces already in the text are protected the same way, so they are never
+//! mistaken for placeholders. Placeholders the service put spaces into, such as
+//! "{{ 0 }}", are still recognized when restoring.
+
+/// A text with numbered placeholders, along with what each placeholder stands for
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Protected {
+    text: String,
+    values: Vec<String>,
+}
+
+/// A piece of a translated text
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token<'a> {
+    Text(&'a str),
+    /// A placeholder such as "{{0}}", along with how it was written
+    Open(usize, &'a str),
+    /// The closing placeholder of a pair, such as "{{/0}}", along with how it was written
+    Close(usize, &'a str),
+}
+
+impl Protected {
+    pub fn new() -> Protected {
+        Protected::default()
+    }
+
+    /// Appends text, protecting the pairs of braces in it
+    pub fn push_text(&mut self, text: &str) {
+        let mut rest = text;
+        while let Some(i) = rest.find("{{").into_iter().chain(rest.find("}}")).min() {
+            self.text.push_str(&rest[..i]);
+            self.push_placeholder(&rest[i..i + 2]);
+            rest = &rest[i + 2..];
+        }
+        self.text.push_str(rest);
+    }
+
+    /// Appends a placeholder standing for the given value, returning its number
+    pub fn push_placeholder<S: Into<String>>(&mut self, value: S) -> usize {
+        let index = self.values.len();
+        self.values.push(value.into());
+        self.text.push_str(&format!("{{{{{}}}}}", index));
+        index
+    }
+
+    /// Appends the closing placeholder of a pair opened with `push_placeholder`
+    pub fn push_close(&mut self, index: usize) {
+        self.text.push_str(&format!("{{{{/{}}}}}", index));
+    }
+
+    /// The text to translate
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// What each placeholder stands for, in order
+    pub fn values(&self) -> &[String] {
+        &self.values
+    }
+
+    /// Puts the values back into a translation of the text.
+    ///
+    /// Also returns whether every placeholder was found exactly once. Placeholders
+    /// that were never handed out are left as they are.
+    pub fn restore(&self, translated: &str) -> (String, bool) {
+        let mut restored = String::with_capacity(translated.len());
+        let mut counts = vec![0; self.values.len()];
+        for token in tokenize(translated) {
+            match token {
+                Token::Open(i, _) if i < self.values.len() => {
+                    restored.push_str(&self.values[i]);
+                    counts[i] += 1;
+                }
+                Token::Text(raw) | Token::Open(_, raw) | Token::Close(_, raw) => restored.push_str(raw),
+            }
+        }
+        (restored, counts.iter().all(|&c| c == 1))
+    }
+}
+
+/// Splits a translated text into text and placeholders
+pub fn tokenize<'a>(translated: &'a str) -> Vec<Token<'a>> {
+    let mut tokens = vec![];
+    let mut text_start = 0;
+    let mut pos = 0;
+    while let Some(offset) = translated[pos..].find("{{") {
+        let start = pos + offset;
+        match parse_placeholder(&translated[start..]) {
+            Some((close, index, len)) => {
+                if start > text_start {
+                    tokens.push(Token::Text(&translated[text_start..start]));
+                }
+                let raw = &translated[start..start + len];
+                tokens.push(if close {
+                                Token::Close(index, raw)
+                            } else {
+                                Token::Open(index, raw)
+                            });
+                pos = start + len;
+                text_start = pos;
+            }
+            // Only skip one brace, the next one may start a placeholder as in "{{{0}}"
+            None => pos = start + 1,
+        }
+    }
+    if translated.len() > text_start {
+        tokens.push(Token::Text(&translated[text_start..]));
+    }
+    tokens
+}
+
+/// Parses a placeholder at the start of a text, returning whether it closes a pair,
+/// its number and its length
+fn parse_placeholder(text: &str) -> Option<(bool, usize, usize)> {
+    let end = text.find("}}")?;
+    let inner = text[2..end].trim();
+    let (close, number) = if inner.starts_with('/') {
+        (true, inner[1..].trim())
+    } else {
+        (false, inner)
+    };
+    if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    number.parse().ok().map(|index| (close, index, end + 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protect_and_restore_test() {
+        let mut protected = Protected::new();
+        protected.push_text("Hi ");
+        protected.push_placeholder("%s");
+        protected.push_text(", {{0}} and {{/1}} are literal");
+        assert_eq!(protected.text(), "Hi {{0}}, {{1}}0{{2}} and {{3}}/1{{4}} are literal");
+        assert_eq!(protected.restore("Hallo {{ 0 }}, {{1}}0{{2}} und {{3}}/1{{4}} sind wörtlich"),
+                   ("Hallo %s, {{0}} und {{/1}} sind wörtlich".to_string(), true));
+        assert_eq!(protected.restore("Hallo {{0}} {{0}} {{9}}"),
+                   ("Hallo %s %s {{9}}".to_string(), false));
+    }
+
+    #[test]
+    fn tokenize_test() {
+        assert_eq!(tokenize("a {{0}}b{{ /0 }} {{x}} {{{1}}}"),
+                   vec![Token::Text("a "),
+                        Token::Open(0, "{{0}}"),
+                        Token::Text("b"),
+                        Token::Close(0, "{{ /0 }}"),
+                        Token::Text(" {{x}} {"),
+                        Token::Open(1, "{{1}}"),
+                        Token::Text("}")]);
+    }
+}
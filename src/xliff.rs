@@ -0,0 +1,815 @@
+//! Holds logic for translating XLIFF 1.2 and 2.0 files.
+//!
+//! A Document wraps the parsed XLIFF tree. XliffTranslator translates the
+//! `<source>` of every unit that has no translation yet into its `<target>`,
+//! keeping inline codes such as `<ph>`, `<g>` and `<x/>` in place, and marks
+//! the targets as machine translated so that they get reviewed. Everything
+//! else is written back as it was read, except for XML comments, processing
+//! instructions and the DOCTYPE, which elementtree does not keep. Documents
+//! count them in `dropped_nodes` and refuse to be written while there are any,
+//! unless `drop_unsupported_nodes` was called.
+use futures::Future;
+use std::io::{Read, Write};
+use elementtree::{self, Element};
+use xml::reader::{self, ParserConfig, XmlEvent};
+use engine::Dispatcher;
+use flow::{self, Flow, CogFlow, Concat};
+use cogs::translation::{TranslateArrayRequest, FutureTranslateArrayResponse};
+use language::LanguageTag;
+use category::CategoryId;
+use placeholder::{Protected, Token, tokenize};
+
+pub const XLIFF_1_2_NS: &'static str = "urn:oasis:names:tc:xliff:document:1.2";
+pub const XLIFF_2_0_NS: &'static str = "urn:oasis:names:tc:xliff:document:2.0";
+
+/// Inline elements whose content is translatable text, as opposed to native codes
+const PAIRED_CODES: &'static [&'static str] = &["g", "pc", "mrk"];
+
+/// XLIFF 1.2 target states that mean there is no translation yet
+const UNTRANSLATED_STATES: &'static [&'static str] = &["new", "needs-translation"];
+
+/// XLIFF version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    V1_2,
+    V2_0,
+}
+
+impl Version {
+    pub fn namespace(&self) -> &'static str {
+        match *self {
+            Version::V1_2 => XLIFF_1_2_NS,
+            Version::V2_0 => XLIFF_2_0_NS,
+        }
+    }
+
+    /// The element holding a source and target pair
+    fn unit_tag(&self) -> &'static str {
+        match *self {
+            Version::V1_2 => "trans-unit",
+            Version::V2_0 => "segment",
+        }
+    }
+}
+
+/// XLIFF error mapping
+#[derive(Debug)]
+pub enum Error {
+    XmlError(elementtree::Error),
+    ParseError(reader::Error),
+    ReadError(::std::io::Error),
+    InvalidXliff(String),
+    /// The Document already has a target language that is not the one to translate into
+    TargetLanguageMismatch(String),
+    /// Writing the Document would drop this many comments, processing instructions or DOCTYPEs
+    DroppedNodes(usize),
+}
+
+/// A parsed XLIFF file
+#[derive(Debug, Clone)]
+pub struct Document {
+    root: Element,
+    version: Version,
+    dropped_nodes: usize,
+}
+
+/// A translatable unit of a Document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unit {
+    /// The id of the `<trans-unit>` (1.2) or of the `<unit>` holding the `<segment>` (2.0)
+    pub id: Option<String>,
+    /// Text of the source, without inline codes
+    pub source: String,
+    /// Text of the target, without inline codes, if there is a target
+    pub target: Option<String>,
+    /// Whether or not XliffTranslator would translate this unit
+    pub needs_translation: bool,
+}
+
+/// Where a unit is, as indices of children starting from the root
+#[derive(Debug, Clone)]
+struct UnitPath {
+    id: Option<String>,
+    children: Vec<usize>,
+}
+
+impl Document {
+    pub fn from_reader<R: Read>(mut r: R) -> Result<Document, Error> {
+        let mut xml = String::new();
+        r.read_to_string(&mut xml).map_err(|e| Error::ReadError(e))?;
+        let root = Element::from_reader(xml.as_bytes()).map_err(|e| Error::XmlError(e))?;
+        if root.tag().name() != "xliff" {
+            return Err(Error::InvalidXliff(format!("root element is {}, not xliff", root.tag().name())));
+        }
+        let version = match (root.tag().ns(), root.get_attr("version")) {
+            (Some(XLIFF_1_2_NS), _) => Version::V1_2,
+            (Some(XLIFF_2_0_NS), _) => Version::V2_0,
+            (_, v) => return Err(Error::InvalidXliff(format!("unsupported XLIFF version {:?}", v))),
+        };
+        Ok(Document {
+               root: root,
+               version: version,
+               dropped_nodes: count_dropped_nodes(&xml)?,
+           })
+    }
+
+    /// Fails with `Error::DroppedNodes` if the Document has nodes that cannot be written back
+    pub fn to_writer<W: Write>(&self, w: W) -> Result<(), Error> {
+        self.check_dropped_nodes()?;
+        self.root.to_writer(w).map_err(|e| Error::XmlError(e))
+    }
+
+    /// Fails with `Error::DroppedNodes` if the Document has nodes that cannot be written back
+    pub fn to_string(&self) -> Result<String, Error> {
+        self.check_dropped_nodes()?;
+        self.root.to_string().map_err(|e| Error::XmlError(e))
+    }
+
+    fn check_dropped_nodes(&self) -> Result<(), Error> {
+        match self.dropped_nodes {
+            0 => Ok(()),
+            n => Err(Error::DroppedNodes(n)),
+        }
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn root(&self) -> &Element {
+        &self.root
+    }
+
+    /// Number of XML comments, processing instructions and DOCTYPEs that cannot be written back
+    pub fn dropped_nodes(&self) -> usize {
+        self.dropped_nodes
+    }
+
+    /// Lets the Document be written without the nodes counted in `dropped_nodes`
+    pub fn drop_unsupported_nodes(&mut self) {
+        self.dropped_nodes = 0;
+    }
+
+    /// The source language, from the first `<file>` (1.2) or the root (2.0)
+    pub fn source_language(&self) -> Option<&str> {
+        match self.version {
+            Version::V1_2 => self.root.find((XLIFF_1_2_NS, "file")).and_then(|f| f.get_attr("source-language")),
+            Version::V2_0 => self.root.get_attr("srcLang"),
+        }
+    }
+
+    /// The target language, from the first `<file>` (1.2) or the root (2.0)
+    pub fn target_language(&self) -> Option<&str> {
+        match self.version {
+            Version::V1_2 => self.root.find((XLIFF_1_2_NS, "file")).and_then(|f| f.get_attr("target-language")),
+            Version::V2_0 => self.root.get_attr("trgLang"),
+        }
+    }
+
+    /// Sets the target language where it is missing.
+    ///
+    /// Fails if a target language is already set to a different language, ignoring regions
+    /// and scripts so that a "de-DE" file can be translated into "de".
+    fn set_missing_target_language(&mut self, language: &LanguageTag) -> Result<(), Error> {
+        let check = |existing: &str| match LanguageTag::parse(existing) {
+            Ok(ref tag) if tag.language() == language.language() => Ok(()),
+            _ => Err(Error::TargetLanguageMismatch(existing.to_string())),
+        };
+        match self.version {
+            Version::V1_2 => {
+                for i in 0..self.root.child_count() {
+                    let file = self.root.get_child_mut(i).unwrap();
+                    if !is(file, Version::V1_2, "file") {
+                        continue;
+                    }
+                    match file.get_attr("target-language") {
+                        Some(existing) => check(existing)?,
+                        None => {
+                            file.set_attr("target-language", language.as_str());
+                        }
+                    }
+                }
+            }
+            Version::V2_0 => {
+                match self.root.get_attr("trgLang") {
+                    Some(existing) => check(existing)?,
+                    None => {
+                        self.root.set_attr("trgLang", language.as_str());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the translatable units, in document order
+    pub fn units(&self) -> Vec<Unit> {
+        self.unit_paths()
+            .into_iter()
+            .map(|path| {
+                let unit = self.element(&path.children);
+                let source = unit.find((self.version.namespace(), "source"));
+                let target = unit.find((self.version.namespace(), "target"));
+                Unit {
+                    id: path.id,
+                    source: source.map_or_else(String::new, |s| plain_text(s)),
+                    target: target.map(|t| plain_text(t)),
+                    needs_translation: self.needs_translation(unit),
+                }
+            })
+            .collect()
+    }
+
+    fn element(&self, path: &[usize]) -> &Element {
+        path.iter().fold(&self.root, |e, &i| e.get_child(i).unwrap())
+    }
+
+    fn element_mut(&mut self, path: &[usize]) -> &mut Element {
+        let mut element = &mut self.root;
+        for &i in path {
+            element = { element }.get_child_mut(i).unwrap();
+        }
+        element
+    }
+
+    fn unit_paths(&self) -> Vec<UnitPath> {
+        let mut paths = vec![];
+        collect_units(&self.root, self.version, None, &mut vec![], &mut paths);
+        paths
+    }
+
+    fn needs_translation(&self, unit: &Element) -> bool {
+        let ns = self.version.namespace();
+        if unit.find((ns, "source")).is_none() {
+            return false;
+        }
+        match unit.find((ns, "target")) {
+            None => true,
+            Some(target) => {
+                is_empty(target) ||
+                (self.version == Version::V1_2 &&
+                 target.get_attr("state").map_or(false, |s| UNTRANSLATED_STATES.contains(&s)))
+            }
+        }
+    }
+}
+
+fn is(element: &Element, version: Version, name: &str) -> bool {
+    element.tag().ns() == Some(version.namespace()) && element.tag().name() == name
+}
+
+fn is_empty(element: &Element) -> bool {
+    element.text().trim().is_empty() && element.child_count() == 0
+}
+
+/// Finds the units below an element, skipping those marked translate="no"
+fn collect_units(element: &Element,
+                 version: Version,
+                 unit_id: Option<&str>,
+                 path: &mut Vec<usize>,
+                 paths: &mut Vec<UnitPath>) {
+    if element.get_attr("translate") == Some("no") {
+        return;
+    }
+    if is(element, version, version.unit_tag()) {
+        let id = match version {
+            Version::V1_2 => element.get_attr("id"),
+            Version::V2_0 => unit_id,
+        };
+        paths.push(UnitPath {
+                       id: id.map(|s| s.to_string()),
+                       children: path.clone(),
+                   });
+        return;
+    }
+    let unit_id = if version == Version::V2_0 && is(element, version, "unit") {
+        element.get_attr("id")
+    } else {
+        unit_id
+    };
+    for (i, child) in element.children().enumerate() {
+        path.push(i);
+        collect_units(child, version, unit_id, path, paths);
+        path.pop();
+    }
+}
+
+/// Counts the comments, processing instructions and DOCTYPE of a document, other than the XML declaration
+fn count_dropped_nodes(xml: &str) -> Result<usize, Error> {
+    let mut reader = ParserConfig::new()
+        .ignore_comments(false)
+        .create_reader(xml.as_bytes());
+    let mut count = 0;
+    loop {
+        match reader.next().map_err(|e| Error::ParseError(e))? {
+            XmlEvent::Comment(_) |
+            XmlEvent::ProcessingInstruction { .. } => count += 1,
+            XmlEvent::EndDocument => break,
+            _ => (),
+        }
+    }
+    if reader.doctype().is_some() {
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// The text of an element and its descendants, without the contents of native codes
+fn plain_text(element: &Element) -> String {
+    let mut text = element.text().to_string();
+    for child in element.children() {
+        if PAIRED_CODES.contains(&child.tag().name()) {
+            text.push_str(&plain_text(child));
+        }
+        text.push_str(child.tail());
+    }
+    text
+}
+
+/// Turns the content of a source into text with numbered tokens in place of inline codes.
+///
+/// Native codes become `{{i}}`, and codes around translatable content become `{{i}}` and
+/// `{{/i}}` around it. Also returns the code of each token, without its content and tail,
+/// or None for the tokens standing for braces that were already in the text.
+fn protect(source: &Element) -> (Protected, Vec<Option<Element>>) {
+    let mut protected = Protected::new();
+    let mut codes = vec![];
+    protect_content(source, &mut protected, &mut codes);
+    (protected, codes)
+}
+
+fn protect_content(element: &Element, protected: &mut Protected, codes: &mut Vec<Option<Element>>) {
+    push_text(protected, codes, element.text());
+    for child in element.children() {
+        let mut code = child.clone();
+        code.set_tail("");
+        if PAIRED_CODES.contains(&child.tag().name()) {
+            code.set_text("");
+            while code.child_count() > 0 {
+                code.remove_child(0);
+            }
+            let i = protected.push_placeholder("");
+            codes.push(Some(code));
+            protect_content(child, protected, codes);
+            protected.push_close(i);
+        } else {
+            protected.push_placeholder("");
+            codes.push(Some(code));
+        }
+        push_text(protected, codes, child.tail());
+    }
+}
+
+/// Appends text, keeping codes numbered like the tokens protecting its braces
+fn push_text(protected: &mut Protected, codes: &mut Vec<Option<Element>>, text: &str) {
+    protected.push_text(text);
+    let count = protected.values().len();
+    codes.resize(count, None);
+}
+
+fn append_text(element: &mut Element, text: &str) {
+    let count = element.child_count();
+    if count == 0 {
+        let joined = format!("{}{}", element.text(), text);
+        element.set_text(joined);
+    } else {
+        let last = element.get_child_mut(count - 1).unwrap();
+        let joined = format!("{}{}", last.tail(), text);
+        last.set_tail(joined);
+    }
+}
+
+/// Fills a target with a translated text, putting the inline codes back.
+///
+/// Returns whether every code came back exactly once and properly nested. Codes that did not
+/// come back are appended at the end so that none are lost.
+fn restore(target: &mut Element, translated: &str, protected: &Protected, codes: &[Option<Element>]) -> bool {
+    let mut used = vec![false; codes.len()];
+    let mut intact = true;
+    let mut stack: Vec<(usize, Element)> = vec![];
+    for token in tokenize(translated) {
+        match token {
+            Token::Open(i, _) if i < codes.len() && !used[i] => {
+                used[i] = true;
+                match codes[i] {
+                    Some(ref code) if PAIRED_CODES.contains(&code.tag().name()) => {
+                        stack.push((i, code.clone()));
+                    }
+                    Some(ref code) => {
+                        match stack.last_mut() {
+                            Some(&mut (_, ref mut open)) => open.append_child(code.clone()),
+                            None => target.append_child(code.clone()),
+                        };
+                    }
+                    None => {
+                        match stack.last_mut() {
+                            Some(&mut (_, ref mut open)) => append_text(open, &protected.values()[i]),
+                            None => append_text(target, &protected.values()[i]),
+                        }
+                    }
+                }
+            }
+            Token::Close(i, _) if stack.last().map(|&(open, _)| open) == Some(i) => {
+                let (_, code) = stack.pop().unwrap();
+                match stack.last_mut() {
+                    Some(&mut (_, ref mut open)) => open.append_child(code),
+                    None => target.append_child(code),
+                };
+            }
+            Token::Text(text) => {
+                match stack.last_mut() {
+                    Some(&mut (_, ref mut open)) => append_text(open, text),
+                    None => append_text(target, text),
+                }
+            }
+            _ => intact = false,
+        }
+    }
+    while let Some((_, code)) = stack.pop() {
+        intact = false;
+        match stack.last_mut() {
+            Some(&mut (_, ref mut open)) => open.append_child(code),
+            None => target.append_child(code),
+        };
+    }
+    for (i, code) in codes.iter().enumerate() {
+        if !used[i] {
+            intact = false;
+            match *code {
+                Some(ref code) => {
+                    target.append_child(code.clone());
+                }
+                None => append_text(target, &protected.values()[i]),
+            }
+        }
+    }
+    intact
+}
+
+/// Translates the units of a Document that have no translation yet, see `translate`.
+pub struct XliffTranslator<'a> {
+    /// Defaults to the source language of the Document
+    pub from: Option<&'a LanguageTag>,
+    pub to: &'a LanguageTag,
+    pub category: Option<&'a CategoryId>,
+}
+
+impl<'a> XliffTranslator<'a> {
+    /// Returns a Flow that translates the units of the given Document that need it, in batches.
+    ///
+    /// Translated targets get state "needs-review-translation" with state-qualifier
+    /// "mt-suggestion" (1.2), or their segment gets state "translated" (2.0). Fails if the
+    /// Document already has a target language other than `to`.
+    pub fn translate(&self, document: &Document) -> Result<XliffTranslation, Error> {
+        let mut document = document.clone();
+        document.set_missing_target_language(self.to)?;
+        let from = self.from
+            .cloned()
+            .or_else(|| document.source_language().and_then(|l| LanguageTag::parse(l).ok()));
+        let mut units = vec![];
+        for path in document.unit_paths() {
+            let unit = document.element(&path.children);
+            if !document.needs_translation(unit) {
+                continue;
+            }
+            let (protected, codes) = protect(unit.find((document.version.namespace(), "source")).unwrap());
+            units.push((path, protected, codes));
+        }
+        let text_refs: Vec<&str> = units.iter().map(|&(_, ref p, _)| p.text()).collect();
+        let request = TranslateArrayRequest {
+            texts: &text_refs,
+            from: from.as_ref(),
+            to: self.to,
+            content_type: None,
+            category: self.category,
+            state: None,
+            profanity_action: None,
+        };
+        let flow = request.batched();
+        Ok(XliffTranslation {
+               document: document,
+               units: units,
+               flow: flow,
+           })
+    }
+}
+
+/// Flow for `XliffTranslator::translate`
+pub struct XliffTranslation {
+    document: Document,
+    units: Vec<(UnitPath, Protected, Vec<Option<Element>>)>,
+    flow: Concat<CogFlow<FutureTranslateArrayResponse>>,
+}
+
+/// A Document whose units were translated
+#[derive(Debug, Clone)]
+pub struct TranslatedDocument {
+    pub document: Document,
+    /// Number of units that were translated
+    pub translated: usize,
+    /// Ids of the translated units whose inline codes did not all come back in place
+    pub code_mismatches: Vec<Option<String>>,
+}
+
+impl Flow for XliffTranslation {
+    type Item = TranslatedDocument;
+
+    fn start(self, dispatcher: Dispatcher) -> Box<Future<Item = Self::Item, Error = flow::Error>> {
+        let XliffTranslation { mut document, units, flow } = self;
+        Box::new(flow.start(dispatcher).map(move |translations| {
+            let version = document.version;
+            let ns = version.namespace();
+            let mut translated = 0;
+            let mut mismatches = vec![];
+            for (&(ref path, ref protected, ref codes), translation) in units.iter().zip(translations) {
+                let unit = document.element_mut(&path.children);
+                let mut target = match unit.find((ns, "target")) {
+                    Some(existing) => {
+                        let mut target = existing.clone();
+                        target.set_text("");
+                        while target.child_count() > 0 {
+                            target.remove_child(0);
+                        }
+                        target
+                    }
+                    None => {
+                        let source = unit.find((ns, "source")).unwrap();
+                        let mut target = Element::new_with_namespaces((ns, "target"), source);
+                        target.set_tail(source.tail());
+                        target
+                    }
+                };
+                if !restore(&mut target, &translation.translated_text, protected, codes) {
+                    mismatches.push(path.id.clone());
+                }
+                match version {
+                    Version::V1_2 => {
+                        target.set_attr("state", "needs-review-translation");
+                        target.set_attr("state-qualifier", "mt-suggestion");
+                    }
+                    Version::V2_0 => {
+                        unit.set_attr("state", "translated");
+                    }
+                }
+                put_target(unit, target, ns);
+                translated += 1;
+            }
+            TranslatedDocument {
+                document: document,
+                translated: translated,
+                code_mismatches: mismatches,
+            }
+        }))
+    }
+}
+
+/// Replaces the target of a unit, or adds it right after the source
+fn put_target(unit: &mut Element, target: Element, ns: &str) {
+    let position = (0..unit.child_count()).find(|&i| {
+                                                    let tag = unit.get_child(i).unwrap().tag();
+                                                    tag.ns() == Some(ns) && tag.name() == "target"
+                                                });
+    let (insert_at, replace) = match position {
+        Some(i) => (i, true),
+        None => {
+            let source = (0..unit.child_count())
+                .find(|&i| {
+                          let tag = unit.get_child(i).unwrap().tag();
+                          tag.ns() == Some(ns) && tag.name() == "source"
+                      })
+                .unwrap();
+            (source + 1, false)
+        }
+    };
+    let mut rest = vec![];
+    while unit.child_count() > insert_at {
+        rest.push(unit.remove_child(insert_at).unwrap());
+    }
+    unit.append_child(target);
+    for child in rest.into_iter().skip(if replace { 1 } else { 0 }) {
+        unit.append_child(child);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::client::Response;
+    use engine::scripted_dispatcher;
+
+    const XLIFF_1_2: &'static str = r#"<?xml version="1.0" encoding="utf-8"?>
+<xliff xmlns="urn:oasis:names:tc:xliff:document:1.2" version="1.2">
+  <file source-language="en" datatype="plaintext" original="app.txt">
+    <body>
+      <trans-unit id="greeting">
+        <source>Hello <g id="1">dear</g> <x id="2"/>user</source>
+        <note>Shown on start</note>
+      </trans-unit>
+      <trans-unit id="done">
+        <source>Done</source>
+        <target state="translated">Fertig</target>
+      </trans-unit>
+      <trans-unit id="code" translate="no">
+        <source>printf</source>
+      </trans-unit>
+      <trans-unit id="empty">
+        <source>Save <ph id="1">%s</ph></source>
+        <target state="new"/>
+      </trans-unit>
+    </body>
+  </file>
+</xliff>"#;
+
+    const XLIFF_2_0: &'static str = r#"<?xml version="1.0" encoding="utf-8"?>
+<xliff xmlns="urn:oasis:names:tc:xliff:document:2.0" version="2.0" srcLang="en">
+  <file id="f1">
+    <unit id="u1">
+      <segment>
+        <source>Click <pc id="1">here</pc><ph id="2"/></source>
+      </segment>
+    </unit>
+    <unit id="u2">
+      <segment state="final">
+        <source>Bye</source>
+        <target>Tschüss</target>
+      </segment>
+    </unit>
+  </file>
+</xliff>"#;
+
+    fn array_response(translations: &[&str]) -> String {
+        let items: String = translations
+            .iter()
+            .map(|t| {
+                     format!("<TranslateArrayResponse><From>en</From><TranslatedText>{}</TranslatedText>\
+                              </TranslateArrayResponse>",
+                             t)
+                 })
+            .collect();
+        format!("<ArrayOfTranslateArrayResponse xmlns=\"http://schemas.datacontract.org/2004/07/Microsoft.MT.Web.Service.V2\">{}</ArrayOfTranslateArrayResponse>",
+                items)
+    }
+
+    fn translate(xliff: &str, expected: &[&str], translations: &[&str]) -> TranslatedDocument {
+        let resp = Response::new().with_body(array_response(translations));
+        let (dispatcher, sent) = scripted_dispatcher(vec![resp]);
+        let de = LanguageTag::parse("de").unwrap();
+        let translator = XliffTranslator {
+            from: None,
+            to: &de,
+            category: None,
+        };
+        let document = Document::from_reader(xliff.as_bytes()).unwrap();
+        let translated = translator.translate(&document).unwrap().start(dispatcher).wait().unwrap();
+        let body = &sent.borrow()[0].body;
+        for text in expected {
+            assert!(body.contains(text), "{} not in {}", text, body);
+        }
+        translated
+    }
+
+    #[test]
+    fn units_test() {
+        let document = Document::from_reader(XLIFF_1_2.as_bytes()).unwrap();
+        assert_eq!(document.version(), Version::V1_2);
+        assert_eq!(document.source_language(), Some("en"));
+        let units = document.units();
+        assert_eq!(units.len(), 3);
+        assert_eq!(units[0].id, Some("greeting".to_string()));
+        assert_eq!(units[0].source, "Hello dear user");
+        assert!(units[0].needs_translation);
+        assert_eq!(units[1].target, Some("Fertig".to_string()));
+        assert!(!units[1].needs_translation);
+        assert!(units[2].needs_translation);
+
+        let document = Document::from_reader(XLIFF_2_0.as_bytes()).unwrap();
+        assert_eq!(document.version(), Version::V2_0);
+        let units = document.units();
+        assert_eq!(units[0].id, Some("u1".to_string()));
+        assert_eq!(units[0].source, "Click here");
+        assert!(!units[1].needs_translation);
+
+        match Document::from_reader("<tmx version=\"1.4\"/>".as_bytes()) {
+            Err(Error::InvalidXliff(_)) => (),
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn protect_test() {
+        let document = Document::from_reader(XLIFF_1_2.as_bytes()).unwrap();
+        let unit = document.element(&document.unit_paths()[0].children);
+        let (protected, codes) = protect(unit.find((XLIFF_1_2_NS, "source")).unwrap());
+        assert_eq!(protected.text(), "Hello {{0}}dear{{/0}} {{1}}user");
+        let names: Vec<Option<&str>> = codes.iter().map(|c| c.as_ref().map(|c| c.tag().name())).collect();
+        assert_eq!(names, vec![Some("g"), Some("x")]);
+
+        let source = Element::from_reader("<source>Use {{0}} <ph id=\"1\">%s</ph></source>".as_bytes()).unwrap();
+        let (protected, codes) = protect(&source);
+        assert_eq!(protected.text(), "Use {{0}}0{{1}} {{2}}");
+        assert_eq!(codes.iter().map(|c| c.is_some()).collect::<Vec<_>>(), vec![false, false, true]);
+        let mut target = Element::new("target");
+        assert!(restore(&mut target, "{{2}} {{0}}0{{1}} verwenden", &protected, &codes));
+        assert_eq!(target.text(), "");
+        assert_eq!(target.get_child(0).unwrap().tail(), " {{0}} verwenden");
+    }
+
+    #[test]
+    fn dropped_nodes_test() {
+        let xliff = r#"<?xml version="1.0"?>
+<!-- Exported by the app -->
+<xliff xmlns="urn:oasis:names:tc:xliff:document:2.0" version="2.0" srcLang="en">
+  <?app keep?>
+  <file id="f1"><unit id="u1"><segment><source><![CDATA[<!-- not a comment -->]]></source></segment></unit></file>
+</xliff>"#;
+        let mut document = Document::from_reader(xliff.as_bytes()).unwrap();
+        assert_eq!(document.dropped_nodes(), 2);
+        assert_eq!(document.units()[0].source, "<!-- not a comment -->");
+        match document.to_string() {
+            Err(Error::DroppedNodes(2)) => (),
+            other => panic!("expected DroppedNodes(2), got {:?}", other),
+        }
+        document.drop_unsupported_nodes();
+        assert!(!document.to_string().unwrap().contains("Exported"));
+
+        let doctype = r#"<?xml version="1.0"?>
+<!DOCTYPE xliff [ <!ENTITY app "cogs"> ]>
+<xliff xmlns="urn:oasis:names:tc:xliff:document:2.0" version="2.0" srcLang="en"><file id="f1"/></xliff>"#;
+        assert_eq!(Document::from_reader(doctype.as_bytes()).unwrap().dropped_nodes(), 1);
+        assert_eq!(Document::from_reader(XLIFF_1_2.as_bytes()).unwrap().dropped_nodes(), 0);
+    }
+
+    #[test]
+    fn target_language_test() {
+        let translator = |to: &'static str| {
+            let xliff = XLIFF_2_0.replace("srcLang=\"en\"", "srcLang=\"en\" trgLang=\"de-DE\"");
+            let document = Document::from_reader(xliff.as_bytes()).unwrap();
+            let to = LanguageTag::parse(to).unwrap();
+            XliffTranslator {
+                    from: None,
+                    to: &to,
+                    category: None,
+                }
+                .translate(&document)
+                .map(|_| ())
+        };
+        assert!(translator("de").is_ok());
+        match translator("fr") {
+            Err(Error::TargetLanguageMismatch(ref l)) if l == "de-DE" => (),
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn translate_1_2_test() {
+        let result = translate(XLIFF_1_2,
+                               &["Hello {{0}}dear{{/0}} {{1}}user", "Save {{0}}"],
+                               &["Hallo {{1}}{{0}}lieber{{/0}} Benutzer", "{{0}} speichern"]);
+        assert_eq!(result.translated, 2);
+        assert!(result.code_mismatches.is_empty());
+        let document = result.document;
+        assert_eq!(document.target_language(), Some("de"));
+        let units = document.units();
+        assert_eq!(units[0].target, Some("Hallo lieber Benutzer".to_string()));
+        assert_eq!(units[2].target, Some(" speichern".to_string()));
+        let xml = document.to_string().unwrap();
+        let reparsed = Document::from_reader(xml.as_bytes()).unwrap();
+        let body = reparsed.root().navigate(&[(XLIFF_1_2_NS, "file"), (XLIFF_1_2_NS, "body")]).unwrap();
+        let unit = body.get_child(0).unwrap();
+        let tags: Vec<&str> = unit.children().map(|c| c.tag().name()).collect();
+        assert_eq!(tags, vec!["source", "target", "note"]);
+        let target = unit.get_child(1).unwrap();
+        assert_eq!(target.get_attr("state"), Some("needs-review-translation"));
+        assert_eq!(target.get_attr("state-qualifier"), Some("mt-suggestion"));
+        let codes: Vec<&str> = target.children().map(|c| c.tag().name()).collect();
+        assert_eq!(codes, vec!["x", "g"]);
+        assert_eq!(target.get_child(1).unwrap().get_attr("id"), Some("1"));
+        assert_eq!(target.get_child(1).unwrap().text(), "lieber");
+        assert_eq!(reparsed.units()[1].target, Some("Fertig".to_string()));
+        assert_eq!(body.get_child(2).unwrap().child_count(), 1);
+    }
+
+    #[test]
+    fn translate_2_0_test() {
+        let result = translate(XLIFF_2_0,
+                               &["Click {{0}}here{{/0}}{{1}}"],
+                               &["Klicken Sie {{0}}hier"]);
+        assert_eq!(result.translated, 1);
+        assert_eq!(result.code_mismatches, vec![Some("u1".to_string())]);
+        let document = result.document;
+        assert_eq!(document.target_language(), Some("de"));
+        let xml = document.to_string().unwrap();
+        let reparsed = Document::from_reader(xml.as_bytes()).unwrap();
+        let units = reparsed.units();
+        assert_eq!(units[0].target, Some("Klicken Sie hier".to_string()));
+        assert_eq!(units[1].target, Some("Tschüss".to_string()));
+        let segment = reparsed
+            .root()
+            .navigate(&[(XLIFF_2_0_NS, "file"), (XLIFF_2_0_NS, "unit"), (XLIFF_2_0_NS, "segment")])
+            .unwrap();
+        assert_eq!(segment.get_attr("state"), Some("translated"));
+        let target = segment.find((XLIFF_2_0_NS, "target")).unwrap();
+        let codes: Vec<&str> = target.children().map(|c| c.tag().name()).collect();
+        assert_eq!(codes, vec!["pc", "ph"]);
+    }
+}